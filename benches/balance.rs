@@ -0,0 +1,46 @@
+//! Measures how `Bank::balance` scales with the size of an account's history.
+//!
+//! Run with `cargo bench --bench balance`.
+
+use brb_dt_at2::{Bank, Money};
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+
+type Actor = u64;
+
+/// History sizes to measure, in transfers
+const SCENARIOS: [usize; 3] = [100, 1_000, 10_000];
+
+/// Builds a bank in which one account sent `transfers` transfers to another,
+/// so that both accounts have `transfers` transfers in their history.
+fn bank_with_transfers(transfers: usize) -> Bank<Actor> {
+    let (sender, recipient) = (0, 1);
+    let mut bank = Bank::new(sender).with_max_initial_balance(Money::MAX);
+    let opened = vec![
+        bank.open_account(sender, transfers as Money),
+        bank.open_account(recipient, 0),
+    ];
+    for op in opened {
+        bank.apply(op);
+    }
+
+    for _ in 0..transfers {
+        let op = bank.transfer(sender, recipient, 1).unwrap();
+        bank.apply(op);
+    }
+    assert_eq!(bank.balance(&recipient), transfers as Money);
+    bank
+}
+
+fn bench_balance(c: &mut Criterion) {
+    let mut group = c.benchmark_group("balance");
+    for transfers in SCENARIOS.iter() {
+        let bank = bank_with_transfers(*transfers);
+        group.bench_with_input(BenchmarkId::from_parameter(transfers), &bank, |b, bank| {
+            b.iter(|| bank.balance(black_box(&1)))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_balance);
+criterion_main!(benches);
@@ -0,0 +1,19 @@
+//! AT2 AccountActivity
+
+/// How many transfers an account took part in, and how much they moved, as
+/// returned by `Bank::account_activity`.
+///
+/// Fee payments are transfers of their own, so they count as outgoing
+/// transfers of the payer and incoming transfers of the fee collector.  A
+/// transfer to oneself counts as both incoming and outgoing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct AccountActivity {
+    /// Number of transfers received
+    pub incoming_count: usize,
+    /// Number of transfers sent
+    pub outgoing_count: usize,
+    /// Sum of the amounts received
+    pub incoming_volume: u128,
+    /// Sum of the amounts sent
+    pub outgoing_volume: u128,
+}
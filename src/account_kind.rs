@@ -0,0 +1,22 @@
+//! AT2 AccountKind
+
+use serde::{Deserialize, Serialize};
+
+/// The kind of an account, chosen when it is opened, for double-entry
+/// bookkeeping.
+///
+/// An asset account's balance never drops below zero.  A liability account
+/// may send more than it holds, down to minus the maximum amount, leaving
+/// it with a negative balance: what it owes, see `Bank::signed_balance`.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default, Serialize, Deserialize,
+)]
+#[serde(rename_all = "camelCase")]
+pub enum AccountKind {
+    /// An account holding money, whose balance is never negative.  This is
+    /// the default.
+    #[default]
+    Asset,
+    /// An account whose balance may be negative
+    Liability,
+}
@@ -0,0 +1,78 @@
+//! AT2 AppliedEffect
+
+use alloc::vec::Vec;
+
+use super::{Money, MoneyT};
+
+/// The balance changes caused by applying an op, as reported by
+/// `Bank::apply_with_effects`.
+///
+/// Balances are those right after the op was applied.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AppliedEffect<A, M: MoneyT = Money> {
+    /// Money moved from one account to another.  Reported for transfers,
+    /// delegated transfers, finalized pending transfers and recovered
+    /// accounts.
+    Transfer {
+        /// Sending account
+        from: A,
+        /// Receiving account
+        to: A,
+        /// Amount credited to the receiving account
+        amount: M,
+        /// Balance of the sending account
+        new_from_balance: M,
+        /// Balance of the receiving account
+        new_to_balance: M,
+    },
+    /// Money moved from one account to several others at once
+    MultiTransfer {
+        /// Sending account
+        from: A,
+        /// Balance of the sending account
+        new_from_balance: M,
+        /// Each receiving account, the amount credited to it and its balance
+        credits: Vec<(A, M, M)>,
+    },
+    /// A pending transfer was initiated, debiting the sending account
+    Reserved {
+        /// Sending account
+        from: A,
+        /// Amount debited, excluding any fee
+        amount: M,
+        /// Balance of the sending account
+        new_from_balance: M,
+    },
+    /// A pending transfer was cancelled, refunding the sending account
+    Released {
+        /// Sending account
+        from: A,
+        /// Amount refunded, excluding any fee
+        amount: M,
+        /// Balance of the sending account
+        new_from_balance: M,
+    },
+    /// An admin set an account's balance directly
+    Adjusted {
+        /// Account owner
+        owner: A,
+        /// Balance of the account before the adjustment
+        previous_balance: M,
+        /// Balance of the account
+        new_balance: M,
+    },
+    /// An account was opened
+    OpenAccount {
+        /// Account owner
+        owner: A,
+        /// Initial balance of the account
+        balance: M,
+    },
+    /// Accounts were opened in a batch
+    OpenAccounts {
+        /// Owner and initial balance of each account, in the order opened
+        accounts: Vec<(A, M)>,
+    },
+    /// No balance changed, e.g. metadata was set or the op was a duplicate
+    NoBalanceChange,
+}
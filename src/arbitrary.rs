@@ -0,0 +1,48 @@
+//! Quickcheck `Arbitrary` implementations, for property testing code built
+//! on AT2.  Only built with the `testing` feature.
+
+use core::hash::Hash;
+
+use crdts::quickcheck::{Arbitrary, Gen};
+
+use super::{money::DEFAULT_CURRENCY, AccountKind, MoneyT, Op, Transfer, TransferBuilder};
+
+impl<A, M> Arbitrary for Transfer<A, M>
+where
+    A: Ord + Hash + Arbitrary,
+    M: MoneyT + Arbitrary + Send,
+{
+    /// A transfer in the default currency, without a fee, time lock or
+    /// dependencies, and with a low sequence number, so that a fair share
+    /// of generated transfers pass validation.
+    fn arbitrary<G: Gen>(g: &mut G) -> Self {
+        let mut transfer = TransferBuilder::new(A::arbitrary(g), A::arbitrary(g), M::arbitrary(g))
+            .seq(u64::from(u8::arbitrary(g) % 4));
+        if bool::arbitrary(g) {
+            transfer = transfer.timestamp(u64::arbitrary(g));
+        }
+        transfer.build()
+    }
+}
+
+impl<A, M> Arbitrary for Op<A, M>
+where
+    A: Ord + Hash + Arbitrary,
+    M: MoneyT + Arbitrary + Send,
+{
+    /// Either a transfer or the opening of an asset account in the default
+    /// currency, without a recovery actor.
+    fn arbitrary<G: Gen>(g: &mut G) -> Self {
+        if bool::arbitrary(g) {
+            Op::Transfer(Transfer::arbitrary(g))
+        } else {
+            Op::OpenAccount {
+                owner: A::arbitrary(g),
+                balance: M::arbitrary(g),
+                currency: DEFAULT_CURRENCY,
+                recovery: None,
+                kind: AccountKind::Asset,
+            }
+        }
+    }
+}
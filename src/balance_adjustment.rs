@@ -0,0 +1,35 @@
+//! AT2 BalanceAdjustment
+
+use serde::{Deserialize, Serialize};
+
+use super::{Money, MoneyT};
+
+/// An administrative correction of an account's balance, applied by
+/// `Op::AdjustBalance` and kept as an audit trail of the account, see
+/// `Bank::balance_adjustments`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct BalanceAdjustment<M: MoneyT = Money> {
+    /// Number of ops applied, including the adjustment itself, which
+    /// orders it among the other adjustments of the account
+    pub applied_op: u64,
+    /// Balance of the account before the adjustment
+    pub previous_balance: M,
+    /// Balance of the account after the adjustment
+    pub new_balance: M,
+}
+
+impl<M: MoneyT> BalanceAdjustment<M> {
+    /// Amount the adjustment credited to the account, zero if it debited it
+    pub fn credit(&self) -> M {
+        self.new_balance
+            .checked_sub(self.previous_balance)
+            .unwrap_or_else(M::zero)
+    }
+
+    /// Amount the adjustment debited from the account, zero if it credited it
+    pub fn debit(&self) -> M {
+        self.previous_balance
+            .checked_sub(self.new_balance)
+            .unwrap_or_else(M::zero)
+    }
+}
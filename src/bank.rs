@@ -20,241 +20,4917 @@
 //! `Actor` and Account are the same thing. Each `Transfer` is
 //! associated with an `Actor`.  There is no Account data structure.
 
-use core::{fmt::Debug, hash::Hash};
-use std::collections::{BTreeMap, BTreeSet};
+use alloc::{
+    collections::{BTreeMap, BTreeSet},
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
+use core::{convert::TryFrom, fmt::Debug, hash::Hash};
 
+#[cfg(feature = "std")]
 use brb::BRBDataType;
 use serde::Serialize;
+use sha2::{Digest, Sha256};
 
-use log::{info, warn};
+use log::{error, info, warn};
 
 use thiserror::Error;
 
-use super::{Money, Op, Transfer};
+use super::{
+    money::DEFAULT_CURRENCY, op_observer::Observers, transfer::canonical_bytes,
+    validation_policy::SharedPolicy, AccountActivity, AccountExport, AccountKind, AppliedEffect,
+    ArithmeticMode, BalanceAdjustment, BankConfig, BankDiff, BankState, BankView, Currency,
+    LedgerExport, Money, MoneyT, Op, OpObserver, Transfer, TransferBuilder, TransferId,
+    ValidationPolicy,
+};
+
+/// Maximum total size, in bytes, of the metadata keys and values of a single account
+pub const MAX_ACCOUNT_METADATA_SIZE: usize = 4 * 1024;
+
+/// Maximum length, in characters, of an account alias
+pub const MAX_ALIAS_LEN: usize = 32;
+
+/// Number of dependencies beyond which `validate_verbose` warns about a
+/// transfer's size
+pub const MANY_DEPS_WARNING_THRESHOLD: usize = 64;
 
 /// AT2 `Bank` for a particular `Actor`
+///
+/// All state is kept in ordered collections, so iteration order, and thus
+/// every result, only depends on the ops applied, never on insertion order
+/// or hashing.  A failing property test replays faithfully from its inputs
+/// alone, and with the `testing` feature, `with_ordering_seed` lets tests
+/// vary the order transfers are visited in.
 #[derive(Debug, Clone, PartialEq, Eq)]
-pub struct Bank<A: Ord + Hash> {
+pub struct Bank<A: Ord + Hash, M: MoneyT = Money> {
     /// Actor associated with this Bank instance
     id: A,
 
     /// The set of dependencies of the next outgoing transfer.
     /// Note that we can only initiate an outgoing transfer
     /// for the account identified by Bank::id
-    deps: BTreeSet<Transfer<A>>,
+    deps: BTreeSet<Transfer<A, M>>,
 
     // PERF: Transfer, used in deps and hist, is recursive and grows too quickly.
     /// The initial balances when an actor opened an account
     /// Normally 0, but this enables an application to force
     /// a non-zero starting balance.  Though of course other
     /// nodes must agree.
-    initial_balances: BTreeMap<A, Money>,
+    initial_balances: BTreeMap<A, M>,
+
+    /// The currency each account is denominated in
+    currencies: BTreeMap<A, Currency>,
+
+    /// Actor allowed to recover each account that designated one
+    recovery_actors: BTreeMap<A, A>,
+
+    /// Position of each account in the order accounts were opened
+    open_indices: BTreeMap<A, u64>,
+
+    /// Set of all transfers, by actor
+    hist: BTreeMap<A, BTreeSet<Transfer<A, M>>>,
+
+    // PERF: the index keeps the full transfers even once `hist` is compacted
+    /// Every transfer recorded in `hist`, by id
+    transfer_index: BTreeMap<TransferId, Transfer<A, M>>,
+
+    /// Display name and other key-value metadata, by actor
+    metadata: BTreeMap<A, BTreeMap<String, String>>,
+
+    /// The account each registered alias resolves to
+    aliases: BTreeMap<String, A>,
+
+    /// Number of distinct transfers recorded in `hist`
+    transfer_count: usize,
+
+    /// Sum of the amounts of all transfers recorded in `hist`
+    total_volume: u128,
+
+    /// Money in existence: the balances accounts were opened with, net of
+    /// any money created or destroyed since.  Transfers leave it unchanged.
+    supply: i128,
+
+    /// Number of ops applied, serving as a logical clock for time-locked transfers
+    applied_ops: u64,
+
+    /// Accounts that can neither send nor receive transfers
+    frozen: BTreeSet<A>,
+
+    /// Number of times each account was frozen or unfrozen, so that merging
+    /// replicas keeps the frozen state set by the latest change
+    frozen_versions: BTreeMap<A, u64>,
+
+    /// Accounts closed for good, which are also frozen
+    closed: BTreeSet<A>,
+
+    /// Accounts opened as liabilities, whose balance may be negative
+    liabilities: BTreeSet<A>,
+
+    /// Transfers initiated but not yet finalized or cancelled, by id.
+    /// Their senders are already debited, their recipients not yet credited.
+    pending_transfers: BTreeMap<TransferId, Transfer<A, M>>,
+
+    /// Ids of the pending transfers that were cancelled, so that merging
+    /// with a replica still holding them doesn't bring them back
+    cancelled_transfers: BTreeSet<TransferId>,
+
+    /// Amounts spenders may transfer out of accounts, by (owner, spender)
+    allowances: BTreeMap<(A, A), M>,
+
+    /// Number of times each allowance was approved, so that merging
+    /// replicas keeps the latest approval, by (owner, spender)
+    allowance_versions: BTreeMap<(A, A), u64>,
+
+    /// The applied op count right after each of a sender's latest
+    /// transfers, oldest first.  Only tracked under a rate limit, and only
+    /// as many as the limit allows.
+    recent_transfers: BTreeMap<A, Vec<u64>>,
+
+    /// Account credited with transfer fees.  Transfers carrying
+    /// a fee are rejected when no collector is configured.
+    fee_collector: Option<A>,
+
+    /// Actor allowed to perform administrative operations such as
+    /// freezing accounts.  Disabled when not configured.
+    admin: Option<A>,
+
+    /// Maximum number of dependencies a transfer may carry.
+    max_deps: usize,
+
+    /// Largest balance an account may be opened with
+    max_initial_balance: M,
+
+    /// Maximum number of accounts, if capped
+    max_accounts: Option<usize>,
+
+    /// How balance arithmetic handles out-of-range results
+    arithmetic_mode: ArithmeticMode,
+
+    /// Whether `apply` re-validates ops in debug builds
+    debug_validation: bool,
+
+    /// Deployment-specific rules checked after the built-in ones
+    validation_policy: Option<SharedPolicy<A, M>>,
+
+    /// Callbacks notified of each applied op
+    observers: Observers<A, M>,
+
+    /// Maximum number of transfers a sender may make within a window of
+    /// applied ops, and the size of that window
+    rate_limit: Option<(usize, u64)>,
+
+    /// Accounts whose settled history was collapsed by
+    /// `checkpoint_account`, with the sequence number their next outgoing
+    /// transfer had at the time
+    checkpoints: BTreeMap<A, u64>,
+
+    /// Number of transfers `prune_to_retention` keeps per account, if limited
+    retention: Option<usize>,
+
+    /// Administrative corrections of account balances, by actor.  Their
+    /// net credit or debit counts towards the account's balance on top of
+    /// its history.
+    adjustments: BTreeMap<A, BTreeSet<BalanceAdjustment<M>>>,
+
+    /// Seed of the order transfers are visited in when computing balances
+    /// and applying ops, if set by a test
+    #[cfg(feature = "testing")]
+    ordering_seed: Option<u64>,
+}
+
+impl<A: Ord + Hash + Debug + Clone + Serialize, M: MoneyT> Bank<A, M> {
+    /// Creates a bank for the actor `id`, configured with `config`.
+    ///
+    /// `Bank::new(id)` is the same as `Bank::with_config(id, BankConfig::default())`.
+    /// The `with_*` methods remain available to adjust a single setting.
+    pub fn with_config(id: A, config: BankConfig<A, M>) -> Self {
+        let BankConfig {
+            fee_collector,
+            admin,
+            max_deps,
+            max_initial_balance,
+            max_accounts,
+            arithmetic_mode,
+            rate_limit,
+            debug_validation,
+        } = config;
+
+        Bank {
+            id,
+            deps: Default::default(),
+            initial_balances: Default::default(),
+            currencies: Default::default(),
+            recovery_actors: Default::default(),
+            open_indices: Default::default(),
+            hist: Default::default(),
+            transfer_index: Default::default(),
+            metadata: Default::default(),
+            aliases: Default::default(),
+            transfer_count: 0,
+            total_volume: 0,
+            supply: 0,
+            applied_ops: 0,
+            frozen: Default::default(),
+            frozen_versions: Default::default(),
+            closed: Default::default(),
+            liabilities: Default::default(),
+            pending_transfers: Default::default(),
+            cancelled_transfers: Default::default(),
+            allowances: Default::default(),
+            allowance_versions: Default::default(),
+            recent_transfers: Default::default(),
+            fee_collector,
+            admin,
+            max_deps,
+            max_initial_balance,
+            max_accounts,
+            arithmetic_mode,
+            debug_validation,
+            validation_policy: None,
+            observers: Default::default(),
+            rate_limit,
+            checkpoints: Default::default(),
+            retention: None,
+            adjustments: Default::default(),
+            #[cfg(feature = "testing")]
+            ordering_seed: None,
+        }
+    }
+
+    /// Returns the configuration of this bank, e.g. to check that replicas
+    /// agree on it.  The validation policy is not part of it.
+    pub fn config(&self) -> BankConfig<A, M> {
+        BankConfig {
+            fee_collector: self.fee_collector.clone(),
+            admin: self.admin.clone(),
+            max_deps: self.max_deps,
+            max_initial_balance: self.max_initial_balance,
+            max_accounts: self.max_accounts,
+            arithmetic_mode: self.arithmetic_mode,
+            rate_limit: self.rate_limit,
+            debug_validation: self.debug_validation,
+        }
+    }
+
+    /// Returns the actor this bank initiates outgoing transfers for.
+    pub fn id(&self) -> &A {
+        &self.id
+    }
+
+    /// Configures the account that collects transfer fees.
+    ///
+    /// All replicas must be configured with the same collector.
+    pub fn with_fee_collector(mut self, fee_collector: A) -> Self {
+        self.fee_collector = Some(fee_collector);
+        self
+    }
+
+    /// Returns the account that collects transfer fees, if one is configured.
+    pub fn fee_collector(&self) -> Option<&A> {
+        self.fee_collector.as_ref()
+    }
+
+    /// Configures the actor allowed to perform administrative operations.
+    ///
+    /// All replicas must be configured with the same admin.
+    pub fn with_admin(mut self, admin: A) -> Self {
+        self.admin = Some(admin);
+        self
+    }
+
+    /// Returns the admin actor, if one is configured.
+    pub fn admin(&self) -> Option<&A> {
+        self.admin.as_ref()
+    }
+
+    /// Limits the number of dependencies a transfer may carry.  Unlimited by default.
+    ///
+    /// This bounds the number of dependencies, while `compact` bounds the
+    /// size of each of them.  When more incoming transfers are waiting to
+    /// be referenced, our outgoing transfers carry the first `max_deps`
+    /// of them and the rest are left for later transfers.  Since funds are
+    /// checked against the full history, this never prevents us from
+    /// spending money we hold.  Applying an incoming transfer also clears
+    /// deps our history already shows as referenced, so they don't crowd
+    /// out the ones still waiting.
+    ///
+    /// All replicas must be configured with the same limit.
+    pub fn with_max_deps(mut self, max_deps: usize) -> Self {
+        self.max_deps = max_deps;
+        self
+    }
+
+    /// Returns the maximum number of dependencies a transfer may carry.
+    pub fn max_deps(&self) -> usize {
+        self.max_deps
+    }
+
+    /// Configures the largest balance an account may be opened with.
+    ///
+    /// Defaults to 0, so that accounts must open empty and no actor can
+    /// mint money by opening an account.  Raise it to pre-fund a mint or
+    /// genesis accounts.
+    ///
+    /// All replicas must be configured with the same cap.
+    pub fn with_max_initial_balance(mut self, max_initial_balance: M) -> Self {
+        self.max_initial_balance = max_initial_balance;
+        self
+    }
+
+    /// Returns the largest balance an account may be opened with.
+    pub fn max_initial_balance(&self) -> M {
+        self.max_initial_balance
+    }
+
+    /// Caps the number of accounts, including frozen ones, that may be
+    /// opened, to keep account creation spam from growing the ledger.
+    /// Unlimited by default.
+    ///
+    /// All replicas must be configured with the same cap: a replica with a
+    /// lower cap rejects accounts others accept, and from then on diverges
+    /// from them.
+    pub fn with_max_accounts(mut self, max_accounts: usize) -> Self {
+        self.max_accounts = Some(max_accounts);
+        self
+    }
+
+    /// Returns the maximum number of accounts, if capped.
+    pub fn max_accounts(&self) -> Option<usize> {
+        self.max_accounts
+    }
+
+    /// Checks that `opening` more accounts may be opened
+    fn check_account_limit(&self, opening: usize) -> Result<(), ValidationError<A, M>> {
+        match self.max_accounts {
+            Some(max_accounts) if self.initial_balances.len() + opening > max_accounts => {
+                Err(ValidationError::AccountLimitReached { max_accounts })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Limits senders to `max_transfers` transfers within any window of
+    /// `window` consecutive applied ops.  Unlimited by default.
+    ///
+    /// BRB provides no wall clock, so the window is measured in ops applied
+    /// by the bank.  See `Bank::applied_ops` for how far replicas agree on
+    /// it.  Transfers, pending
+    /// transfers and delegated transfers all count against their sender.
+    ///
+    /// All replicas must be configured with the same limit, and configured
+    /// before applying any ops, since transfers are only tracked while a
+    /// limit is in place.
+    pub fn with_rate_limit(mut self, max_transfers: usize, window: u64) -> Self {
+        self.rate_limit = Some((max_transfers, window));
+        self
+    }
+
+    /// Visits transfers in an order derived from `seed` when computing
+    /// balances and applying ops, instead of the order of the underlying
+    /// collections.
+    ///
+    /// Results must not depend on that order, so a test can replay a
+    /// failing case under the seed it failed with, and check that other
+    /// seeds give the same results.  The seed is not part of snapshots.
+    #[cfg(feature = "testing")]
+    pub fn with_ordering_seed(mut self, seed: u64) -> Self {
+        self.ordering_seed = Some(seed);
+        self
+    }
+
+    /// Returns the ordering seed, if a test set one
+    fn ordering_seed(&self) -> Option<u64> {
+        #[cfg(feature = "testing")]
+        return self.ordering_seed;
+        #[cfg(not(feature = "testing"))]
+        return None;
+    }
+
+    /// Returns transfers in the order set by an ordering seed, if any
+    fn ordered<'a>(
+        seed: Option<u64>,
+        transfers: impl Iterator<Item = &'a Transfer<A, M>>,
+    ) -> impl Iterator<Item = &'a Transfer<A, M>>
+    where
+        A: 'a,
+    {
+        #[cfg(feature = "testing")]
+        let transfers = {
+            let mut transfers: Vec<_> = transfers.collect();
+            if let Some(seed) = seed {
+                transfers.sort_by_cached_key(|t| Sha256::digest(&canonical_bytes(&(seed, t))));
+            }
+            transfers.into_iter()
+        };
+        #[cfg(not(feature = "testing"))]
+        let _ = seed;
+        transfers
+    }
+
+    /// Returns the maximum number of transfers a sender may make within a
+    /// window of applied ops, and the size of that window, if limited.
+    pub fn rate_limit(&self) -> Option<(usize, u64)> {
+        self.rate_limit
+    }
+
+    /// Makes `apply` validate each op again in debug builds, panicking if
+    /// it fails, to catch integrations that apply ops without validating
+    /// them.  Has no effect in release builds.
+    ///
+    /// Ops are validated as if initiated by the actor entitled to initiate
+    /// them.  Off by default: under BRB, ops from different sources may be
+    /// applied against a different state than they were validated against,
+    /// e.g. concurrent registrations of the same alias both validate, but
+    /// whichever is applied second would fail again.
+    pub fn with_debug_validation(mut self) -> Self {
+        self.debug_validation = true;
+        self
+    }
+
+    /// Configures how balance arithmetic handles a result below zero or
+    /// above the maximum amount.  Defaults to `ArithmeticMode::Panic`.
+    ///
+    /// Such a result means the history is inconsistent.  Only `Panic`
+    /// preserves the invariant checking of `balance` and `apply_checked`;
+    /// with `Saturate`, balances are clamped to zero or the maximum amount,
+    /// and with `Error`, `balance_checked` and `balances` skip the account
+    /// while `try_balance` and `apply_checked` return an error.
+    ///
+    /// All replicas should be configured with the same mode.
+    pub fn with_arithmetic_mode(mut self, arithmetic_mode: ArithmeticMode) -> Self {
+        self.arithmetic_mode = arithmetic_mode;
+        self
+    }
+
+    /// Returns how balance arithmetic handles out-of-range results.
+    pub fn arithmetic_mode(&self) -> ArithmeticMode {
+        self.arithmetic_mode
+    }
+
+    /// Configures deployment-specific rules, checked by `validate` after
+    /// the built-in checks.  Ops the policy rejects fail validation with
+    /// `ValidationError::PolicyRejected`.
+    ///
+    /// The policy must be deterministic and all replicas must be configured
+    /// with the same one, see `ValidationPolicy`.
+    pub fn with_validation_policy(mut self, policy: impl ValidationPolicy<A, M> + 'static) -> Self {
+        self.validation_policy = Some(SharedPolicy(Arc::new(policy)));
+        self
+    }
+
+    /// Registers a callback notified of each op `apply` applies, along with
+    /// its effect, e.g. for an indexer to react to new transfers right away.
+    ///
+    /// Observers are called synchronously, in the order registered, after
+    /// the op is applied.  Duplicates, which applying skips, and ops refused
+    /// by `apply_checked` are not notified.  An observer must not panic, as
+    /// it would unwind through `apply`, and can't mutate the bank.  Clones
+    /// of the bank don't inherit observers.  With no observer registered,
+    /// `apply` does no extra work.
+    pub fn subscribe(&mut self, observer: OpObserver<A, M>) {
+        self.observers.push(observer);
+    }
+
+    /// Checks an op against the validation policy, if one is configured
+    fn check_policy(&self, source: &A, op: &Op<A, M>) -> Result<(), ValidationError<A, M>> {
+        match &self.validation_policy {
+            Some(policy) => policy
+                .0
+                .check(self, source, op)
+                .map_err(ValidationError::PolicyRejected),
+            None => Ok(()),
+        }
+    }
+
+    /// Freeze an account so it can neither send nor receive transfers.
+    ///
+    /// Only the admin's bank can generate this op.
+    pub fn freeze(&self, owner: A) -> Result<Op<A, M>, ValidationError<A, M>> {
+        self.set_frozen(owner, true)
+    }
+
+    /// Unfreeze a frozen account.
+    ///
+    /// Only the admin's bank can generate this op.
+    pub fn unfreeze(&self, owner: A) -> Result<Op<A, M>, ValidationError<A, M>> {
+        self.set_frozen(owner, false)
+    }
+
+    fn set_frozen(&self, owner: A, frozen: bool) -> Result<Op<A, M>, ValidationError<A, M>> {
+        self.check_admin(&self.id)?;
+        Ok(Op::SetFrozen { owner, frozen })
+    }
+
+    /// Set an account's balance directly, to correct it.
+    ///
+    /// Only the admin's bank can generate this op.
+    pub fn adjust_balance(
+        &self,
+        owner: A,
+        new_balance: M,
+    ) -> Result<Op<A, M>, ValidationError<A, M>> {
+        self.check_admin(&self.id)?;
+        Ok(Op::AdjustBalance { owner, new_balance })
+    }
+
+    /// Returns the balance adjustments of an account, oldest first.
+    pub fn balance_adjustments<'a>(
+        &'a self,
+        actor: &A,
+    ) -> impl Iterator<Item = &'a BalanceAdjustment<M>> {
+        self.adjustments.get(actor).into_iter().flatten()
+    }
+
+    /// Returns true if the account is frozen.
+    pub fn is_frozen(&self, actor: &A) -> bool {
+        self.frozen.contains(actor)
+    }
+
+    /// Checks that the actor is the configured admin
+    fn check_admin(&self, actor: &A) -> Result<(), ValidationError<A, M>> {
+        if self.admin.as_ref() == Some(actor) {
+            Ok(())
+        } else {
+            Err(ValidationError::NotInitiatedByAdmin {
+                initiator: actor.clone(),
+            })
+        }
+    }
+
+    /// Checks that both accounts of a transfer, and the fee collector if
+    /// the transfer carries a fee, are denominated in the transfer's currency
+    fn check_currency(&self, transfer: &Transfer<A, M>) -> Result<(), ValidationError<A, M>> {
+        let fee_collector = self
+            .fee_collector
+            .as_ref()
+            .filter(|_| transfer.fee > M::zero());
+        let mismatch = [&transfer.from, &transfer.to]
+            .iter()
+            .cloned()
+            .chain(fee_collector)
+            .map(|a| (a, self.account_currency(a).unwrap_or(DEFAULT_CURRENCY)))
+            .find(|(_, currency)| *currency != transfer.currency);
+
+        match mismatch {
+            Some((account, account_currency)) => Err(ValidationError::CurrencyMismatch {
+                account: account.clone(),
+                account_currency,
+                transfer_currency: transfer.currency,
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Checks that neither party of a transfer is frozen
+    fn check_not_frozen(&self, transfer: &Transfer<A, M>) -> Result<(), ValidationError<A, M>> {
+        match [&transfer.from, &transfer.to]
+            .iter()
+            .find(|a| self.is_frozen(a))
+        {
+            Some(account) => Err(ValidationError::AccountFrozen {
+                account: (*account).clone(),
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Open a new account.
+    ///
+    /// The balance field should normally be 0, but this field
+    /// enables an application to force a non-zero starting balance.
+    /// Though of course other nodes must agree.  This could for
+    /// example be used to pre-fund a "MINT" account that spends
+    /// money into existence (in other accounts) over time.
+    ///
+    /// A mint is an ordinary account, with no reserved role: its balance
+    /// is never negative, but is what it has yet to issue.  It opens with
+    /// the most money it will ever issue, which counts towards the supply
+    /// from the start, and the money issued so far is its initial balance
+    /// less its balance.  Transfers issuing more than is left are rejected
+    /// with `InsufficientFunds` like any other overdraft.  Alternatively, a
+    /// mint opened with `open_liability_account` starts empty and its
+    /// negative balance is the money it has issued.
+    pub fn open_account(&self, owner: A, balance: M) -> Op<A, M> {
+        self.open_account_in(owner, balance, DEFAULT_CURRENCY)
+    }
+
+    /// Open a new account denominated in the given currency.
+    ///
+    /// Transfers are only possible between accounts of the same currency.
+    pub fn open_account_in(&self, owner: A, balance: M, currency: Currency) -> Op<A, M> {
+        Op::OpenAccount {
+            owner,
+            balance,
+            currency,
+            recovery: None,
+            kind: AccountKind::Asset,
+        }
+    }
+
+    /// Open a new, empty liability account, whose balance may go negative,
+    /// e.g. to record money owed in double-entry bookkeeping.
+    ///
+    /// A liability account may send more than it holds, down to minus the
+    /// maximum amount.  While negative, its `balance` reads as zero and
+    /// `signed_balance` gives the amount owed.
+    pub fn open_liability_account(&self, owner: A) -> Op<A, M> {
+        Op::OpenAccount {
+            owner,
+            balance: M::zero(),
+            currency: DEFAULT_CURRENCY,
+            recovery: None,
+            kind: AccountKind::Liability,
+        }
+    }
+
+    /// Open many accounts at once, in the default currency, e.g. the genesis
+    /// accounts of a network, in a single BRB round.
+    ///
+    /// Accounts are opened in the order listed, and either all are opened
+    /// or none is: the batch is rejected if any owner already has an
+    /// account or is listed twice.
+    ///
+    /// Only the admin's bank can generate this op.
+    pub fn batch_open(&self, accounts: Vec<(A, M)>) -> Result<Op<A, M>, ValidationError<A, M>> {
+        self.check_admin(&self.id)?;
+        Ok(Op::BatchOpen { accounts })
+    }
+
+    /// Returns the kind of an account, or `None` if it does not exist.
+    pub fn account_kind(&self, actor: &A) -> Option<AccountKind> {
+        if !self.account_exists(actor) {
+            None
+        } else if self.liabilities.contains(actor) {
+            Some(AccountKind::Liability)
+        } else {
+            Some(AccountKind::Asset)
+        }
+    }
+
+    /// Open a new account that `recovery` may later move to a new owner,
+    /// see `recover_account`.
+    ///
+    /// The recovery actor is as powerful as the owner: it can move the
+    /// whole balance to an account of its choosing at any time, without
+    /// the owner's consent.  Only designate an actor the owner trusts as
+    /// much as its own key, e.g. a second key kept offline.
+    pub fn open_account_with_recovery(&self, owner: A, balance: M, recovery: A) -> Op<A, M> {
+        Op::OpenAccount {
+            owner,
+            balance,
+            currency: DEFAULT_CURRENCY,
+            recovery: Some(recovery),
+            kind: AccountKind::Asset,
+        }
+    }
+
+    /// Move the balance of an account to a new account for `new_owner`,
+    /// which must not already have one (but does not apply the
+    /// recovery).  Must be initiated by the account's recovery actor.
+    ///
+    /// The new account is opened in the same currency and of the same kind,
+    /// with the same recovery actor and a copy of the old account's
+    /// metadata, and receives the balance through a transfer from the old
+    /// account, whose deps link back to the old account's history.  The
+    /// old account's aliases resolve to the new account from then on.  The
+    /// old account is frozen, keeping its history and metadata.  Pending
+    /// transfers out of it that are later cancelled are refunded to the
+    /// frozen account.  A liability account with a negative balance can't
+    /// be recovered, as its debt would be left behind.
+    pub fn recover_account(&self, owner: A, new_owner: A) -> Op<A, M> {
+        Op::RecoverAccount { owner, new_owner }
+    }
+
+    /// Returns the actor allowed to recover an account, if it designated one.
+    pub fn recovery_actor(&self, owner: &A) -> Option<&A> {
+        self.recovery_actors.get(owner)
+    }
+
+    /// Opens an account, numbering it after the accounts already open
+    fn open(
+        &mut self,
+        owner: A,
+        balance: M,
+        currency: Currency,
+        recovery: Option<A>,
+        kind: AccountKind,
+    ) {
+        info!(
+            owner:? = owner,
+            balance:% = balance,
+            currency = currency,
+            kind:? = kind;
+            "[BANK] opening new account"
+        );
+        if kind == AccountKind::Liability {
+            self.liabilities.insert(owner.clone());
+        }
+        let open_index = self.open_indices.len() as u64;
+        self.open_indices.insert(owner.clone(), open_index);
+        self.currencies.insert(owner.clone(), currency);
+        if let Some(recovery) = recovery {
+            self.recovery_actors.insert(owner.clone(), recovery);
+        }
+        self.initial_balances.insert(owner, balance);
+        self.supply = self.supply.saturating_add(to_i128(balance));
+    }
+
+    /// Opens an account for `new_owner` and sweeps the balance of `owner`'s
+    /// account into it, then freezes `owner`'s account.
+    fn reassign_account(&mut self, owner: A, new_owner: A) {
+        let currency = self.account_currency(&owner).unwrap_or(DEFAULT_CURRENCY);
+        let open_index = self.open_indices.len() as u64;
+        self.open_indices.insert(new_owner.clone(), open_index);
+        self.currencies.insert(new_owner.clone(), currency);
+        self.initial_balances.insert(new_owner.clone(), M::zero());
+        if let Some(recovery) = self.recovery_actors.get(&owner).cloned() {
+            self.recovery_actors.insert(new_owner.clone(), recovery);
+        }
+        if self.liabilities.contains(&owner) {
+            self.liabilities.insert(new_owner.clone());
+        }
+        if let Some(metadata) = self.metadata.get(&owner).cloned() {
+            self.metadata.insert(new_owner.clone(), metadata);
+        }
+        for reserved in self.aliases.values_mut().filter(|a| **a == owner) {
+            *reserved = new_owner.clone();
+        }
+
+        self.sweep_balance(&owner, new_owner);
+        self.set_frozen_state(owner, true);
+    }
+
+    /// Freezes or unfreezes an account, counting the change towards its
+    /// frozen version
+    fn set_frozen_state(&mut self, owner: A, frozen: bool) {
+        *self.frozen_versions.entry(owner.clone()).or_default() += 1;
+        if frozen {
+            self.frozen.insert(owner);
+        } else {
+            self.frozen.remove(&owner);
+        }
+    }
+
+    /// Empty an account into `destination`'s account, then close it (but
+    /// does not apply it).  Must be initiated by the account's owner.
+    ///
+    /// Both happen in a single op, so no transfer can reach the account
+    /// between the sweep and the close.  The balance moves through a
+    /// transfer whose deps link back to the account's history, which is
+    /// kept since counterparties' histories refer to it.  A closed account
+    /// is frozen for good: it can neither send nor receive transfers, and
+    /// can't be unfrozen.  Accounts with pending transfers can't be closed,
+    /// and neither can liability accounts with a negative balance.
+    pub fn close_and_sweep(&self, owner: A, destination: A) -> Op<A, M> {
+        Op::CloseAndSweep { owner, destination }
+    }
+
+    /// Returns true if the account was closed with `close_and_sweep`.
+    pub fn is_closed(&self, actor: &A) -> bool {
+        self.closed.contains(actor)
+    }
+
+    /// Moves the whole balance of `owner`'s account to `destination`'s
+    /// through a recorded transfer, if there is any balance to move
+    fn sweep_balance(&mut self, owner: &A, destination: A) {
+        if let Some(sweep) = self.sweep_transfer(owner, destination) {
+            self.record_transfer(sweep);
+        }
+    }
+
+    /// Returns the transfer moving the whole balance of `owner`'s account
+    /// to `destination`'s, or `None` if the balance is zero
+    fn sweep_transfer(&self, owner: &A, destination: A) -> Option<Transfer<A, M>> {
+        let balance = self.balance(owner);
+        if balance == M::zero() {
+            return None;
+        }
+        let currency = self.account_currency(owner).unwrap_or(DEFAULT_CURRENCY);
+        let sweep = TransferBuilder::new(owner.clone(), destination, balance)
+            .currency(currency)
+            .seq(self.next_seq(owner))
+            .deps(self.pending_deps(owner))
+            .build();
+        Some(sweep)
+    }
+
+    /// Closes `owner`'s account after sweeping its balance into
+    /// `destination`'s account
+    fn close_account(&mut self, owner: A, destination: A) {
+        self.sweep_balance(&owner, destination);
+        self.set_frozen_state(owner.clone(), true);
+        self.closed.insert(owner);
+    }
+
+    /// Sets the balance of `owner`'s account, recording the adjustment in
+    /// its audit trail.
+    fn adjust_account_balance(&mut self, owner: A, new_balance: M) {
+        let previous_balance = self.balance(&owner);
+        let adjustment = BalanceAdjustment {
+            applied_op: self.applied_ops,
+            previous_balance,
+            new_balance,
+        };
+        self.supply = self
+            .supply
+            .saturating_add(to_i128(adjustment.credit()))
+            .saturating_sub(to_i128(adjustment.debit()));
+        self.adjustments
+            .entry(owner)
+            .or_default()
+            .insert(adjustment);
+    }
+
+    /// Returns the position of an account in the order accounts were opened,
+    /// starting at 0, or `None` if the account does not exist.
+    ///
+    /// Accounts opened concurrently may be applied in a different order by
+    /// other replicas, so this order is local to this bank.
+    pub fn open_index(&self, actor: &A) -> Option<u64> {
+        self.open_indices.get(actor).copied()
+    }
+
+    /// Returns every account, oldest first.
+    pub fn accounts_by_open_order(&self) -> Vec<A> {
+        let mut accounts: Vec<(&A, u64)> = self
+            .open_indices
+            .iter()
+            .map(|(actor, open_index)| (actor, *open_index))
+            .collect();
+        accounts.sort_by_key(|(_, open_index)| *open_index);
+        accounts
+            .into_iter()
+            .map(|(actor, _)| actor.clone())
+            .collect()
+    }
+
+    /// Returns the currency an account is denominated in, or `None` if the
+    /// account does not exist.
+    pub fn account_currency(&self, actor: &A) -> Option<Currency> {
+        self.currencies.get(actor).copied()
+    }
+
+    /// Open an account unless the owner already has one.
+    ///
+    /// Returns `None` when the account already exists, in which case there
+    /// is nothing to broadcast.  `validate` still rejects an `OpenAccount`
+    /// op for an existing account.
+    pub fn ensure_account(&self, owner: A, balance: M) -> Option<Op<A, M>> {
+        if self.account_exists(&owner) {
+            None
+        } else {
+            Some(self.open_account(owner, balance))
+        }
+    }
+
+    /// Set a metadata entry on an account.
+    ///
+    /// Only the account owner may set their own metadata, and the total
+    /// size of an account's metadata is capped at `MAX_ACCOUNT_METADATA_SIZE`.
+    pub fn set_account_metadata(&self, owner: A, key: String, value: String) -> Op<A, M> {
+        Op::SetAccountMetadata { owner, key, value }
+    }
+
+    /// Returns an account's metadata, if any has been set.
+    pub fn account_metadata(&self, actor: &A) -> Option<&BTreeMap<String, String>> {
+        self.metadata.get(actor)
+    }
+
+    /// Reserve a human-readable alias for an account, so that others can
+    /// send money to the alias instead of the actor.
+    ///
+    /// Aliases are 1 to `MAX_ALIAS_LEN` characters of lowercase ASCII
+    /// letters, digits, `-` and `_`, and are unique across the ledger.  An
+    /// account may hold several aliases.
+    pub fn register_alias(&self, owner: A, alias: String) -> Op<A, M> {
+        Op::RegisterAlias { owner, alias }
+    }
+
+    /// Returns the account an alias resolves to, if it is registered.
+    pub fn resolve_alias(&self, alias: &str) -> Option<&A> {
+        self.aliases.get(alias)
+    }
+
+    /// Generates a new Transfer operation to the account an alias resolves
+    /// to (but does not apply it)
+    pub fn transfer_to_alias(
+        &self,
+        from: A,
+        alias: &str,
+        amount: M,
+    ) -> Result<Op<A, M>, ValidationError<A, M>> {
+        let to = self
+            .resolve_alias(alias)
+            .ok_or_else(|| ValidationError::UnknownAlias {
+                alias: alias.to_string(),
+            })?;
+        self.transfer(from, to.clone(), amount)
+    }
+
+    /// Records an alias, unless it is already taken.
+    ///
+    /// An alias never moves to another account once registered, so that a
+    /// transfer built by `transfer_to_alias` pays the account it was built
+    /// for.  BRB only orders ops from the same source, so two actors may
+    /// both be allowed to register an alias: each replica keeps the
+    /// registration it applied first, and `merge` reports the conflict.
+    fn reserve_alias(&mut self, alias: String, owner: A) {
+        self.aliases.entry(alias).or_insert(owner);
+    }
+
+    /// Checks an alias is made of allowed characters and not already taken
+    fn check_alias(&self, alias: &str) -> Result<(), ValidationError<A, M>> {
+        let valid_char =
+            |c: char| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_';
+        if alias.is_empty() || alias.len() > MAX_ALIAS_LEN || !alias.chars().all(valid_char) {
+            return Err(ValidationError::InvalidAlias {
+                alias: alias.to_string(),
+            });
+        }
+        if self.aliases.contains_key(alias) {
+            return Err(ValidationError::AliasTaken {
+                alias: alias.to_string(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Returns the size an account's metadata would have after setting `key` to `value`.
+    fn metadata_size_with(&self, actor: &A, key: &str, value: &str) -> usize {
+        let current: usize = self
+            .metadata
+            .get(actor)
+            .into_iter()
+            .flatten()
+            .filter(|(k, _)| k.as_str() != key)
+            .map(|(k, v)| k.len() + v.len())
+            .sum();
+
+        current + key.len() + value.len()
+    }
+
+    /// Returns a snapshot of the ledger seen by this bank.
+    pub fn snapshot(&self) -> BankState<A, M> {
+        BankState {
+            initial_balances: self.initial_balances.clone(),
+            currencies: self.currencies.clone(),
+            recovery_actors: self.recovery_actors.clone(),
+            open_indices: self.open_indices.clone(),
+            hist: self.hist.clone(),
+            transfer_ids: self.compacted_ids(),
+            metadata: self.metadata.clone(),
+            aliases: self.aliases.clone(),
+            applied_ops: self.applied_ops,
+            frozen: self.frozen.clone(),
+            frozen_versions: self.frozen_versions.clone(),
+            closed: self.closed.clone(),
+            liabilities: self.liabilities.clone(),
+            pending_transfers: self.pending_transfers.clone(),
+            cancelled_transfers: self.cancelled_transfers.clone(),
+            allowances: self.allowances.clone(),
+            allowance_versions: self.allowance_versions.clone(),
+            recent_transfers: self.recent_transfers.clone(),
+            checkpoints: self.checkpoints.clone(),
+            adjustments: self.adjustments.clone(),
+            config: self.config(),
+        }
+    }
+
+    /// Restores a bank for the given actor from a ledger snapshot, configured
+    /// as the bank the snapshot was taken from.  The validation policy is not
+    /// part of the snapshot and must be configured again.
+    ///
+    /// The dependencies of the actor's next outgoing transfer are recomputed
+    /// from history: every incoming transfer not yet referenced by one of the
+    /// actor's outgoing transfers.
+    pub fn from_snapshot(id: A, state: BankState<A, M>) -> Self {
+        let BankState {
+            initial_balances,
+            currencies,
+            recovery_actors,
+            open_indices,
+            hist,
+            transfer_ids,
+            metadata,
+            aliases,
+            applied_ops,
+            frozen,
+            frozen_versions,
+            closed,
+            liabilities,
+            pending_transfers,
+            cancelled_transfers,
+            allowances,
+            allowance_versions,
+            recent_transfers,
+            checkpoints,
+            adjustments,
+            config,
+        } = state;
+        let BankConfig {
+            fee_collector,
+            admin,
+            max_deps,
+            max_initial_balance,
+            max_accounts,
+            arithmetic_mode,
+            rate_limit,
+            debug_validation,
+        } = config;
+
+        let mut bank = Bank {
+            id,
+            deps: Default::default(),
+            initial_balances,
+            currencies,
+            recovery_actors,
+            open_indices,
+            hist,
+            transfer_index: Default::default(),
+            metadata,
+            aliases,
+            transfer_count: 0,
+            total_volume: 0,
+            supply: 0,
+            applied_ops,
+            frozen,
+            frozen_versions,
+            closed,
+            liabilities,
+            pending_transfers,
+            cancelled_transfers,
+            allowances,
+            allowance_versions,
+            recent_transfers,
+            fee_collector,
+            admin,
+            max_deps,
+            max_initial_balance,
+            max_accounts,
+            arithmetic_mode,
+            debug_validation,
+            validation_policy: None,
+            observers: Default::default(),
+            rate_limit,
+            checkpoints,
+            retention: None,
+            adjustments,
+            #[cfg(feature = "testing")]
+            ordering_seed: None,
+        };
+        bank.deps = bank.pending_deps(&bank.id);
+        bank.recount_transfers();
+        bank.supply = bank.compute_supply();
+        bank.transfer_index = bank
+            .all_transfers()
+            .into_iter()
+            .map(|t| {
+                let id = t.id();
+                (transfer_ids.get(&id).copied().unwrap_or(id), t.clone())
+            })
+            .collect();
+        bank
+    }
+
+    /// Maps the id of each transfer stored compacted in history to the id
+    /// it was applied with, so that a bank restored from a snapshot indexes
+    /// it under the same id as we do.
+    fn compacted_ids(&self) -> BTreeMap<TransferId, TransferId> {
+        self.transfer_index
+            .iter()
+            .filter_map(|(id, t)| {
+                let compacted = t.compacted();
+                let stored = [&t.from, &t.to].iter().any(|account| {
+                    self.hist
+                        .get(*account)
+                        .is_some_and(|h| h.contains(&compacted))
+                });
+                Some((compacted.id(), *id)).filter(|(stored_id, _)| stored && stored_id != id)
+            })
+            .collect()
+    }
+
+    /// Merges the ledger of another replica into ours, e.g. to reconcile
+    /// replicas after a partition.
+    ///
+    /// Accounts and histories are unioned and derived state is recomputed.
+    /// Transfers are identified by their compacted form, so a transfer one
+    /// replica compacted and the other didn't is kept once, compacted.
+    /// Conflicting metadata values resolve to the greatest value, and the
+    /// applied op count is the largest of both, so that merging is
+    /// commutative, associative and idempotent.
+    /// Merging with a peer that applied every op we did thus brings our
+    /// applied op count, and with it time locks and rate limits, in line
+    /// with the peer's.
+    /// Frozen states and allowances are versioned by the number of changes
+    /// applied to them, and the replica that saw the most changes wins: an
+    /// account unfrozen on one replica stays unfrozen, and a new approval
+    /// replaces the other replica's allowance.  Replicas that saw the same
+    /// approval keep the smaller allowance, the one that saw more spending.
+    /// Pending transfers are unioned, less those either replica finalized
+    /// or cancelled.  Closed accounts are unioned, since closing is final.
+    /// Transfers tracked for rate limiting are unioned, keeping the latest,
+    /// and so are checkpoints, keeping the latest sequence number.
+    /// The exception is account open order, which is local to each
+    /// replica: accounts only `other` knows of are ordered after ours.
+    /// Configuration is left as is.
+    ///
+    /// Returns an error, leaving the bank untouched, if the replicas
+    /// disagree on the initial balance, currency or recovery actor of an
+    /// account or on the account an alias resolves to, or if the merged history puts an account's balance out of
+    /// range, unless the arithmetic mode saturates balances: such replicas
+    /// can't both be honest.
+    pub fn merge(&mut self, other: &Bank<A, M>) -> Result<(), MergeError<A>> {
+        for (actor, balance) in other.initial_balances.iter() {
+            if matches!(self.initial_balances.get(actor), Some(ours) if ours != balance) {
+                return Err(MergeError::InitialBalanceMismatch {
+                    account: actor.clone(),
+                });
+            }
+        }
+        for (actor, currency) in other.currencies.iter() {
+            if matches!(self.currencies.get(actor), Some(ours) if ours != currency) {
+                return Err(MergeError::CurrencyMismatch {
+                    account: actor.clone(),
+                });
+            }
+        }
+        for (actor, recovery) in other.recovery_actors.iter() {
+            if matches!(self.recovery_actors.get(actor), Some(ours) if ours != recovery) {
+                return Err(MergeError::RecoveryActorMismatch {
+                    account: actor.clone(),
+                });
+            }
+        }
+        for (alias, owner) in other.aliases.iter() {
+            if matches!(self.aliases.get(alias), Some(ours) if ours != owner) {
+                return Err(MergeError::AliasMismatch {
+                    alias: alias.clone(),
+                });
+            }
+        }
+
+        let mut merged = self.clone();
+        merged.merge_ledger(other);
+        if merged.arithmetic_mode != ArithmeticMode::Saturate {
+            for actor in merged.initial_balances.keys() {
+                match merged.try_balance(actor) {
+                    Ok(_) => (),
+                    Err(ApplyError::BalanceUnderflow { account }) => {
+                        return Err(MergeError::NegativeBalance { account })
+                    }
+                    Err(_) => {
+                        return Err(MergeError::BalanceOverflow {
+                            account: actor.clone(),
+                        })
+                    }
+                }
+            }
+        }
+        *self = merged;
+        Ok(())
+    }
+
+    /// Merges the ledger of another replica into ours, once `merge` checked
+    /// the replicas agree on every account they both know of
+    fn merge_ledger(&mut self, other: &Bank<A, M>) {
+        for (actor, balance) in other.initial_balances.iter() {
+            self.initial_balances
+                .entry(actor.clone())
+                .or_insert(*balance);
+        }
+        for (actor, currency) in other.currencies.iter() {
+            self.currencies.entry(actor.clone()).or_insert(*currency);
+        }
+        for (actor, recovery) in other.recovery_actors.iter() {
+            self.recovery_actors
+                .entry(actor.clone())
+                .or_insert_with(|| recovery.clone());
+        }
+
+        // Accounts only the other replica knows of are numbered after ours
+        let mut unseen: Vec<(&A, u64)> = other
+            .open_indices
+            .iter()
+            .filter(|(actor, _)| !self.open_indices.contains_key(actor))
+            .map(|(actor, open_index)| (actor, *open_index))
+            .collect();
+        unseen.sort_by_key(|(_, open_index)| *open_index);
+        for (actor, _) in unseen {
+            let open_index = self.open_indices.len() as u64;
+            self.open_indices.insert(actor.clone(), open_index);
+        }
+
+        for (actor, transfers) in other.hist.iter() {
+            let ours = self.hist.entry(actor.clone()).or_default();
+            let stored: BTreeMap<Transfer<A, M>, Transfer<A, M>> =
+                ours.iter().map(|t| (t.compacted(), t.clone())).collect();
+            for transfer in transfers.iter() {
+                let compacted = transfer.compacted();
+                match stored.get(&compacted) {
+                    None => {
+                        ours.insert(transfer.clone());
+                    }
+                    Some(stored) if stored != &compacted && transfer == &compacted => {
+                        ours.remove(stored);
+                        ours.insert(compacted);
+                    }
+                    Some(_) => (),
+                }
+            }
+        }
+        self.transfer_index
+            .extend(other.transfer_index.iter().map(|(id, t)| (*id, t.clone())));
+
+        for (actor, entries) in other.metadata.iter() {
+            let ours = self.metadata.entry(actor.clone()).or_default();
+            for (key, value) in entries.iter() {
+                let ours = ours.entry(key.clone()).or_insert_with(|| value.clone());
+                if value > ours {
+                    *ours = value.clone();
+                }
+            }
+        }
+
+        for (alias, owner) in other.aliases.iter() {
+            self.reserve_alias(alias.clone(), owner.clone());
+        }
+
+        let frozen_accounts: BTreeSet<A> = other
+            .frozen_versions
+            .keys()
+            .chain(other.frozen.iter())
+            .cloned()
+            .collect();
+        for actor in frozen_accounts {
+            let ours = self.frozen_versions.get(&actor).copied().unwrap_or(0);
+            let theirs = other.frozen_versions.get(&actor).copied().unwrap_or(0);
+            let frozen = other.frozen.contains(&actor);
+            if theirs > ours {
+                self.frozen_versions.insert(actor.clone(), theirs);
+                if frozen {
+                    self.frozen.insert(actor);
+                } else {
+                    self.frozen.remove(&actor);
+                }
+            } else if theirs == ours && frozen {
+                self.frozen.insert(actor);
+            }
+        }
+        self.closed.extend(other.closed.iter().cloned());
+        self.frozen.extend(self.closed.iter().cloned());
+        self.liabilities.extend(other.liabilities.iter().cloned());
+        self.applied_ops = self.applied_ops.max(other.applied_ops);
+
+        for (sender, applied) in other.recent_transfers.iter() {
+            let ours = self.recent_transfers.entry(sender.clone()).or_default();
+            ours.extend(applied.iter().copied());
+            ours.sort_unstable();
+            ours.dedup();
+            if let Some((max_transfers, _)) = self.rate_limit {
+                let excess = ours.len().saturating_sub(max_transfers);
+                ours.drain(..excess);
+            }
+        }
+
+        for (actor, next_seq) in other.checkpoints.iter() {
+            let ours = self.checkpoints.entry(actor.clone()).or_insert(*next_seq);
+            *ours = (*ours).max(*next_seq);
+        }
+
+        for (actor, adjustments) in other.adjustments.iter() {
+            self.adjustments
+                .entry(actor.clone())
+                .or_default()
+                .extend(adjustments.iter().cloned());
+        }
+
+        let allowances: BTreeSet<(A, A)> = other
+            .allowance_versions
+            .keys()
+            .chain(other.allowances.keys())
+            .cloned()
+            .collect();
+        for key in allowances {
+            let ours = self.allowance_versions.get(&key).copied().unwrap_or(0);
+            let theirs = other.allowance_versions.get(&key).copied().unwrap_or(0);
+            let their_allowance = other.allowances.get(&key).copied();
+            let allowance = if theirs > ours {
+                self.allowance_versions.insert(key.clone(), theirs);
+                their_allowance
+            } else if theirs == ours {
+                // Spending only lowers the allowance both replicas approved
+                self.allowances.get(&key).copied().min(their_allowance)
+            } else {
+                continue;
+            };
+            match allowance {
+                Some(allowance) => self.allowances.insert(key, allowance),
+                None => self.allowances.remove(&key),
+            };
+        }
+
+        // Transfers finalized or cancelled by either replica are no longer pending
+        self.cancelled_transfers
+            .extend(other.cancelled_transfers.iter().copied());
+        self.pending_transfers.extend(
+            other
+                .pending_transfers
+                .iter()
+                .map(|(id, t)| (*id, t.clone())),
+        );
+        let settled: Vec<TransferId> = self
+            .pending_transfers
+            .iter()
+            .filter(|(id, t)| self.cancelled_transfers.contains(id) || self.is_recorded(t))
+            .map(|(id, _)| *id)
+            .collect();
+        for id in settled {
+            self.pending_transfers.remove(&id);
+        }
+
+        self.deps = self.pending_deps(&self.id);
+        self.recount_transfers();
+        self.supply = self.compute_supply();
+    }
+
+    /// Recomputes the transfer count and volume from history
+    fn recount_transfers(&mut self) {
+        // Each transfer is recorded in the histories of both its accounts,
+        // either of which may have been checkpointed
+        let transfers = self.all_transfers();
+        let transfer_count = transfers.len();
+        let total_volume = transfers
+            .iter()
+            .fold(0u128, |total, t| total.saturating_add(t.amount.as_u128()));
+
+        self.transfer_count = transfer_count;
+        self.total_volume = total_volume;
+    }
+
+    /// Computes the supply from scratch: the initial balance of every
+    /// account plus the transfers into it, less the transfers out of it, as
+    /// recorded in its own history.
+    ///
+    /// Each transfer is counted in and out once, so transfers cancel out,
+    /// including those a checkpoint folded into an initial balance.
+    /// Pending transfers are not in history, so their amounts count as
+    /// still held by their senders.  Balance adjustments create or destroy
+    /// the money they credit or debit.
+    fn compute_supply(&self) -> i128 {
+        let mut supply = self
+            .initial_balances
+            .values()
+            .fold(0i128, |supply, balance| {
+                supply.saturating_add(to_i128(*balance))
+            });
+        for (actor, transfers) in self.hist.iter() {
+            for t in transfers.iter() {
+                if &t.to == actor {
+                    supply = supply.saturating_add(to_i128(t.amount));
+                }
+                if &t.from == actor {
+                    supply = supply.saturating_sub(to_i128(t.amount));
+                }
+            }
+        }
+        for adjustment in self.adjustments.values().flatten() {
+            supply = supply
+                .saturating_add(to_i128(adjustment.credit()))
+                .saturating_sub(to_i128(adjustment.debit()));
+        }
+        supply
+    }
+
+    /// Returns whether another replica agrees with ours on accounts, their
+    /// histories and balance adjustments.
+    ///
+    /// Unlike `==`, the owning actor and the dependencies of its next
+    /// transfer, which differ between replicas, are ignored, and so are
+    /// metadata and other account state: see `diff` for a full comparison.
+    /// Transfers are compared in their compacted form, so running `compact`
+    /// on either replica doesn't set them apart.
+    pub fn same_ledger(&self, other: &Bank<A, M>) -> bool {
+        self.initial_balances == other.initial_balances
+            && self.compacted_hist() == other.compacted_hist()
+            && self.adjustments == other.adjustments
+    }
+
+    /// Returns a digest of the accounts, their histories and balance
+    /// adjustments, as compared by `same_ledger`: the SHA-256 hash of their
+    /// canonical encoding.
+    ///
+    /// Replicas that agree produce the same digest, so it can be gossiped
+    /// to detect divergence without exchanging the ledger itself.
+    pub fn state_hash(&self) -> [u8; 32] {
+        Sha256::digest(&canonical_bytes(&(
+            &self.initial_balances,
+            &self.compacted_hist(),
+            &self.adjustments,
+        )))
+        .into()
+    }
+
+    /// Returns every account's history with its transfers compacted, the
+    /// form `compact` converges to.
+    fn compacted_hist(&self) -> BTreeMap<&A, BTreeSet<Transfer<A, M>>> {
+        self.hist
+            .iter()
+            .map(|(actor, transfers)| (actor, transfers.iter().map(Transfer::compacted).collect()))
+            .collect()
+    }
+
+    /// Reports how this bank's ledger diverges from another replica's.
+    ///
+    /// Only ledger state is compared; the owning actor and pending
+    /// dependencies are expected to differ between replicas.  Transfers
+    /// are compared as stored, so a replica that has run `compact` will
+    /// report its compacted transfers as divergent.
+    pub fn diff(&self, other: &Bank<A, M>) -> BankDiff<A, M> {
+        let ours: BTreeSet<&A> = self.initial_balances.keys().collect();
+        let theirs: BTreeSet<&A> = other.initial_balances.keys().collect();
+
+        let balance_mismatches = ours
+            .intersection(&theirs)
+            .map(|a| (*a, self.balance(a), other.balance(a)))
+            .filter(|(_, b1, b2)| b1 != b2)
+            .map(|(a, b1, b2)| (a.clone(), (b1, b2)))
+            .collect();
+
+        let our_transfers = self.all_transfers();
+        let their_transfers = other.all_transfers();
+
+        BankDiff {
+            accounts_only_in_self: ours.difference(&theirs).cloned().cloned().collect(),
+            accounts_only_in_other: theirs.difference(&ours).cloned().cloned().collect(),
+            balance_mismatches,
+            transfers_only_in_self: our_transfers
+                .difference(&their_transfers)
+                .cloned()
+                .cloned()
+                .collect(),
+            transfers_only_in_other: their_transfers
+                .difference(&our_transfers)
+                .cloned()
+                .cloned()
+                .collect(),
+        }
+    }
+
+    /// Returns every transfer in the ledger, de-duplicated across the
+    /// histories of the sender and recipient.
+    ///
+    /// Transfers are deduplicated in a `BTreeSet` and iterate in `Transfer`'s
+    /// `Ord`, so replicas holding the same transfers yield them in the same
+    /// order, whatever order they were applied in.  `state_hash`, snapshots
+    /// and exports rely on this; iterators over transfers must never
+    /// deduplicate through hashing, whose order varies between processes.
+    pub fn all_transfers(&self) -> BTreeSet<&Transfer<A, M>> {
+        self.hist.values().flatten().collect()
+    }
+
+    /// Returns the transfers whose amount is between `min` and `max`
+    /// inclusive, e.g. to flag large transfers or filter out dust.
+    ///
+    /// Each transfer is yielded once, in transfer order, though it is in
+    /// the histories of both its sender and recipient.  Fee payments are
+    /// transfers of their own, of the fee amount.
+    pub fn transfers_in_range(&self, min: M, max: M) -> impl Iterator<Item = &Transfer<A, M>> {
+        self.all_transfers()
+            .into_iter()
+            .filter(move |t| min <= t.amount && t.amount <= max)
+    }
+
+    /// Exports the ledger as a flat list of accounts and transfers, for
+    /// consumption by external tools.
+    ///
+    /// See `snapshot` for syncing replicas.
+    pub fn export_ledger(&self) -> LedgerExport<A, M> {
+        LedgerExport {
+            accounts: self
+                .initial_balances
+                .iter()
+                .map(|(actor, initial_balance)| AccountExport {
+                    actor: actor.clone(),
+                    currency: self.account_currency(actor).unwrap_or(DEFAULT_CURRENCY),
+                    initial_balance: *initial_balance,
+                    current_balance: self.balance(actor),
+                    kind: self.account_kind(actor).unwrap_or_default(),
+                })
+                .collect(),
+            transfers: self.all_transfers().into_iter().cloned().collect(),
+        }
+    }
+
+    /// Reconstructs a bank for the given actor from a ledger export.
+    ///
+    /// Fails if a transfer involves an account missing from the export, or
+    /// if an account's exported balance doesn't match the balance computed
+    /// from its transfers.
+    ///
+    /// The export doesn't record the order accounts were opened in, so
+    /// accounts are numbered in the order they are listed.  Nor does it
+    /// record the configuration, so the bank has the default one.
+    pub fn import_ledger(id: A, export: LedgerExport<A, M>) -> Result<Self, ImportError<A, M>> {
+        let initial_balances: BTreeMap<A, M> = export
+            .accounts
+            .iter()
+            .map(|a| (a.actor.clone(), a.initial_balance))
+            .collect();
+        let currencies = export
+            .accounts
+            .iter()
+            .map(|a| (a.actor.clone(), a.currency))
+            .collect();
+        let open_indices = export
+            .accounts
+            .iter()
+            .zip(0..)
+            .map(|(a, open_index)| (a.actor.clone(), open_index))
+            .collect();
+        let liabilities = export
+            .accounts
+            .iter()
+            .filter(|a| a.kind == AccountKind::Liability)
+            .map(|a| a.actor.clone())
+            .collect();
+
+        let mut hist: BTreeMap<A, BTreeSet<Transfer<A, M>>> = BTreeMap::new();
+        for transfer in export.transfers {
+            for account in [&transfer.from, &transfer.to].iter() {
+                if !initial_balances.contains_key(account) {
+                    return Err(ImportError::UnknownAccount {
+                        account: (*account).clone(),
+                    });
+                }
+            }
+            hist.entry(transfer.from.clone())
+                .or_default()
+                .insert(transfer.clone());
+            hist.entry(transfer.to.clone())
+                .or_default()
+                .insert(transfer);
+        }
+
+        let bank = Self::from_snapshot(
+            id,
+            BankState {
+                initial_balances,
+                currencies,
+                recovery_actors: Default::default(),
+                open_indices,
+                hist,
+                transfer_ids: Default::default(),
+                metadata: Default::default(),
+                aliases: Default::default(),
+                applied_ops: 0,
+                frozen: Default::default(),
+                frozen_versions: Default::default(),
+                closed: Default::default(),
+                liabilities,
+                pending_transfers: Default::default(),
+                cancelled_transfers: Default::default(),
+                allowances: Default::default(),
+                allowance_versions: Default::default(),
+                recent_transfers: Default::default(),
+                checkpoints: Default::default(),
+                adjustments: Default::default(),
+                config: Default::default(),
+            },
+        );
+
+        for account in export.accounts {
+            let computed = bank
+                .replay_balance(&account.actor, account.initial_balance)
+                .ok();
+            if computed != Some(account.current_balance) {
+                return Err(ImportError::BalanceMismatch {
+                    account: account.actor,
+                    exported: account.current_balance,
+                    computed,
+                });
+            }
+        }
+
+        Ok(bank)
+    }
+
+    /// Returns a replica of this bank owned by another actor, e.g. to
+    /// onboard a node from a peer's state, or to initiate transfers for
+    /// another account in a simulation.
+    ///
+    /// The ledger and configuration are cloned as is.  The dependencies of
+    /// the new owner's next outgoing transfer are recomputed from history,
+    /// as in `from_snapshot`.  Observers are not carried over.
+    pub fn clone_for(&self, new_id: A) -> Self {
+        let mut bank = self.clone();
+        bank.id = new_id;
+        bank.deps = bank.pending_deps(&bank.id);
+        bank
+    }
+
+    /// Incoming transfers of an actor that no outgoing transfer depends on yet
+    fn pending_deps(&self, actor: &A) -> BTreeSet<Transfer<A, M>> {
+        let spent = self.spent_deps(actor);
+        self.history(actor)
+            .into_iter()
+            .filter(|t| &t.to == actor && !spent.contains(&t.without_deps()))
+            .collect()
+    }
+
+    /// Incoming transfers of an actor that its outgoing transfers depend on,
+    /// without their own deps
+    fn spent_deps(&self, actor: &A) -> BTreeSet<Transfer<A, M>> {
+        self.history_iter(actor)
+            .filter(|t| &t.from == actor)
+            .flat_map(|t| t.deps.iter().map(Transfer::without_deps))
+            .collect()
+    }
+
+    /// Returns an account's starting balance, prior to any transfers in or out.
+    pub fn initial_balance(&self, actor: &A) -> M {
+        self.initial_balances
+            .get(&actor)
+            .cloned()
+            .unwrap_or_else(|| panic!("[ERROR] No initial balance for {:?}", actor))
+    }
+
+    /// Returns every open account, ordered by actor.
+    pub fn accounts(&self) -> impl Iterator<Item = &A> {
+        self.initial_balances.keys()
+    }
+
+    /// Returns the sum of the balances accounts were opened with, amounts
+    /// reserved by pending transfers included.
+    ///
+    /// Transfers and fees only move money between accounts, so this is the
+    /// money in existence as long as none was created or destroyed since:
+    /// balance adjustments are not counted, and `checkpoint_account`
+    /// rewrites initial balances.  See `current_supply` for the money in
+    /// existence.  Panics if the sum overflows.
+    pub fn total_supply(&self) -> M {
+        checked_sum(self.initial_balances.values().copied())
+            .expect("[ERROR] Total supply overflowed")
+    }
+
+    /// Returns the amount of money in existence, maintained as ops are
+    /// applied rather than summed over accounts.
+    ///
+    /// Unlike `total_supply`, it stays correct once the money in existence
+    /// no longer matches the balances accounts were opened with, e.g. after
+    /// a balance adjustment or `checkpoint_account`.
+    pub fn current_supply(&self) -> u128 {
+        u128::try_from(self.supply).unwrap_or(0)
+    }
+
+    /// Returns a read-only view of the bank, cheap to copy and share
+    /// between readers.
+    pub fn view(&self) -> BankView<'_, A, M> {
+        BankView::new(self)
+    }
+
+    /// Returns true if the actor has opened an account.
+    pub fn account_exists(&self, actor: &A) -> bool {
+        self.initial_balances.contains_key(actor)
+    }
+
+    /// Returns an account's present balance.
+    ///
+    /// Panics if the account does not exist, see `balance_checked`.
+    /// An out-of-range balance is handled according to the arithmetic mode;
+    /// having no error to return, this panics under `ArithmeticMode::Error`
+    /// too, so use `try_balance` in that mode.  A liability account's
+    /// negative balance reads as zero, see `signed_balance`.
+    ///
+    /// This is presently a slow operation as the entire history of all
+    /// transfers is iterated.  i.e., it degrades O(n) with the size of the history.
+    pub fn balance(&self, actor: &A) -> M {
+        self.expect_balance(actor, self.try_balance(actor))
+    }
+
+    /// Returns an account's present balance in the given currency, or `None`
+    /// if the account does not exist.
+    ///
+    /// An account only holds money in the currency it was opened in, so its
+    /// balance in any other currency is zero.
+    pub fn balance_in(&self, actor: &A, currency: Currency) -> Option<M> {
+        if self.account_currency(actor)? == currency {
+            self.balance_checked(actor)
+        } else {
+            Some(M::zero())
+        }
+    }
+
+    /// Returns an account's present balance, or `None` if the account does not exist.
+    ///
+    /// An out-of-range balance is handled according to the arithmetic mode,
+    /// and also yields `None` under `ArithmeticMode::Error`.
+    pub fn balance_checked(&self, actor: &A) -> Option<M> {
+        // PERF: Can we make this function faster?  perhaps even O(1)?
+        let initial_balance = *self.initial_balances.get(actor)?;
+        let balance = self.replay_balance(actor, initial_balance);
+
+        self.resolve_balance(balance)
+    }
+
+    /// Returns an account's present balance, or an error if the account does
+    /// not exist or its history puts the balance out of range.
+    ///
+    /// Unlike `balance`, this never panics nor saturates, whatever the
+    /// arithmetic mode.
+    pub fn try_balance(&self, actor: &A) -> Result<M, ApplyError<A>> {
+        self.balance_with(actor, self.pending_debits(actor), core::iter::empty())
+    }
+
+    /// Handles an out-of-range balance according to the arithmetic mode
+    fn resolve_balance(&self, balance: Result<M, ApplyError<A>>) -> Option<M> {
+        match (balance, self.arithmetic_mode) {
+            (Ok(balance), _) => Some(balance),
+            // sanity check that we haven't violated our balance constraint and that the balance fits
+            (Err(e), ArithmeticMode::Panic) => panic!("[ERROR] Invalid balance: {}", e),
+            (Err(ApplyError::BalanceUnderflow { .. }), ArithmeticMode::Saturate) => Some(M::zero()),
+            (Err(_), ArithmeticMode::Saturate) => Some(M::max_value()),
+            (Err(_), ArithmeticMode::Error) => None,
+        }
+    }
+
+    /// Returns the balance apply-time checks work from, surfacing an
+    /// out-of-range balance as an error under `ArithmeticMode::Error`.
+    fn balance_for_apply(&self, actor: &A) -> Result<M, ApplyError<A>> {
+        match self.arithmetic_mode {
+            ArithmeticMode::Error => self.try_balance(actor),
+            ArithmeticMode::Panic | ArithmeticMode::Saturate => Ok(self.balance(actor)),
+        }
+    }
+
+    /// Returns an account's settled balance: its initial balance plus the
+    /// transfers in its history, ignoring pending transfers.
+    ///
+    /// This differs from `balance` only while the account has initiated
+    /// transfers that are neither finalized nor cancelled: `balance` has
+    /// already debited them, this hasn't.  Incoming transfers this bank
+    /// holds as `deps` for its next outgoing transfer are already in
+    /// history, so every balance counts them.
+    ///
+    /// Panics if the account does not exist.  An out-of-range balance is
+    /// handled like in `balance`.
+    pub fn confirmed_balance(&self, actor: &A) -> M {
+        let balance = self.balance_with(actor, core::iter::empty(), core::iter::empty());
+        self.expect_balance(actor, balance)
+    }
+
+    /// Returns the balance an account will have once every pending transfer
+    /// is finalized: `balance`, plus the pending transfers the account is
+    /// due to receive.
+    ///
+    /// This differs from `balance` only while transfers to the account, or
+    /// their fees if the account collects fees, are pending.  Cancelled
+    /// transfers never arrive, so this is a projection, not a promise.
+    ///
+    /// Panics if the account does not exist.  An out-of-range balance is
+    /// handled like in `balance`.
+    pub fn projected_balance(&self, actor: &A) -> M {
+        let balance = self.balance_with(
+            actor,
+            self.pending_debits(actor),
+            self.pending_credits(actor),
+        );
+        self.expect_balance(actor, balance)
+    }
+
+    /// Unwraps a balance like `balance` does
+    fn expect_balance(&self, actor: &A, balance: Result<M, ApplyError<A>>) -> M {
+        if let Err(ApplyError::AccountDoesNotExist { .. }) = balance {
+            panic!("[ERROR] No initial balance for {:?}", actor);
+        }
+
+        self.resolve_balance(balance)
+            .unwrap_or_else(|| panic!("[ERROR] Invalid balance for {:?}", actor))
+    }
+
+    /// Applies the actor's history, along with extra debits and credits, to
+    /// its initial balance
+    fn balance_with(
+        &self,
+        actor: &A,
+        debits: impl Iterator<Item = M>,
+        credits: impl Iterator<Item = M>,
+    ) -> Result<M, ApplyError<A>> {
+        let initial_balance =
+            *self
+                .initial_balances
+                .get(actor)
+                .ok_or_else(|| ApplyError::AccountDoesNotExist {
+                    account: actor.clone(),
+                })?;
+
+        self.replay_with(actor, initial_balance, debits, credits)
+    }
+
+    /// Applies the actor's history to its initial balance, returning an
+    /// error if the balance would drop below zero or overflow.
+    fn replay_balance(&self, actor: &A, initial_balance: M) -> Result<M, ApplyError<A>> {
+        self.replay_with(
+            actor,
+            initial_balance,
+            self.pending_debits(actor),
+            core::iter::empty(),
+        )
+    }
+
+    /// Applies the actor's history, along with extra debits and credits, to
+    /// the given initial balance
+    fn replay_with(
+        &self,
+        actor: &A,
+        initial_balance: M,
+        debits: impl Iterator<Item = M>,
+        credits: impl Iterator<Item = M>,
+    ) -> Result<M, ApplyError<A>> {
+        self.replay_signed(actor, initial_balance, debits, credits)
+            .map(to_money)
+    }
+
+    /// Applies the actor's history, along with extra debits and credits, to
+    /// the given initial balance, keeping the sign of a liability's balance
+    fn replay_signed(
+        &self,
+        actor: &A,
+        initial_balance: M,
+        debits: impl Iterator<Item = M>,
+        credits: impl Iterator<Item = M>,
+    ) -> Result<i128, ApplyError<A>> {
+        // TODO: in the paper, when we read from an actor, we union the actor
+        //       history with the deps, I don't see a use for this since anything
+        //       in deps is already in the actor history. Think this through a
+        //       bit more carefully.
+        let underflow = || ApplyError::BalanceUnderflow {
+            account: actor.clone(),
+        };
+        let overflow = || ApplyError::BalanceOverflow {
+            account: actor.clone(),
+        };
+
+        // Sums are taken wider than amounts, so money moving back and forth
+        // can add up past the maximum amount while the balance stays valid.
+        // More money out than can be counted can only overdraw the account
+        let outgoing = wide_sum(
+            Self::ordered(self.ordering_seed(), self.history_iter(actor))
+                .filter(|t| &t.from == actor)
+                .map(|t| t.amount)
+                .chain(self.balance_adjustments(actor).map(|a| a.debit()))
+                .chain(debits),
+        )
+        .ok_or_else(underflow)?;
+        let incoming = wide_sum(
+            Self::ordered(self.ordering_seed(), self.history_iter(actor))
+                .filter(|t| &t.to == actor)
+                .map(|t| t.amount)
+                .chain(self.balance_adjustments(actor).map(|a| a.credit()))
+                .chain(credits),
+        )
+        .ok_or_else(overflow)?;
+
+        self.net_balance(actor, initial_balance, incoming, outgoing)
+    }
+
+    /// Applies the net difference of incoming and outgoing amounts to an
+    /// account's initial balance, which only a liability account may take
+    /// below zero, down to minus the maximum amount
+    fn net_balance(
+        &self,
+        actor: &A,
+        initial_balance: M,
+        incoming: u128,
+        outgoing: u128,
+    ) -> Result<i128, ApplyError<A>> {
+        let balance = to_i128(initial_balance)
+            .saturating_add(wide_to_i128(incoming))
+            .saturating_sub(wide_to_i128(outgoing));
+        let max = to_i128(M::max_value());
+        let min = if self.liabilities.contains(actor) {
+            -max
+        } else {
+            0
+        };
+
+        let account = actor.clone();
+        if balance < min {
+            Err(ApplyError::BalanceUnderflow { account })
+        } else if balance > max {
+            Err(ApplyError::BalanceOverflow { account })
+        } else {
+            Ok(balance)
+        }
+    }
+
+    /// Returns an account's present balance, negative while a liability
+    /// account owes money, or `None` if the account does not exist or its
+    /// history puts the balance out of range.
+    ///
+    /// For asset accounts, and liability accounts that owe nothing, this is
+    /// `balance`.
+    pub fn signed_balance(&self, actor: &A) -> Option<i128> {
+        let initial_balance = *self.initial_balances.get(actor)?;
+        self.replay_signed(
+            actor,
+            initial_balance,
+            self.pending_debits(actor),
+            core::iter::empty(),
+        )
+        .ok()
+    }
+
+    /// Returns whether a liability account can send `amount` by taking its
+    /// balance (further) below zero
+    fn liability_covers(&self, actor: &A, amount: M) -> bool {
+        self.liability_covers_after(actor, amount, &PriorLegs::new())
+    }
+
+    /// Like `liability_covers`, once the given earlier transfers of a
+    /// MultiTransfer are applied
+    fn liability_covers_after(&self, actor: &A, amount: M, legs: &PriorLegs<A>) -> bool {
+        self.liabilities.contains(actor)
+            && matches!(
+                self.signed_balance(actor),
+                Some(balance) if balance
+                    .saturating_add(legs.moved(actor))
+                    .saturating_sub(to_i128(amount)) >= -to_i128(M::max_value())
+            )
+    }
+
+    /// Returns an account's balance once the given earlier transfers of a
+    /// MultiTransfer are applied
+    fn balance_after(&self, actor: &A, balance: M, legs: &PriorLegs<A>) -> M {
+        to_money(to_i128(balance).saturating_add(legs.moved(actor)))
+    }
+
+    /// Returns an account's balance counting only transfers stamped with a
+    /// unix time in milliseconds earlier than `timestamp`.
+    ///
+    /// Timestamps are chosen by senders and are not validated, so this is
+    /// advisory, for display and reporting only.  Untimestamped and pending
+    /// transfers are not counted.  Returns `None` if the account does not
+    /// exist, or if the timestamps are inconsistent enough to put the
+    /// balance below zero or above the maximum amount.
+    pub fn balance_before_time(&self, actor: &A, timestamp: u64) -> Option<M> {
+        let initial_balance = *self.initial_balances.get(actor)?;
+        let before: Vec<&Transfer<A, M>> = self
+            .history_iter(actor)
+            .filter(|t| match t.timestamp {
+                Some(ts) => ts < timestamp,
+                None => false,
+            })
+            .collect();
+
+        let outgoing = wide_sum(before.iter().filter(|t| &t.from == actor).map(|t| t.amount))?;
+        let incoming = wide_sum(before.iter().filter(|t| &t.to == actor).map(|t| t.amount))?;
+
+        self.net_balance(actor, initial_balance, incoming, outgoing)
+            .ok()
+            .map(to_money)
+    }
+
+    /// Amounts, including fees, reserved by the actor's pending outgoing transfers
+    fn pending_debits<'a>(&'a self, actor: &'a A) -> impl Iterator<Item = M> + 'a {
+        self.pending_transfers
+            .values()
+            .filter(move |t| &t.from == actor)
+            .map(|t| {
+                t.amount
+                    .checked_add(t.fee)
+                    .expect("[ERROR] Pending transfer amount overflowed")
+            })
+    }
+
+    /// Amounts, including fees for the fee collector, due to the actor once
+    /// pending transfers are finalized
+    fn pending_credits<'a>(&'a self, actor: &'a A) -> impl Iterator<Item = M> + 'a {
+        let collects_fees = self.fee_collector.as_ref() == Some(actor);
+        self.pending_transfers.values().flat_map(move |t| {
+            let amount = Some(t.amount).filter(|_| &t.to == actor);
+            let fee = Some(t.fee).filter(|_| collects_fees);
+            amount.into_iter().chain(fee)
+        })
+    }
+
+    /// Returns the present balance of every account.
+    ///
+    /// Computed in a single pass over the history, which is cheaper than
+    /// calling `balance` for each account.  Out-of-range balances are
+    /// handled according to the arithmetic mode; under
+    /// `ArithmeticMode::Error` their accounts are left out.
+    pub fn balances(&self) -> BTreeMap<A, M> {
+        let mut incoming: BTreeMap<&A, u128> = BTreeMap::new();
+        let mut outgoing: BTreeMap<&A, u128> = BTreeMap::new();
+        // Each transfer is recorded in the history of both accounts, so
+        // count it from each account's own history, as either may have
+        // been checkpointed
+        for (actor, transfers) in self.hist.iter() {
+            for t in transfers.iter() {
+                if &t.from == actor {
+                    add_amount(&mut outgoing, actor, t.amount);
+                }
+                if &t.to == actor {
+                    add_amount(&mut incoming, actor, t.amount);
+                }
+            }
+        }
+        for t in self.pending_transfers.values() {
+            let debit = t.amount.checked_add(t.fee);
+            add_amount(
+                &mut outgoing,
+                &t.from,
+                debit.expect("[ERROR] Pending transfer amount overflowed"),
+            );
+        }
+        for (actor, adjustments) in self.adjustments.iter() {
+            for adjustment in adjustments.iter() {
+                add_amount(&mut incoming, actor, adjustment.credit());
+                add_amount(&mut outgoing, actor, adjustment.debit());
+            }
+        }
+
+        self.initial_balances
+            .iter()
+            .filter_map(|(actor, initial_balance)| {
+                let incoming = incoming.get(actor).copied().unwrap_or(0);
+                let outgoing = outgoing.get(actor).copied().unwrap_or(0);
+                let balance = self
+                    .net_balance(actor, *initial_balance, incoming, outgoing)
+                    .map(to_money);
+                let balance = self.resolve_balance(balance)?;
+                Some((actor.clone(), balance))
+            })
+            .collect()
+    }
+
+    /// Returns the `n` accounts with the largest balances, largest first.
+    ///
+    /// Accounts with equal balances are ordered by actor.
+    pub fn richest_accounts(&self, n: usize) -> Vec<(A, M)> {
+        let mut accounts: Vec<(A, M)> = self.balances().into_iter().collect();
+
+        accounts.sort_by(|(a1, b1), (a2, b2)| b2.cmp(b1).then_with(|| a1.cmp(a2)));
+        accounts.truncate(n);
+        accounts
+    }
+
+    /// Returns the number of distinct transfers in the ledger.
+    pub fn transfer_count(&self) -> usize {
+        self.transfer_count
+    }
+
+    /// Returns the sum of the amounts of all transfers in the ledger.
+    pub fn total_volume(&self) -> u128 {
+        self.total_volume
+    }
+
+    /// Strips settled dependencies from the transfers stored in history.
+    ///
+    /// Each dependency of a stored transfer is itself a transfer that carries
+    /// its own dependencies, so the stored copy grows recursively.  Once a
+    /// dependency is recorded in the history of the account it credited, the
+    /// nested dependencies it carries are redundant and are dropped, leaving
+    /// a single level of `deps` on each stored transfer.
+    ///
+    /// Validation is unaffected: a dependency is accepted if either its full
+    /// or its compacted form is present in the sender's history.
+    pub fn compact(&mut self) {
+        let compacted: BTreeMap<A, BTreeSet<Transfer<A, M>>> = self
+            .hist
+            .iter()
+            .map(|(actor, transfers)| {
+                let transfers = transfers
+                    .iter()
+                    .map(|t| {
+                        if t.deps.iter().all(|d| self.history_contains(&d.to, d)) {
+                            t.compacted()
+                        } else {
+                            t.clone()
+                        }
+                    })
+                    .collect();
+                (actor.clone(), transfers)
+            })
+            .collect();
+
+        self.hist = compacted;
+    }
+
+    /// Collapses an account's settled history into its opening balance,
+    /// returning the number of transfers dropped from its history.
+    ///
+    /// The account's initial balance is adjusted by the net amount of the
+    /// collapsed transfers, leaving its balance unchanged.  Incoming
+    /// transfers that are still live dependencies are kept: those not yet
+    /// spent by one of the account's outgoing transfers, which its next
+    /// outgoing transfer will carry, and those carried by its pending
+    /// transfers.  Everything else in the account's history is collapsed,
+    /// while counterparties keep their copies of the collapsed transfers.
+    ///
+    /// Preconditions:
+    ///
+    /// - Every outgoing transfer of the account must have been applied, as
+    ///   one still in flight would carry dependencies that are collapsed.
+    /// - Checkpoints change initial balances, so all replicas must
+    ///   checkpoint the account at the same point before they are merged
+    ///   or diffed, and ledger exports no longer add up for the account.
+    /// - Counters only cover transfers still in some history, so they drop
+    ///   once both accounts of a transfer have been checkpointed.
+    ///
+    /// Panics if the account's balance is negative or overflows.  Liability
+    /// accounts are left as is, returning 0, as an opening balance can't be
+    /// negative.
+    pub fn checkpoint_account(&mut self, actor: &A) -> usize {
+        self.collapse_history(actor, 0)
+    }
+
+    /// Keeps at most `per_account` transfers in each account's history
+    /// when pruning with `prune_to_retention`, e.g. to bound storage or to
+    /// limit how much history a replica retains.  Unlimited by default.
+    ///
+    /// This is local policy: it doesn't affect validation, but pruned
+    /// replicas must prune alike before they are merged or diffed, see
+    /// `checkpoint_account`.
+    pub fn set_retention(&mut self, per_account: usize) {
+        self.retention = Some(per_account);
+    }
+
+    /// Returns the number of transfers kept per account when pruning, if limited.
+    pub fn retention(&self) -> Option<usize> {
+        self.retention
+    }
+
+    /// Collapses all but the most recent transfers of each account, as set
+    /// by `set_retention`, into its opening balance, returning the number of
+    /// transfers dropped from histories.  Does nothing unless a retention
+    /// is set.
+    ///
+    /// Like `checkpoint_account`, this leaves balances unchanged and has the
+    /// same preconditions.  Transfers are ranked by `transfers_causal_order`.
+    ///
+    /// Pruning too aggressively could break proof of funds: the incoming
+    /// transfers an account has yet to spend are the dependencies its next
+    /// outgoing transfer carries to prove it holds the money, and validators
+    /// look them up in its history.  So, whatever the retention, such
+    /// transfers, and those carried by the account's pending transfers,
+    /// are kept, and an account may retain more transfers than the limit.
+    pub fn prune_to_retention(&mut self) -> usize {
+        let retain = match self.retention {
+            Some(retain) => retain,
+            None => return 0,
+        };
+        let accounts: Vec<A> = self.hist.keys().cloned().collect();
+        accounts
+            .iter()
+            .map(|actor| self.collapse_history(actor, retain))
+            .sum()
+    }
+
+    /// Collapses an account's settled history into its opening balance,
+    /// except for its `retain` most recent transfers and the transfers that
+    /// are still live dependencies, returning the number of transfers
+    /// dropped from its history.
+    fn collapse_history(&mut self, actor: &A, retain: usize) -> usize {
+        if self.liabilities.contains(actor) {
+            return 0;
+        }
+        let next_seq = self.next_seq(actor);
+        let mut live: BTreeSet<Transfer<A, M>> = self
+            .pending_deps(actor)
+            .iter()
+            .map(Transfer::without_deps)
+            .collect();
+        live.extend(
+            self.pending_transfers
+                .values()
+                .filter(|t| &t.from == actor)
+                .flat_map(|t| t.deps.iter().map(Transfer::without_deps)),
+        );
+
+        let recent: BTreeSet<Transfer<A, M>> = self
+            .transfers_causal_order(actor)
+            .into_iter()
+            .rev()
+            .take(retain)
+            .collect();
+
+        let (kept, collapsed): (BTreeSet<_>, BTreeSet<_>) = self
+            .history_iter(actor)
+            .cloned()
+            .partition(|t| recent.contains(t) || live.contains(&t.without_deps()));
+        if collapsed.is_empty() {
+            return 0;
+        }
+
+        let incoming = wide_sum(
+            collapsed
+                .iter()
+                .filter(|t| &t.to == actor)
+                .map(|t| t.amount),
+        );
+        let outgoing = wide_sum(
+            collapsed
+                .iter()
+                .filter(|t| &t.from == actor)
+                .map(|t| t.amount),
+        );
+        let opening = incoming
+            .zip(outgoing)
+            .and_then(|(incoming, outgoing)| {
+                self.net_balance(actor, self.initial_balance(actor), incoming, outgoing)
+                    .ok()
+            })
+            .map(to_money)
+            .unwrap_or_else(|| panic!("[ERROR] Balance of {:?} is out of range", actor));
+
+        info!(
+            account:? = actor,
+            collapsed = collapsed.len();
+            "[BANK] checkpointing account"
+        );
+        self.initial_balances.insert(actor.clone(), opening);
+        self.hist.insert(actor.clone(), kept);
+        self.checkpoints.insert(actor.clone(), next_seq);
+        self.recount_transfers();
+
+        // Transfers the counterparty still records remain indexed
+        let dropped: Vec<TransferId> = self
+            .transfer_index
+            .iter()
+            .filter(|(_, t)| !self.is_recorded(t))
+            .map(|(id, _)| *id)
+            .collect();
+        for id in dropped {
+            self.transfer_index.remove(&id);
+        }
+        collapsed.len()
+    }
+
+    /// Records a transfer in the history of both accounts and updates the
+    /// dependencies of our next outgoing transfer.
+    fn record_transfer(&mut self, transfer: Transfer<A, M>) {
+        self.transfer_count += 1;
+        self.total_volume = self.total_volume.saturating_add(transfer.amount.as_u128());
+        self.transfer_index.insert(transfer.id(), transfer.clone());
+
+        // Update the history for the outgoing account
+        self.hist
+            .entry(transfer.from.clone())
+            .or_default()
+            .insert(transfer.clone());
+
+        // Update the history for the incoming account
+        self.hist
+            .entry(transfer.to.clone())
+            .or_default()
+            .insert(transfer.clone());
+
+        // Add this transfer to self.deps only if we are recipient.
+        if transfer.to == self.id {
+            self.deps.insert(transfer.clone());
+
+            // Opportunistically clear deps that our outgoing transfers already
+            // reference, e.g. in their compacted form when they were sent by
+            // another replica of ours, so they don't take up room under max_deps.
+            let spent = self.spent_deps(&self.id);
+            self.deps.retain(|dep| !spent.contains(&dep.without_deps()));
+        }
+
+        // remove transfer.deps from self.deps only if we are sender.
+        if transfer.from == self.id {
+            // In the paper, deps are cleared after the broadcast completes in
+            // self.transfer.
+            // Here we break up the initiation of the transfer from the completion.
+            // We move the clearing of the deps here since this is where we now know
+            // the transfer was successfully validated and applied by the network.
+            for prior_transfer in Self::ordered(self.ordering_seed(), transfer.deps.iter()) {
+                // for each dependency listed in the transfer
+                // we remove it from the set of dependencies for a transfer
+                self.deps.remove(prior_transfer);
+            }
+        }
+    }
+
+    /// Records a transfer along with its fee payment leg, if any.
+    fn record_transfer_with_fee(&mut self, transfer: Transfer<A, M>) {
+        let fee_leg = self.fee_leg(&transfer);
+        self.record_transfer(transfer);
+        if let Some(fee_leg) = fee_leg {
+            self.record_transfer(fee_leg);
+        }
+    }
+
+    /// Returns the fee payment leg of a transfer, if it carries a fee.
+    ///
+    /// The leg is recorded as a transfer of its own from the sender to the
+    /// fee collector, sharing the sequence number of the transfer that paid
+    /// it.  The fee is paid out of the same funds, so the leg carries the
+    /// same proof of funds: the transfer's deps, which credit the sender and
+    /// are stored in its history like those of any other transfer.
+    fn fee_leg(&self, transfer: &Transfer<A, M>) -> Option<Transfer<A, M>> {
+        match &self.fee_collector {
+            Some(collector) if transfer.fee > M::zero() => {
+                let mut leg =
+                    TransferBuilder::new(transfer.from.clone(), collector.clone(), transfer.fee)
+                        .currency(transfer.currency)
+                        .seq(transfer.seq)
+                        .deps(transfer.deps.iter().cloned());
+                if let Some(timestamp) = transfer.timestamp {
+                    leg = leg.timestamp(timestamp);
+                }
+                Some(leg.build())
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the full transitive closure of a transfer's dependencies.
+    ///
+    /// Each dependency is resolved against the history, so that dependencies
+    /// stripped by `compact` are still followed.  Dependencies missing from
+    /// the history are included as given.  The walk visits each transfer at
+    /// most once, so it terminates even on corrupt, cyclic data.
+    pub fn transfer_deps_closure(&self, transfer: &Transfer<A, M>) -> BTreeSet<Transfer<A, M>> {
+        let mut closure = BTreeSet::new();
+        let mut to_visit: Vec<&Transfer<A, M>> = transfer.deps.iter().collect();
+
+        while let Some(dep) = to_visit.pop() {
+            let dep = self.resolve_transfer(dep).unwrap_or(dep);
+            if closure.insert(dep.clone()) {
+                to_visit.extend(dep.deps.iter());
+            }
+        }
+
+        closure
+    }
+
+    /// Returns an actor's history sorted so that every transfer comes after
+    /// the transfers it depends on.
+    ///
+    /// This is a best-effort causal order, not a wall-clock order: transfers
+    /// are sorted by the length of their longest dependency chain, and
+    /// independent transfers at the same depth fall back to `Transfer`'s `Ord`.
+    pub fn transfers_causal_order(&self, actor: &A) -> Vec<Transfer<A, M>> {
+        let mut depths = BTreeMap::new();
+        let mut transfers: Vec<(usize, Transfer<A, M>)> = self
+            .history(actor)
+            .into_iter()
+            .map(|t| (self.causal_depth(&t, &mut depths), t))
+            .collect();
+
+        transfers.sort();
+        transfers.into_iter().map(|(_, t)| t).collect()
+    }
+
+    /// Returns the length of the longest dependency chain among an actor's
+    /// transfers, or 0 if none has dependencies, e.g. to gauge how deep the
+    /// nested deps of its transfers have grown.
+    ///
+    /// Dependencies are followed to their stored copies, which corrupt data
+    /// could make cyclic; cycles are cut rather than followed forever.
+    pub fn history_depth(&self, actor: &A) -> usize {
+        let mut depths = BTreeMap::new();
+        self.history_iter(actor)
+            .map(|t| self.causal_depth(t, &mut depths))
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Returns an actor's balance right after each transfer in its history,
+    /// in the order of `transfers_causal_order`, e.g. to chart the balance
+    /// over time.
+    ///
+    /// Transfers that don't depend on one another are ordered arbitrarily,
+    /// but the same way on every call and every replica.  Since a transfer
+    /// need not depend on all the transfers that funded it, the balance may
+    /// seemingly dip below zero along the way, in which case it is reported
+    /// as zero.  Pending transfers and balance adjustments are not in
+    /// history and are left out.  The series of an unknown account is empty.
+    pub fn balance_series(&self, actor: &A) -> Vec<(TransferId, M)> {
+        let mut balance = match self.initial_balances.get(actor) {
+            Some(balance) => *balance,
+            None => return Vec::new(),
+        };
+        self.transfers_causal_order(actor)
+            .into_iter()
+            .map(|t| {
+                if &t.to == actor {
+                    balance = balance.checked_add(t.amount).unwrap_or_else(M::max_value);
+                }
+                if &t.from == actor {
+                    balance = balance.checked_sub(t.amount).unwrap_or_else(M::zero);
+                }
+                (t.id(), balance)
+            })
+            .collect()
+    }
+
+    /// Length of the longest chain of dependencies leading up to a transfer.
+    fn causal_depth(
+        &self,
+        transfer: &Transfer<A, M>,
+        depths: &mut BTreeMap<Transfer<A, M>, usize>,
+    ) -> usize {
+        if let Some(depth) = depths.get(transfer) {
+            return *depth;
+        }
+
+        // Guards against cycles in corrupt data
+        depths.insert(transfer.clone(), 0);
+
+        let depth = transfer
+            .deps
+            .iter()
+            .map(|d| self.causal_depth(self.resolve_transfer(d).unwrap_or(d), depths) + 1)
+            .max()
+            .unwrap_or(0);
+        depths.insert(transfer.clone(), depth);
+        depth
+    }
+
+    /// Finds the stored copy of a transfer in the history of its recipient,
+    /// which may have been compacted or may be the full form of a compacted
+    /// dependency.
+    fn resolve_transfer(&self, transfer: &Transfer<A, M>) -> Option<&Transfer<A, M>> {
+        self.resolve_in_history(&transfer.to, transfer)
+    }
+
+    /// Finds the stored copy of a transfer in an actor's history, as
+    /// `resolve_transfer` does in the history of its recipient.
+    fn resolve_in_history(&self, actor: &A, transfer: &Transfer<A, M>) -> Option<&Transfer<A, M>> {
+        let h = self.hist.get(actor)?;
+        h.get(transfer)
+            .or_else(|| h.get(&transfer.compacted()))
+            .or_else(|| {
+                // Compacted transfers keep their deps without nested deps
+                h.iter()
+                    .filter(|_| transfer.deps.is_empty())
+                    .find(|t| &t.without_deps() == transfer)
+            })
+    }
+
+    /// Returns true if the transfer is recorded in the actor's history, either
+    /// in full or in the form left behind by `compact`.
+    fn history_contains(&self, actor: &A, transfer: &Transfer<A, M>) -> bool {
+        match self.hist.get(actor) {
+            Some(h) => h.contains(transfer) || h.contains(&transfer.compacted()),
+            None => false,
+        }
+    }
+
+    /// Returns whether a transfer is in the history of its sender or
+    /// recipient, either of which may have been checkpointed since.
+    fn is_recorded(&self, transfer: &Transfer<A, M>) -> bool {
+        self.history_contains(&transfer.from, transfer)
+            || self.history_contains(&transfer.to, transfer)
+    }
+
+    /// Returns whether an op was already applied, so that applying it again
+    /// is skipped: a transfer already recorded, possibly before being
+    /// compacted, or a pending transfer already initiated, or even finalized.
+    fn is_duplicate(&self, op: &Op<A, M>) -> bool {
+        match op {
+            Op::Transfer(transfer) | Op::TransferFrom { transfer, .. } => {
+                self.is_recorded(transfer)
+            }
+            Op::InitiateTransfer(transfer) => {
+                self.pending_transfers.contains_key(&transfer.id()) || self.is_recorded(transfer)
+            }
+            // The transfers are applied all or none, so any of them will do
+            Op::MultiTransfer { transfers } => transfers.iter().any(|t| self.is_recorded(t)),
+            _ => false,
+        }
+    }
+
+    /// Returns whether a transfer is final from this bank's view: recorded
+    /// in the histories of both its sender and recipient, e.g. for a wallet
+    /// to poll after broadcasting it.
+    ///
+    /// A transfer merely initiated, and still pending finalization, is not
+    /// confirmed, nor is one that was broadcast but not yet applied.  The
+    /// incoming transfers in `deps` awaiting our next outgoing transfer are
+    /// already in history, so they are confirmed.  A checkpointed account's
+    /// copy may have been collapsed, in which case its counterparty's copy
+    /// suffices.
+    pub fn is_confirmed(&self, transfer: &Transfer<A, M>) -> bool {
+        self.is_recorded(transfer)
+            && [&transfer.from, &transfer.to].iter().all(|account| {
+                self.history_contains(account, transfer) || self.checkpoints.contains_key(account)
+            })
+    }
+
+    /// Looks up a transfer recorded in history by its id, e.g. to verify a
+    /// receipt or resolve a dependency.
+    ///
+    /// Transfers are indexed under the id they were applied with, even once
+    /// `compact` strips their nested dependencies, and so are those of a
+    /// bank restored from a snapshot.  Pending transfers are only indexed
+    /// once finalized.
+    pub fn find_transfer(&self, id: &TransferId) -> Option<&Transfer<A, M>> {
+        self.transfer_index.get(id)
+    }
+
+    /// Returns the ids of every transfer in history, as looked up by
+    /// `find_transfer`, for a peer to pass to `missing_from`.
+    pub fn known_transfers(&self) -> BTreeSet<TransferId> {
+        self.transfer_index.keys().copied().collect()
+    }
+
+    /// Returns the transfers in our history that a peer lacks, given the
+    /// ids of the transfers it knows of, for the peer to `absorb`.
+    ///
+    /// Fee payment legs are left out, as absorbing the transfer that paid
+    /// the fee records its leg.  Transfers are ordered so that each comes
+    /// after its dependencies.
+    pub fn missing_from(&self, other_known: &BTreeSet<TransferId>) -> Vec<Transfer<A, M>> {
+        let fee_legs: BTreeSet<TransferId> = self
+            .transfer_index
+            .values()
+            .filter_map(|t| self.fee_leg(t))
+            .map(|leg| leg.id())
+            .collect();
+
+        let mut depths = BTreeMap::new();
+        let mut missing: Vec<(usize, &Transfer<A, M>)> = self
+            .transfer_index
+            .iter()
+            .filter(|(id, _)| !other_known.contains(id) && !fee_legs.contains(id))
+            .map(|(_, t)| (self.causal_depth(t, &mut depths), t))
+            .collect();
+
+        missing.sort();
+        missing.into_iter().map(|(_, t)| t.clone()).collect()
+    }
+
+    /// Returns the transfers received by the provided actor
+    pub fn incoming_transfers(&self, actor: &A) -> BTreeSet<Transfer<A, M>> {
+        self.history_iter(actor)
+            .filter(|t| &t.to == actor)
+            .cloned()
+            .collect()
+    }
+
+    /// Returns the transfers sent by the provided actor
+    pub fn outgoing_transfers(&self, actor: &A) -> BTreeSet<Transfer<A, M>> {
+        self.history_iter(actor)
+            .filter(|t| &t.from == actor)
+            .cloned()
+            .collect()
+    }
+
+    /// Returns how many transfers an account sent and received, and the
+    /// amounts they moved, e.g. to spot unusually active accounts.
+    ///
+    /// Computed in a single pass over the account's history, so pending
+    /// transfers are left out.
+    pub fn account_activity(&self, actor: &A) -> AccountActivity {
+        let mut activity = AccountActivity::default();
+        for t in self.history_iter(actor) {
+            if &t.to == actor {
+                activity.incoming_count += 1;
+                activity.incoming_volume =
+                    activity.incoming_volume.saturating_add(t.amount.as_u128());
+            }
+            if &t.from == actor {
+                activity.outgoing_count += 1;
+                activity.outgoing_volume =
+                    activity.outgoing_volume.saturating_add(t.amount.as_u128());
+            }
+        }
+        activity
+    }
+
+    /// Returns the net amount that has flowed from `a` to `b`: the sum of
+    /// transfers from `a` to `b` minus the sum of transfers from `b` to `a`.
+    ///
+    /// A positive value means net flow from `a` to `b`.
+    pub fn net_flow(&self, a: &A, b: &A) -> i128 {
+        if a == b {
+            return 0;
+        }
+
+        self.smaller_history(a, b).fold(0, |flow, t| {
+            let amount = t.amount.as_u128() as i128;
+            if &t.from == a && &t.to == b {
+                flow + amount
+            } else if &t.from == b && &t.to == a {
+                flow - amount
+            } else {
+                flow
+            }
+        })
+    }
+
+    /// Returns the transfers that moved money between `a` and `b`, in
+    /// either direction, in transfer order.
+    ///
+    /// This itemizes what `net_flow` sums up, e.g. to reconcile the two
+    /// counterparties' records.
+    pub fn transfers_between(&self, a: &A, b: &A) -> Vec<Transfer<A, M>> {
+        self.smaller_history(a, b)
+            .filter(|t| (&t.from == a && &t.to == b) || (&t.from == b && &t.to == a))
+            .cloned()
+            .collect()
+    }
+
+    /// Iterates over the shorter of the histories of `a` and `b`, which
+    /// holds every transfer between the two
+    fn smaller_history<'a>(&'a self, a: &A, b: &A) -> impl Iterator<Item = &'a Transfer<A, M>> {
+        let hist_a = self.hist.get(a).map_or(0, BTreeSet::len);
+        let hist_b = self.hist.get(b).map_or(0, BTreeSet::len);
+        let smaller = if hist_a <= hist_b { a } else { b };
+        self.hist.get(smaller).into_iter().flatten()
+    }
+
+    /// Iterates over the history of transfers for provided actor, without cloning it
+    pub(crate) fn history_iter<'a>(
+        &'a self,
+        actor: &A,
+    ) -> impl Iterator<Item = &'a Transfer<A, M>> {
+        self.hist.get(actor).into_iter().flatten()
+    }
+
+    /// Returns complete history of transfers for provided actor
+    fn history(&self, actor: &A) -> BTreeSet<Transfer<A, M>> {
+        // PERF: can we make this faster, without need to clone?
+        self.hist.get(&actor).cloned().unwrap_or_default()
+    }
+
+    /// Generates a new Transfer operation (but does not apply it)
+    ///
+    /// Returns the reason the network would reject the transfer if either
+    /// account does not exist or `from` has insufficient funds.
+    pub fn transfer(&self, from: A, to: A, amount: M) -> Result<Op<A, M>, ValidationError<A, M>> {
+        self.transfer_with_fee(from, to, amount, M::zero())
+    }
+
+    /// Generates a transfer refunding `original` to its sender (but does not
+    /// apply it), since AT2 transfers are final and can only be undone by
+    /// a compensating transfer.
+    ///
+    /// Only the recipient can spend the refunded money, so this returns
+    /// `None` unless `original` was sent to our actor, or if we lack the
+    /// funds to refund it.  Any fee the original paid is not refunded.
+    pub fn reverse(&self, original: &Transfer<A, M>) -> Option<Op<A, M>> {
+        if original.to != self.id {
+            return None;
+        }
+        self.transfer(original.to.clone(), original.from.clone(), original.amount)
+            .ok()
+    }
+
+    /// Generates a new Transfer operation paying `fee` to the fee collector
+    /// on top of `amount` (but does not apply it)
+    pub fn transfer_with_fee(
+        &self,
+        from: A,
+        to: A,
+        amount: M,
+        fee: M,
+    ) -> Result<Op<A, M>, ValidationError<A, M>> {
+        self.new_transfer(TransferBuilder::new(from, to, amount).fee(fee))
+            .map(Op::Transfer)
+    }
+
+    /// Generates a new Transfer operation stamped with a unix time in
+    /// milliseconds (but does not apply it)
+    ///
+    /// The timestamp is only used for reporting, see `balance_before_time`.
+    pub fn transfer_at(
+        &self,
+        from: A,
+        to: A,
+        amount: M,
+        timestamp: u64,
+    ) -> Result<Op<A, M>, ValidationError<A, M>> {
+        self.new_transfer(TransferBuilder::new(from, to, amount).timestamp(timestamp))
+            .map(Op::Transfer)
+    }
+
+    /// Generates a new Transfer operation that the network rejects until it
+    /// has applied at least `not_before` ops (but does not apply it)
+    ///
+    /// BRB provides no wall clock, so the lock is expressed in terms of the
+    /// number of ops applied by the bank.  See `applied_ops` for how far
+    /// replicas agree on it.
+    pub fn transfer_locked(
+        &self,
+        from: A,
+        to: A,
+        amount: M,
+        not_before: u64,
+    ) -> Result<Op<A, M>, ValidationError<A, M>> {
+        self.new_transfer(TransferBuilder::new(from, to, amount).not_before(not_before))
+            .map(Op::Transfer)
+    }
+
+    /// Generates a new Transfer operation carrying `deps` as its proof of
+    /// funds, instead of all our unspent incoming transfers (but does not
+    /// apply it), e.g. the ones picked by `minimal_deps_for`.
+    ///
+    /// Besides the checks the network runs, `deps` must cover the amount,
+    /// fees included, together with the part of our balance that no longer
+    /// needs a proof: what is left once our unspent incoming transfers are
+    /// taken out.
+    pub fn transfer_with_deps(
+        &self,
+        from: A,
+        to: A,
+        amount: M,
+        deps: BTreeSet<Transfer<A, M>>,
+    ) -> Result<Op<A, M>, ValidationError<A, M>> {
+        let mut transfer = self.outgoing(TransferBuilder::new(from, to, amount));
+        transfer.deps = deps;
+        self.check_transfer(&transfer)?;
+
+        let total = transfer.amount.checked_add(transfer.fee);
+        let unspent = if transfer.from == self.id {
+            wide_sum(self.deps.iter().map(|t| t.amount)).unwrap_or(u128::MAX)
+        } else {
+            0
+        };
+        let proven = wide_sum(transfer.deps.iter().map(|t| t.amount)).unwrap_or(u128::MAX);
+        let covered = self
+            .balance(&transfer.from)
+            .as_u128()
+            .saturating_sub(unspent)
+            .saturating_add(proven);
+        match total {
+            Some(total)
+                if covered >= total.as_u128() || self.liability_covers(&transfer.from, total) =>
+            {
+                Ok(Op::Transfer(transfer))
+            }
+            total => Err(ValidationError::DepsDoNotCoverAmount {
+                covered: M::from_u128(covered).unwrap_or_else(M::max_value),
+                transfer_amount: total.unwrap_or_else(M::max_value),
+            }),
+        }
+    }
+
+    /// Generates a MultiTransfer operation splitting `total` across
+    /// recipients in proportion to their weights (but does not apply it),
+    /// e.g. to pay out a payroll by percentage.
+    ///
+    /// Each share is rounded down and what rounding leaves over goes to the
+    /// last recipient, so the shares add up to exactly `total`.  Recipients
+    /// whose share is zero are left out.  Only the first transfer carries
+    /// our deps, the others are proven by the same funds.
+    ///
+    /// Returns `None` if no recipient has a non-zero weight, if `total`
+    /// exceeds our balance, or if any of the transfers would fail.
+    pub fn transfer_split(&self, from: A, total: M, weights: Vec<(A, u32)>) -> Option<Op<A, M>> {
+        let weight_sum: u128 = weights.iter().map(|(_, weight)| u128::from(*weight)).sum();
+        if weight_sum == 0 {
+            return None;
+        }
+        if total > self.balance(&from) && !self.liability_covers(&from, total) {
+            return None;
+        }
+
+        // Computes total * weight / weight_sum, rounded down, without overflowing
+        let (quotient, remainder) = (total.as_u128() / weight_sum, total.as_u128() % weight_sum);
+        let mut shares: Vec<(A, u128)> = weights
+            .into_iter()
+            .map(|(to, weight)| {
+                let weight = u128::from(weight);
+                (to, quotient * weight + remainder * weight / weight_sum)
+            })
+            .collect();
+        let apportioned: u128 = shares.iter().map(|(_, share)| share).sum();
+        if let Some((_, last)) = shares.last_mut() {
+            *last += total.as_u128() - apportioned;
+        }
+
+        let mut transfers: Vec<Transfer<A, M>> = Vec::new();
+        for (to, share) in shares.into_iter().filter(|(_, share)| *share > 0) {
+            // A share never exceeds the total, so it fits
+            let amount = M::from_u128(share).unwrap_or(total);
+            let transfer = match transfers.last() {
+                Some(previous) => TransferBuilder::new(from.clone(), to, amount)
+                    .currency(previous.currency)
+                    .seq(previous.seq + 1)
+                    .build(),
+                None => self.outgoing(TransferBuilder::new(from.clone(), to, amount)),
+            };
+            transfers.push(transfer);
+        }
+
+        let mut legs = PriorLegs::new();
+        for transfer in transfers.iter() {
+            self.check_transfer_after(transfer, &legs).ok()?;
+            self.note_leg(&mut legs, transfer);
+        }
+        Some(Op::MultiTransfer { transfers })
+    }
+
+    /// Generates an op initiating a cancellable transfer (but does not apply it)
+    ///
+    /// Once applied, `amount` is debited from `from` straight away, but `to`
+    /// is only credited when `from` finalizes the transfer.  Until then
+    /// `from` may cancel it instead, getting the money back.
+    pub fn initiate_transfer(
+        &self,
+        from: A,
+        to: A,
+        amount: M,
+    ) -> Result<Op<A, M>, ValidationError<A, M>> {
+        self.new_transfer(TransferBuilder::new(from, to, amount))
+            .map(Op::InitiateTransfer)
+    }
+
+    /// Generates an op crediting the recipient of one of our pending transfers
+    pub fn finalize_transfer(&self, id: TransferId) -> Result<Op<A, M>, ValidationError<A, M>> {
+        self.check_pending_sender(&self.id, &id)?;
+        Ok(Op::FinalizeTransfer { id })
+    }
+
+    /// Generates an op cancelling one of our pending transfers
+    pub fn cancel_transfer(&self, id: TransferId) -> Result<Op<A, M>, ValidationError<A, M>> {
+        self.check_pending_sender(&self.id, &id)?;
+        Ok(Op::CancelTransfer { id })
+    }
+
+    /// Generates an op authorizing `spender` to transfer up to `amount`,
+    /// fees included, out of `owner`'s account (but does not apply it)
+    ///
+    /// Only the account owner may approve spenders.  The allowance replaces
+    /// any previous one, so approving 0 revokes it.
+    pub fn approve(&self, owner: A, spender: A, amount: M) -> Op<A, M> {
+        Op::Approve {
+            owner,
+            spender,
+            amount,
+        }
+    }
+
+    /// Returns the amount `spender` may still transfer out of `owner`'s account.
+    pub fn allowance(&self, owner: &A, spender: &A) -> M {
+        self.allowances
+            .get(&(owner.clone(), spender.clone()))
+            .copied()
+            .unwrap_or_else(M::zero)
+    }
+
+    /// Generates an op transferring money out of `from`'s account on its
+    /// behalf, within the allowance granted to our actor (but does not
+    /// apply it)
+    ///
+    /// The transfer's proof of funds is made of the incoming transfers of
+    /// `from` that none of its outgoing transfers reference yet.
+    pub fn transfer_from(
+        &self,
+        from: A,
+        to: A,
+        amount: M,
+    ) -> Result<Op<A, M>, ValidationError<A, M>> {
+        let transfer = TransferBuilder::new(from.clone(), to, amount)
+            .currency(self.account_currency(&from).unwrap_or(DEFAULT_CURRENCY))
+            .seq(self.next_seq(&from))
+            .deps(self.pending_deps(&from).into_iter().take(self.max_deps))
+            .build();
+        self.validate_transfer_from(&self.id, &self.id, &transfer)?;
+
+        Ok(Op::TransferFrom {
+            spender: self.id.clone(),
+            transfer,
+        })
+    }
+
+    /// Validates a transfer initiated by `source` on behalf of the sender,
+    /// as `spender`
+    fn validate_transfer_from(
+        &self,
+        source: &A,
+        spender: &A,
+        transfer: &Transfer<A, M>,
+    ) -> Result<(), ValidationError<A, M>> {
+        if source != spender {
+            return Err(ValidationError::NotInitiatedByAccountOwner {
+                initiator: source.clone(),
+                owner: spender.clone(),
+            });
+        }
+        self.check_not_duplicate(transfer)?;
+
+        let allowance = self.allowance(&transfer.from, spender);
+        match transfer.amount.checked_add(transfer.fee) {
+            Some(total) if total <= allowance => (),
+            total => {
+                return Err(ValidationError::AllowanceExceeded {
+                    allowance,
+                    transfer_amount: total.unwrap_or_else(M::max_value),
+                })
+            }
+        }
+
+        // The spender stands in for the account owner
+        self.validate_transfer(&transfer.from, transfer)
+    }
+
+    /// Returns a transfer that has been initiated but not yet finalized or cancelled.
+    pub fn pending_transfer(&self, id: &TransferId) -> Option<&Transfer<A, M>> {
+        self.pending_transfers.get(id)
+    }
+
+    /// Checks that the transfer is pending and that the actor sent it
+    fn check_pending_sender(
+        &self,
+        actor: &A,
+        id: &TransferId,
+    ) -> Result<&Transfer<A, M>, ValidationError<A, M>> {
+        match self.pending_transfers.get(id) {
+            None => Err(ValidationError::PendingTransferDoesNotExist { id: *id }),
+            Some(transfer) if &transfer.from != actor => {
+                Err(ValidationError::NotInitiatedByAccountOwner {
+                    initiator: actor.clone(),
+                    owner: transfer.from.clone(),
+                })
+            }
+            Some(transfer) => Ok(transfer),
+        }
+    }
+
+    /// Returns the sequence number of the actor's next outgoing transfer.
+    ///
+    /// Sequence numbers give an actor's transfers a human-meaningful order,
+    /// e.g. "transfer #5 from this account".  They complement, and don't
+    /// replace, the causal order given by `deps`.  Pending transfers count
+    /// as sent.
+    pub fn next_seq(&self, actor: &A) -> u64 {
+        // PERF: this scans the actor's history
+        self.history_iter(actor)
+            .chain(self.pending_transfers.values())
+            .filter(|t| &t.from == actor)
+            .map(|t| t.seq + 1)
+            .chain(self.checkpoints.get(actor).copied())
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Returns the number of ops applied by this bank.
+    ///
+    /// The count is kept by each replica, so replicas agree on it once they
+    /// have applied the same ops, in whichever order they were delivered.
+    /// Transfers a lagging replica `absorb`s from a peer count as applied,
+    /// as they did on the peer, and `merge` takes the larger of both counts,
+    /// so catching up with a peer brings the count in line with it.  Until
+    /// then, a replica may disagree with its peers on time locks and rate
+    /// limits, which are measured against this count.
+    pub fn applied_ops(&self) -> u64 {
+        self.applied_ops
+    }
+
+    /// Checks whether a transfer from our account would be accepted by the
+    /// network, without generating the op.
+    ///
+    /// Runs the same checks as `validate`, using the dependencies our next
+    /// outgoing transfer would carry.
+    pub fn can_transfer(&self, from: &A, to: &A, amount: M) -> Result<(), ValidationError<A, M>> {
+        let transfer = self.outgoing(TransferBuilder::new(from.clone(), to.clone(), amount));
+        self.validate_transfer(&self.id, &transfer)
+    }
+
+    /// Prepares a transfer generated by this bank, then checks that the network would accept it, ignoring
+    /// any time lock.
+    fn new_transfer(
+        &self,
+        transfer: TransferBuilder<A, M>,
+    ) -> Result<Transfer<A, M>, ValidationError<A, M>> {
+        let transfer = self.outgoing(transfer);
+
+        match self.check_transfer(&transfer) {
+            Ok(()) => Ok(transfer),
+            Err(ValidationError::InsufficientFunds {
+                balance,
+                transfer_amount,
+            }) => {
+                warn!(
+                    from:? = transfer.from,
+                    to:? = transfer.to,
+                    amount:% = transfer.amount,
+                    fee:% = transfer.fee,
+                    balance:% = balance;
+                    "[BANK] not enough money to transfer"
+                );
+                Err(ValidationError::InsufficientFunds {
+                    balance,
+                    transfer_amount,
+                })
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Returns the fewest of our unspent incoming transfers that together
+    /// cover `amount`, fees included, as the dependencies of a transfer
+    /// smaller than one carrying all of them.
+    ///
+    /// Transfers are picked largest first.  If they can't cover the amount,
+    /// e.g. because part of our balance is our initial balance, all of them
+    /// are returned.  Validation only requires dependencies to be in our
+    /// history and our balance to cover the transfer, so any subset is
+    /// accepted.  The trade-off is that the transfers left out remain
+    /// dependencies of our next transfers until one carries them, instead
+    /// of being settled right away.
+    pub fn minimal_deps_for(&self, amount: M) -> BTreeSet<Transfer<A, M>> {
+        let mut incoming: Vec<&Transfer<A, M>> = self.deps.iter().collect();
+        incoming.sort_by_key(|t| core::cmp::Reverse(t.amount));
+
+        let mut covered = M::zero();
+        let mut deps = BTreeSet::new();
+        for transfer in incoming {
+            if covered >= amount {
+                break;
+            }
+            covered = covered
+                .checked_add(transfer.amount)
+                .unwrap_or_else(M::max_value);
+            deps.insert(transfer.clone());
+        }
+        deps
+    }
+
+    /// Returns the serialized size of our next outgoing transfer of
+    /// `amount`, carrying our current dependencies, e.g. to decide whether
+    /// to `compact` them before broadcasting.
+    ///
+    /// The transfer is measured as if sent to ourselves, with no fee, so
+    /// the estimate is off by the difference in size of the recipient's
+    /// encoding, if actors vary in size.
+    pub fn next_transfer_size_estimate(&self, amount: M) -> usize {
+        self.outgoing(TransferBuilder::new(
+            self.id.clone(),
+            self.id.clone(),
+            amount,
+        ))
+        .serialized_size()
+    }
+
+    /// Denominates a transfer in the sender's currency and attaches the
+    /// dependencies of our next outgoing transfer to it.
+    fn outgoing(&self, transfer: TransferBuilder<A, M>) -> Transfer<A, M> {
+        let from = &transfer.transfer.from;
+        let currency = self.account_currency(from).unwrap_or(DEFAULT_CURRENCY);
+        let seq = self.next_seq(from);
+        transfer
+            .currency(currency)
+            .seq(seq)
+            .deps(self.deps.iter().take(self.max_deps).cloned())
+            .build()
+    }
+
+    /// Validates a transfer, or the initiation of a pending transfer, from `source`
+    fn validate_transfer(
+        &self,
+        source: &A,
+        transfer: &Transfer<A, M>,
+    ) -> Result<(), ValidationError<A, M>> {
+        self.validate_transfer_after(source, transfer, &PriorLegs::new())
+    }
+
+    /// Like `validate_transfer`, once the given earlier transfers of a
+    /// MultiTransfer are applied
+    fn validate_transfer_after(
+        &self,
+        source: &A,
+        transfer: &Transfer<A, M>,
+        legs: &PriorLegs<A>,
+    ) -> Result<(), ValidationError<A, M>> {
+        if source != &transfer.from {
+            return Err(ValidationError::NotInitiatedByAccountOwner {
+                initiator: source.clone(),
+                owner: transfer.from.clone(),
+            });
+        }
+        let id = transfer.id();
+        if legs.ids.contains(&id) {
+            return Err(ValidationError::DuplicateTransfer { id });
+        }
+        self.check_not_duplicate(transfer)?;
+        self.check_transfer_after(transfer, legs)?;
+
+        if transfer.not_before > Some(self.applied_ops) {
+            return Err(ValidationError::TimeLockNotElapsed {
+                not_before: transfer.not_before.unwrap_or_default(),
+                applied_ops: self.applied_ops,
+            });
+        }
+
+        self.check_rate_limit(&transfer.from, legs.sent(&transfer.from))
+    }
+
+    /// Validates the transfers of a MultiTransfer from `source`, each against
+    /// the state left by applying those before it
+    fn validate_multi_transfer(
+        &self,
+        source: &A,
+        transfers: &[Transfer<A, M>],
+    ) -> Result<(), ValidationError<A, M>> {
+        let mut legs = PriorLegs::new();
+        for transfer in transfers {
+            self.validate_transfer_after(source, transfer, &legs)?;
+            self.note_leg(&mut legs, transfer);
+        }
+        Ok(())
+    }
+
+    /// Tallies a transfer of a MultiTransfer for checking the ones after it
+    fn note_leg<'a>(&'a self, legs: &mut PriorLegs<'a, A>, transfer: &'a Transfer<A, M>) {
+        let collector = self
+            .fee_collector
+            .as_ref()
+            .filter(|c| transfer.fee > M::zero() && self.account_exists(c));
+        legs.record(transfer, collector);
+    }
+
+    /// Checks that the sender has not used up its transfers for the current
+    /// window, counting `pending` more transfers it makes in the same op
+    fn check_rate_limit(&self, sender: &A, pending: u64) -> Result<(), ValidationError<A, M>> {
+        let (max_transfers, window) = match self.rate_limit {
+            Some(limit) => limit,
+            None => return Ok(()),
+        };
+        let recent = self
+            .recent_transfers
+            .get(sender)
+            .map(|applied| {
+                applied
+                    .iter()
+                    .filter(|a| a.saturating_add(window) > self.applied_ops)
+                    .count()
+            })
+            .unwrap_or(0)
+            .saturating_add(pending as usize);
+
+        if recent >= max_transfers {
+            Err(ValidationError::RateLimited {
+                max_transfers,
+                window,
+            })
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Tracks a transfer made by the sender, for rate limiting
+    fn note_transfer(&mut self, sender: &A) {
+        let max_transfers = match self.rate_limit {
+            Some((max_transfers, _)) => max_transfers,
+            None => return,
+        };
+        let recent = self.recent_transfers.entry(sender.clone()).or_default();
+        recent.push(self.applied_ops);
+        let excess = recent.len().saturating_sub(max_transfers);
+        recent.drain(..excess);
+    }
+
+    /// Checks that the accounts exist and can transact, that the sender can
+    /// afford the transfer and that its dependencies are in our history.
+    ///
+    /// Neither the initiator nor any time lock are checked.
+    fn check_transfer(&self, transfer: &Transfer<A, M>) -> Result<(), ValidationError<A, M>> {
+        self.check_transfer_after(transfer, &PriorLegs::new())
+    }
+
+    /// Like `check_transfer`, once the given earlier transfers of a
+    /// MultiTransfer are applied
+    fn check_transfer_after(
+        &self,
+        transfer: &Transfer<A, M>,
+        legs: &PriorLegs<A>,
+    ) -> Result<(), ValidationError<A, M>> {
+        if !self.account_exists(&transfer.from) {
+            return Err(ValidationError::FromAccountDoesNotExist {
+                from: transfer.from.clone(),
+            });
+        }
+        if !self.account_exists(&transfer.to) {
+            return Err(ValidationError::ToAccountDoesNotExist {
+                to: transfer.to.clone(),
+            });
+        }
+        if transfer.fee > M::zero() && !self.has_fee_collector_account() {
+            return Err(ValidationError::NoFeeCollector);
+        }
+        self.check_currency(transfer)?;
+        self.check_not_frozen(transfer)?;
+
+        let expected = self.next_seq(&transfer.from) + legs.sent(&transfer.from);
+        if transfer.seq != expected {
+            return Err(ValidationError::BadSequence {
+                seq: transfer.seq,
+                expected,
+            });
+        }
+
+        // PERF: balance() is presently an expensive call, so the sender's
+        //       balance is computed once, for both the check and the error.
+        let balance = self.balance_after(&transfer.from, self.balance(&transfer.from), legs);
+        match transfer.amount.checked_add(transfer.fee) {
+            Some(total)
+                if total <= balance || self.liability_covers_after(&transfer.from, total, legs) => {
+            }
+            total => {
+                return Err(ValidationError::InsufficientFunds {
+                    balance,
+                    transfer_amount: total.unwrap_or_else(M::max_value),
+                })
+            }
+        }
+
+        if transfer.deps.len() > self.max_deps {
+            return Err(ValidationError::TooManyDeps {
+                deps: transfer.deps.len(),
+                max_deps: self.max_deps,
+            });
+        }
+        if has_dependency_cycle(transfer, &mut BTreeSet::new(), &mut BTreeSet::new()) {
+            return Err(ValidationError::DependencyCycle);
+        }
+        // Deps are a proof of funds, so they must all credit the sender
+        if transfer.deps.iter().any(|d| d.to != transfer.from) {
+            return Err(ValidationError::InvalidDependency);
+        }
+        if !transfer
+            .deps
+            .iter()
+            .all(|d| self.history_contains(&transfer.from, d))
+        {
+            return Err(ValidationError::MissingDependentOps);
+        }
+
+        self.check_recipient_balance_after(transfer, legs)
+    }
+
+    /// Checks that the transfer is neither recorded nor pending already, so
+    /// a re-submitted transfer is reported instead of looking newly accepted
+    fn check_not_duplicate(&self, transfer: &Transfer<A, M>) -> Result<(), ValidationError<A, M>> {
+        let id = transfer.id();
+        if self.is_recorded(transfer) || self.pending_transfers.contains_key(&id) {
+            Err(ValidationError::DuplicateTransfer { id })
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Checks that crediting the transfer won't overflow the recipient's balance
+    fn check_recipient_balance(
+        &self,
+        transfer: &Transfer<A, M>,
+    ) -> Result<(), ValidationError<A, M>> {
+        self.check_recipient_balance_after(transfer, &PriorLegs::new())
+    }
+
+    /// Like `check_recipient_balance`, once the given earlier transfers of a
+    /// MultiTransfer are applied
+    fn check_recipient_balance_after(
+        &self,
+        transfer: &Transfer<A, M>,
+        legs: &PriorLegs<A>,
+    ) -> Result<(), ValidationError<A, M>> {
+        // A self transfer leaves the balance unchanged, so skip the scan
+        if transfer.from == transfer.to {
+            return Ok(());
+        }
+
+        let balance = self.balance_after(&transfer.to, self.balance(&transfer.to), legs);
+        if balance.checked_add(transfer.amount).is_none() {
+            Err(ValidationError::RecipientBalanceOverflow {
+                balance,
+                transfer_amount: transfer.amount,
+            })
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Returns true if a fee collector is configured and has an open account
+    fn has_fee_collector_account(&self) -> bool {
+        match &self.fee_collector {
+            Some(collector) => self.account_exists(collector),
+            None => false,
+        }
+    }
+
+    /// Generates a new Transfer operation, or `None` if `transfer` would fail
+    pub fn try_transfer(&self, from: A, to: A, amount: M) -> Option<Op<A, M>> {
+        self.transfer(from, to, amount).ok()
+    }
+
+    /// Checks that both accounts of a transfer exist and that applying it
+    /// would neither overdraw the sender nor overflow the recipient.
+    fn check_transfer_invariants(&self, transfer: &Transfer<A, M>) -> Result<(), ApplyError<A>> {
+        self.check_transfer_invariants_after(transfer, &PriorLegs::new())
+    }
+
+    /// Like `check_transfer_invariants`, once the given earlier transfers of
+    /// a MultiTransfer are applied
+    fn check_transfer_invariants_after(
+        &self,
+        transfer: &Transfer<A, M>,
+        legs: &PriorLegs<A>,
+    ) -> Result<(), ApplyError<A>> {
+        for account in [&transfer.from, &transfer.to].iter() {
+            if !self.account_exists(account) {
+                return Err(ApplyError::AccountDoesNotExist {
+                    account: (*account).clone(),
+                });
+            }
+        }
+
+        let balance = self.balance_after(
+            &transfer.from,
+            self.balance_for_apply(&transfer.from)?,
+            legs,
+        );
+        let debit = transfer.amount.checked_add(transfer.fee);
+        if debit
+            .filter(|d| *d <= balance || self.liability_covers_after(&transfer.from, *d, legs))
+            .is_none()
+        {
+            return Err(ApplyError::BalanceUnderflow {
+                account: transfer.from.clone(),
+            });
+        }
+
+        self.check_credit_after(transfer, legs)
+    }
+
+    /// Checks that crediting a transfer would overflow neither the
+    /// recipient nor, if the transfer carries a fee, the fee collector
+    fn check_credit(&self, transfer: &Transfer<A, M>) -> Result<(), ApplyError<A>> {
+        self.check_credit_after(transfer, &PriorLegs::new())
+    }
+
+    /// Like `check_credit`, once the given earlier transfers of a
+    /// MultiTransfer are applied
+    fn check_credit_after(
+        &self,
+        transfer: &Transfer<A, M>,
+        legs: &PriorLegs<A>,
+    ) -> Result<(), ApplyError<A>> {
+        let overflow = |account: &A| ApplyError::BalanceOverflow {
+            account: account.clone(),
+        };
+
+        // Money credited back to the sender can't exceed what it was debited
+        let mut credits: BTreeMap<&A, M> = BTreeMap::new();
+        if transfer.to != transfer.from {
+            credits.insert(&transfer.to, transfer.amount);
+        }
+        if let Some(collector) = self
+            .fee_collector
+            .as_ref()
+            .filter(|c| transfer.fee > M::zero() && **c != transfer.from && self.account_exists(c))
+        {
+            let credit = credits.entry(collector).or_insert_with(M::zero);
+            *credit = credit
+                .checked_add(transfer.fee)
+                .ok_or_else(|| overflow(collector))?;
+        }
+
+        for (account, credit) in credits {
+            if self
+                .balance_after(account, self.balance_for_apply(account)?, legs)
+                .checked_add(credit)
+                .is_none()
+            {
+                return Err(overflow(account));
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks that refunding a cancelled pending transfer would not
+    /// overflow its sender, which may have been credited since it was debited
+    fn check_refund(&self, transfer: &Transfer<A, M>) -> Result<(), ApplyError<A>> {
+        let refund = transfer.amount.checked_add(transfer.fee);
+        let balance = self.balance_for_apply(&transfer.from)?;
+        if refund
+            .and_then(|refund| balance.checked_add(refund))
+            .is_none()
+        {
+            return Err(ApplyError::BalanceOverflow {
+                account: transfer.from.clone(),
+            });
+        }
+        Ok(())
+    }
+}
+
+impl<A: Ord + Hash + Debug + Clone + 'static + Serialize, M: MoneyT> Bank<A, M> {
+    /// Applies an op like `apply_checked`, reporting the balances it changed.
+    pub fn apply_with_effects(
+        &mut self,
+        op: Op<A, M>,
+    ) -> Result<AppliedEffect<A, M>, ApplyError<A>> {
+        // The moved money, and whether it was credited to the recipient
+        let moved = match &op {
+            Op::Transfer(transfer) | Op::TransferFrom { transfer, .. } => Some((transfer, true)),
+            Op::InitiateTransfer(transfer) => Some((transfer, false)),
+            Op::FinalizeTransfer { id } => self.pending_transfers.get(id).map(|t| (t, true)),
+            Op::CancelTransfer { id } => self.pending_transfers.get(id).map(|t| (t, false)),
+            _ => None,
+        }
+        .map(|(t, credited)| (t.from.clone(), t.to.clone(), t.amount, credited))
+        .or_else(|| match &op {
+            // The whole balance is swept into the new account
+            Op::RecoverAccount { owner, new_owner } => self
+                .try_balance(owner)
+                .ok()
+                .map(|balance| (owner.clone(), new_owner.clone(), balance, true)),
+            // The whole balance is swept into the destination account
+            Op::CloseAndSweep { owner, destination } => self
+                .try_balance(owner)
+                .ok()
+                .map(|balance| (owner.clone(), destination.clone(), balance, true)),
+            _ => None,
+        });
+        let cancel = matches!(op, Op::CancelTransfer { .. });
+        let opened = match &op {
+            Op::OpenAccount { owner, balance, .. } => Some((owner.clone(), *balance)),
+            _ => None,
+        };
+        let batch = match &op {
+            Op::BatchOpen { accounts } => Some(accounts.clone()),
+            _ => None,
+        };
+        let multi = match &op {
+            Op::MultiTransfer { transfers } => transfers.first().map(|first| {
+                let credits: Vec<(A, M)> =
+                    transfers.iter().map(|t| (t.to.clone(), t.amount)).collect();
+                (first.from.clone(), credits)
+            }),
+            _ => None,
+        };
+        let adjusted = match &op {
+            Op::AdjustBalance { owner, .. } => self
+                .try_balance(owner)
+                .ok()
+                .map(|previous_balance| (owner.clone(), previous_balance)),
+            _ => None,
+        };
+
+        let applied_ops = self.applied_ops;
+        self.apply_checked(op)?;
+        if self.applied_ops == applied_ops {
+            // A duplicate, skipped by apply_checked
+            return Ok(AppliedEffect::NoBalanceChange);
+        }
+        if let Some(accounts) = batch {
+            return Ok(AppliedEffect::OpenAccounts { accounts });
+        }
+        if let Some((from, credits)) = multi {
+            return Ok(AppliedEffect::MultiTransfer {
+                new_from_balance: self.balance(&from),
+                from,
+                credits: credits
+                    .into_iter()
+                    .map(|(to, amount)| {
+                        let new_to_balance = self.balance(&to);
+                        (to, amount, new_to_balance)
+                    })
+                    .collect(),
+            });
+        }
+        if let Some((owner, previous_balance)) = adjusted {
+            return Ok(AppliedEffect::Adjusted {
+                new_balance: self.balance(&owner),
+                owner,
+                previous_balance,
+            });
+        }
+
+        let effect = match (moved, opened) {
+            (Some((from, to, amount, true)), _) => AppliedEffect::Transfer {
+                new_from_balance: self.balance(&from),
+                new_to_balance: self.balance(&to),
+                from,
+                to,
+                amount,
+            },
+            (Some((from, _, amount, false)), _) if cancel => AppliedEffect::Released {
+                new_from_balance: self.balance(&from),
+                from,
+                amount,
+            },
+            (Some((from, _, amount, false)), _) => AppliedEffect::Reserved {
+                new_from_balance: self.balance(&from),
+                from,
+                amount,
+            },
+            (None, Some((owner, balance))) => AppliedEffect::OpenAccount { owner, balance },
+            (None, None) => AppliedEffect::NoBalanceChange,
+        };
+        Ok(effect)
+    }
+
+    /// Applies an op after verifying that doing so keeps the bank consistent,
+    /// leaving the bank untouched if it would not.
+    ///
+    /// Ops are expected to have been validated, so an error here points to
+    /// an integration bug or to state loaded from an untrusted source.  In
+    /// particular, an op that would push a balance, including the fee
+    /// collector's or a refunded sender's, past the maximum amount is
+    /// refused with `ApplyError::BalanceOverflow`, which `apply` logs.
+    ///
+    /// Applying a transfer that is already in history is a no-op, so BRB
+    /// re-delivering a committed transfer leaves balances and counters as is.
+    pub fn apply_checked(&mut self, op: Op<A, M>) -> Result<(), ApplyError<A>> {
+        if self.is_duplicate(&op) {
+            // Skip it so the counters don't double count it
+            return Ok(());
+        }
+        match &op {
+            Op::Transfer(transfer) | Op::InitiateTransfer(transfer) => {
+                self.check_transfer_invariants(transfer)?;
+            }
+            Op::TransferFrom { spender, transfer } => {
+                self.check_transfer_invariants(transfer)?;
+
+                let covered = transfer
+                    .amount
+                    .checked_add(transfer.fee)
+                    .filter(|total| *total <= self.allowance(&transfer.from, spender));
+                if covered.is_none() {
+                    return Err(ApplyError::AllowanceExceeded {
+                        owner: transfer.from.clone(),
+                        spender: spender.clone(),
+                    });
+                }
+            }
+            Op::OpenAccount { owner, .. } => {
+                if self.account_exists(owner) {
+                    return Err(ApplyError::AccountAlreadyExists {
+                        account: owner.clone(),
+                    });
+                }
+            }
+            Op::BatchOpen { accounts } => {
+                let mut owners = BTreeSet::new();
+                for (owner, _) in accounts.iter() {
+                    if self.account_exists(owner) || !owners.insert(owner) {
+                        return Err(ApplyError::AccountAlreadyExists {
+                            account: owner.clone(),
+                        });
+                    }
+                }
+            }
+            Op::FinalizeTransfer { id } => match self.pending_transfers.get(id) {
+                // The recipient may have been frozen since the transfer was initiated
+                Some(transfer) if self.is_frozen(&transfer.to) => {
+                    return Err(ApplyError::AccountFrozen {
+                        account: transfer.to.clone(),
+                    })
+                }
+                // Funds were already debited when the transfer was initiated
+                Some(transfer) => self.check_credit(transfer)?,
+                None => return Err(ApplyError::PendingTransferDoesNotExist { id: *id }),
+            },
+            Op::CancelTransfer { id } => match self.pending_transfers.get(id) {
+                Some(transfer) => self.check_refund(transfer)?,
+                None => return Err(ApplyError::PendingTransferDoesNotExist { id: *id }),
+            },
+            Op::RecoverAccount { owner, new_owner } => {
+                if !self.account_exists(owner) {
+                    return Err(ApplyError::AccountDoesNotExist {
+                        account: owner.clone(),
+                    });
+                }
+                if self.account_exists(new_owner) {
+                    return Err(ApplyError::AccountAlreadyExists {
+                        account: new_owner.clone(),
+                    });
+                }
+                self.balance_for_apply(owner)?;
+            }
+            Op::CloseAndSweep { owner, destination } => {
+                for account in [owner, destination].iter() {
+                    if !self.account_exists(account) {
+                        return Err(ApplyError::AccountDoesNotExist {
+                            account: (*account).clone(),
+                        });
+                    }
+                }
+                self.balance_for_apply(owner)?;
+                if let Some(sweep) = self.sweep_transfer(owner, destination.clone()) {
+                    self.check_credit(&sweep)?;
+                }
+            }
+            Op::AdjustBalance { owner, .. } => {
+                if !self.account_exists(owner) {
+                    return Err(ApplyError::AccountDoesNotExist {
+                        account: owner.clone(),
+                    });
+                }
+                self.balance_for_apply(owner)?;
+            }
+            Op::MultiTransfer { transfers } => {
+                // Each transfer is checked against the balances left by those before it
+                let mut legs = PriorLegs::new();
+                for transfer in transfers.iter() {
+                    self.check_transfer_invariants_after(transfer, &legs)?;
+                    self.note_leg(&mut legs, transfer);
+                }
+            }
+            Op::Approve { .. }
+            | Op::SetAccountMetadata { .. }
+            | Op::SetFrozen { .. }
+            | Op::RegisterAlias { .. } => (),
+        }
+        self.applied_ops += 1;
+
+        match &op {
+            Op::Transfer(transfer)
+            | Op::TransferFrom { transfer, .. }
+            | Op::InitiateTransfer(transfer) => self.note_transfer(&transfer.from),
+            Op::MultiTransfer { transfers } => {
+                for transfer in transfers.iter() {
+                    self.note_transfer(&transfer.from);
+                }
+            }
+            _ => (),
+        }
+
+        match op {
+            Op::Transfer(transfer) => self.record_transfer_with_fee(transfer),
+            Op::TransferFrom { spender, transfer } => {
+                let key = (transfer.from.clone(), spender);
+                let allowance = self.allowances.get(&key).copied().unwrap_or_else(M::zero);
+                // The allowance was checked to cover the amount and fee above
+                let remaining = transfer
+                    .amount
+                    .checked_add(transfer.fee)
+                    .and_then(|total| allowance.checked_sub(total))
+                    .unwrap_or_else(M::zero);
+                if remaining > M::zero() {
+                    self.allowances.insert(key, remaining);
+                } else {
+                    self.allowances.remove(&key);
+                }
+                self.record_transfer_with_fee(transfer);
+            }
+            Op::Approve {
+                owner,
+                spender,
+                amount,
+            } => {
+                *self
+                    .allowance_versions
+                    .entry((owner.clone(), spender.clone()))
+                    .or_default() += 1;
+                if amount > M::zero() {
+                    self.allowances.insert((owner, spender), amount);
+                } else {
+                    self.allowances.remove(&(owner, spender));
+                }
+            }
+            Op::InitiateTransfer(transfer) => {
+                self.pending_transfers.insert(transfer.id(), transfer);
+            }
+            Op::FinalizeTransfer { id } => {
+                if let Some(transfer) = self.pending_transfers.remove(&id) {
+                    self.record_transfer_with_fee(transfer);
+                }
+            }
+            Op::CancelTransfer { id } => {
+                self.pending_transfers.remove(&id);
+                self.cancelled_transfers.insert(id);
+            }
+            Op::OpenAccount {
+                owner,
+                balance,
+                currency,
+                recovery,
+                kind,
+            } => self.open(owner, balance, currency, recovery, kind),
+            Op::BatchOpen { accounts } => {
+                info!(accounts = accounts.len(); "[BANK] opening accounts in batch");
+                for (owner, balance) in accounts {
+                    self.open(owner, balance, DEFAULT_CURRENCY, None, AccountKind::Asset);
+                }
+            }
+            Op::RecoverAccount { owner, new_owner } => {
+                info!(owner:? = owner, new_owner:? = new_owner; "[BANK] recovering account");
+                self.reassign_account(owner, new_owner);
+            }
+            Op::CloseAndSweep { owner, destination } => {
+                info!(owner:? = owner, destination:? = destination; "[BANK] closing account");
+                self.close_account(owner, destination);
+            }
+            Op::SetAccountMetadata { owner, key, value } => {
+                self.metadata.entry(owner).or_default().insert(key, value);
+            }
+            Op::SetFrozen { owner, frozen } => {
+                info!(owner:? = owner, frozen = frozen; "[BANK] setting frozen state");
+                self.set_frozen_state(owner, frozen);
+            }
+            Op::AdjustBalance { owner, new_balance } => {
+                info!(owner:? = owner, new_balance:% = new_balance; "[BANK] adjusting balance");
+                self.adjust_account_balance(owner, new_balance);
+            }
+            Op::RegisterAlias { owner, alias } => {
+                info!(owner:? = owner, alias = alias.as_str(); "[BANK] registering alias");
+                self.reserve_alias(alias, owner);
+            }
+            Op::MultiTransfer { transfers } => {
+                for transfer in transfers {
+                    self.record_transfer_with_fee(transfer);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Builds a bank by validating and applying each `(source, op)` pair of
+    /// an op log in sequence.
+    ///
+    /// The bank uses the default configuration, see `replay_ops` otherwise.
+    /// Returns the first validation error encountered.
+    pub fn rebuild_from_ops(
+        id: A,
+        ops: impl IntoIterator<Item = (A, Op<A, M>)>,
+    ) -> Result<Self, ValidationError<A, M>> {
+        Self::new(id).replay_ops(ops)
+    }
+
+    /// Validates and applies each `(source, op)` pair of an op log in
+    /// sequence, on top of this bank.
+    ///
+    /// Returns the first validation error encountered.
+    pub fn replay_ops(
+        mut self,
+        ops: impl IntoIterator<Item = (A, Op<A, M>)>,
+    ) -> Result<Self, ValidationError<A, M>> {
+        for (source, op) in ops {
+            self.validate(&source, &op)?;
+            self.apply(op);
+        }
+        Ok(self)
+    }
+
+    /// Panics if an op about to be applied fails validation, to catch
+    /// integrations that apply ops without validating them first.  Only
+    /// built with `debug_assertions`, see `with_debug_validation`.
+    ///
+    /// The op is validated as if initiated by the actor entitled to
+    /// initiate it, or by our own actor when there is none.  Duplicates,
+    /// which applying skips, are not checked.  This assumes the op is
+    /// applied against the state it was validated against.
+    #[cfg(debug_assertions)]
+    fn assert_valid(&self, op: &Op<A, M>) {
+        if self.is_duplicate(op) {
+            return;
+        }
+        let source = match op {
+            Op::Transfer(transfer) | Op::InitiateTransfer(transfer) => Some(&transfer.from),
+            Op::TransferFrom { spender, .. } => Some(spender),
+            Op::OpenAccount { owner, .. }
+            | Op::SetAccountMetadata { owner, .. }
+            | Op::Approve { owner, .. }
+            | Op::RegisterAlias { owner, .. }
+            | Op::CloseAndSweep { owner, .. } => Some(owner),
+            Op::SetFrozen { .. } | Op::AdjustBalance { .. } | Op::BatchOpen { .. } => {
+                self.admin.as_ref()
+            }
+            Op::FinalizeTransfer { id } | Op::CancelTransfer { id } => {
+                self.pending_transfers.get(id).map(|t| &t.from)
+            }
+            Op::RecoverAccount { owner, .. } => self.recovery_actors.get(owner),
+            Op::MultiTransfer { transfers } => transfers.first().map(|t| &t.from),
+        }
+        .unwrap_or(&self.id);
+
+        if let Err(e) = self.validate(source, op) {
+            panic!(
+                "[ERROR] Applying an op that fails validation, was it validated? {:?}: {}",
+                op, e
+            );
+        }
+    }
+
+    /// Validates a sequence of ops from `source` as if they were applied in order.
+    ///
+    /// Ops may depend on earlier ops in the batch, e.g. a transfer out of an
+    /// account opened earlier in the batch.  Returns the index and error of
+    /// the first op that fails.  `self` is left untouched.
+    pub fn validate_batch(
+        &self,
+        source: &A,
+        ops: &[Op<A, M>],
+    ) -> Result<(), (usize, ValidationError<A, M>)> {
+        let mut scratch = self.clone();
+        for (i, op) in ops.iter().enumerate() {
+            scratch.validate(source, op).map_err(|e| (i, e))?;
+            scratch.apply(op.clone());
+        }
+        Ok(())
+    }
+
+    /// Validates and applies transfers received from a peer's
+    /// `missing_from`, returning those rejected along with the reason.
+    ///
+    /// Transfers already in history are skipped.  Each transfer is
+    /// validated as an op from its sender, so a transfer that needs another
+    /// to be applied first is retried until no more can be applied, in
+    /// whatever order the transfers are given.  Transfers are absorbed as
+    /// plain transfers: the allowances of transfers made by a spender are
+    /// not synced.
+    pub fn absorb(
+        &mut self,
+        transfers: Vec<Transfer<A, M>>,
+    ) -> Vec<(Transfer<A, M>, ValidationError<A, M>)> {
+        let mut pending: Vec<Transfer<A, M>> = transfers
+            .into_iter()
+            .filter(|t| !self.is_recorded(t))
+            .collect();
+
+        loop {
+            let attempted = pending.len();
+            let mut rejected = Vec::new();
+            for transfer in pending {
+                let op = Op::Transfer(transfer.clone());
+                match self.validate(&transfer.from, &op) {
+                    Ok(()) => self.apply(op),
+                    Err(e) => rejected.push((transfer, e)),
+                }
+            }
+
+            // Stop once a pass makes no progress
+            if rejected.is_empty() || rejected.len() == attempted {
+                return rejected;
+            }
+            pending = rejected.into_iter().map(|(t, _)| t).collect();
+        }
+    }
+
+    /// Classifies each op of a log, with the actor that initiated it,
+    /// against the current state: whether it could be applied now, was
+    /// already applied, or is blocked, e.g. on missing deps.
+    ///
+    /// Each op is checked on its own against the current state, without
+    /// applying the ops before it, so an op depending on an earlier op of
+    /// the log is reported as blocked.  `self` is left untouched.
+    pub fn classify_ops(&self, ops: &[(A, Op<A, M>)]) -> Vec<OpStatus<A, M>> {
+        ops.iter()
+            .map(|(source, op)| {
+                if self.is_duplicate(op) {
+                    OpStatus::AlreadyApplied
+                } else {
+                    match self.validate(source, op) {
+                        Ok(()) => OpStatus::Applicable,
+                        Err(e) => OpStatus::Blocked(e),
+                    }
+                }
+            })
+            .collect()
+    }
+
+    /// Validates an op like `validate`, along with advisory warnings about
+    /// conditions that are worth flagging to users but don't make the op
+    /// invalid, such as a transfer draining its sender's account.
+    ///
+    /// Warnings are reported whether or not the op is valid.
+    pub fn validate_verbose(&self, source: &A, op: &Op<A, M>) -> VerboseValidation<A, M> {
+        let result = self.validate(source, op);
+
+        let transfer = match op {
+            Op::Transfer(transfer)
+            | Op::InitiateTransfer(transfer)
+            | Op::TransferFrom { transfer, .. } => transfer,
+            _ => return (result, Vec::new()),
+        };
+        let mut warnings = Vec::new();
+        if transfer.amount == M::zero() && transfer.fee == M::zero() {
+            warnings.push(ValidationWarning::ZeroAmount);
+        }
+        if transfer.from == transfer.to {
+            warnings.push(ValidationWarning::SelfTransfer {
+                account: transfer.from.clone(),
+            });
+        }
+        let total = transfer.amount.checked_add(transfer.fee);
+        if total.is_some() && self.try_balance(&transfer.from).ok() == total {
+            warnings.push(ValidationWarning::DrainsAccount {
+                account: transfer.from.clone(),
+            });
+        }
+        if transfer.deps.len() > MANY_DEPS_WARNING_THRESHOLD {
+            warnings.push(ValidationWarning::ManyDeps {
+                deps: transfer.deps.len(),
+            });
+        }
+        (result, warnings)
+    }
+
+    /// Returns whether applying an op from `source` would change the
+    /// ledger, so that callers can skip a BRB round for ops that would not.
+    ///
+    /// Ops that fail `validate` are never effective.  Nor are transfers
+    /// that move no money, being of zero amount or to the sender itself,
+    /// with no fee, though applying one would still use up a sequence
+    /// number.  Nor are ops setting metadata, frozen state or an allowance
+    /// to its present value.
+    pub fn is_effective(&self, source: &A, op: &Op<A, M>) -> bool {
+        if self.validate(source, op).is_err() {
+            return false;
+        }
+
+        let moves_money =
+            |t: &Transfer<A, M>| t.fee != M::zero() || (t.amount != M::zero() && t.from != t.to);
+        match op {
+            Op::Transfer(transfer)
+            | Op::InitiateTransfer(transfer)
+            | Op::TransferFrom { transfer, .. } => moves_money(transfer),
+            Op::SetAccountMetadata { owner, key, value } => {
+                self.account_metadata(owner)
+                    .and_then(|entries| entries.get(key))
+                    != Some(value)
+            }
+            Op::SetFrozen { owner, frozen } => self.is_frozen(owner) != *frozen,
+            Op::AdjustBalance { owner, new_balance } => {
+                self.try_balance(owner).ok() != Some(*new_balance)
+            }
+            Op::Approve {
+                owner,
+                spender,
+                amount,
+            } => self.allowance(owner, spender) != *amount,
+            Op::BatchOpen { accounts } => !accounts.is_empty(),
+            Op::MultiTransfer { transfers } => transfers.iter().any(moves_money),
+            Op::OpenAccount { .. }
+            | Op::RecoverAccount { .. }
+            | Op::CloseAndSweep { .. }
+            | Op::FinalizeTransfer { .. }
+            | Op::CancelTransfer { .. }
+            | Op::RegisterAlias { .. } => true,
+        }
+    }
+
+    /// Checks that the bank is internally consistent, returning every
+    /// violation found.
+    ///
+    /// Verifies that the accounts of every transfer exist, that every
+    /// transfer is in the histories of both its sender and recipient, that
+    /// the dependencies of every transfer are in its sender's history, and
+    /// that no balance is below zero or overflows.  Meant for debugging
+    /// diverging replicas and as a test oracle, it scans the whole ledger
+    /// and never panics, whatever the arithmetic mode.
+    ///
+    /// Transfers collapsed by `checkpoint_account` or `prune_to_retention`
+    /// are expected to be missing from the checkpointed account's history,
+    /// so the dependencies of its outgoing transfers are not checked.
+    pub fn check_invariants(&self) -> Result<(), Vec<InvariantViolation<A>>> {
+        let mut violations = Vec::new();
+
+        for transfer in self.all_transfers() {
+            let id = transfer.id();
+            for account in [&transfer.from, &transfer.to].iter() {
+                if !self.account_exists(account) {
+                    violations.push(InvariantViolation::UnknownAccount {
+                        transfer: id,
+                        account: (*account).clone(),
+                    });
+                } else if !self.history_contains(account, transfer)
+                    && !self.checkpoints.contains_key(account)
+                {
+                    violations.push(InvariantViolation::MissingFromHistory {
+                        transfer: id,
+                        account: (*account).clone(),
+                    });
+                }
+            }
+            if self.checkpoints.contains_key(&transfer.from) {
+                // Its dependencies may have been collapsed by a checkpoint
+                // or pruning of its sender, even when it was retained
+                continue;
+            }
+            for dep in transfer.deps.iter() {
+                // Deps of compacted transfers are stripped of their own deps
+                if self.resolve_in_history(&transfer.from, dep).is_none() {
+                    violations.push(InvariantViolation::MissingDependency {
+                        transfer: id,
+                        dependency: dep.id(),
+                    });
+                }
+            }
+        }
+
+        for account in self.initial_balances.keys() {
+            match self.try_balance(account) {
+                Err(ApplyError::BalanceUnderflow { account }) => {
+                    violations.push(InvariantViolation::NegativeBalance { account })
+                }
+                Err(ApplyError::BalanceOverflow { account }) => {
+                    violations.push(InvariantViolation::BalanceOverflow { account })
+                }
+                _ => (),
+            }
+        }
+
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(violations)
+        }
+    }
+}
+
+/// Iterates over every account and its present balance, ordered by actor,
+/// as computed by `Bank::balances` in a single pass over the history.
+impl<A: Ord + Hash + Debug + Clone + Serialize, M: MoneyT> IntoIterator for &Bank<A, M> {
+    type Item = (A, M);
+    type IntoIter = alloc::collections::btree_map::IntoIter<A, M>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.balances().into_iter()
+    }
+}
+
+/// Adds an amount to a running total, kept wider than amounts like in
+/// `wide_sum`, panicking on overflow
+fn add_amount<K: Ord, M: MoneyT>(totals: &mut BTreeMap<K, u128>, key: K, amount: M) {
+    let total = totals.entry(key).or_insert(0);
+    *total = total
+        .checked_add(amount.as_u128())
+        .expect("[ERROR] Sum of transfers overflowed");
+}
+
+/// Sums amounts of money as a u128, so that a sum past the maximum amount
+/// doesn't overflow before it is netted against the opposite sum, or
+/// returns `None` if even that overflows
+fn wide_sum<M: MoneyT>(mut amounts: impl Iterator<Item = M>) -> Option<u128> {
+    amounts.try_fold(0u128, |sum, amount| sum.checked_add(amount.as_u128()))
+}
 
-    /// Set of all transfers, by actor
-    hist: BTreeMap<A, BTreeSet<Transfer<A>>>,
+/// Sums amounts of money, or returns `None` on overflow
+fn checked_sum<M: MoneyT>(mut amounts: impl Iterator<Item = M>) -> Option<M> {
+    amounts.try_fold(M::zero(), |sum, amount| sum.checked_add(amount))
 }
 
-impl<A: Ord + Hash + Debug + Clone> Bank<A> {
-    /// Open a new account.
-    ///
-    /// The balance field should normally be 0, but this field
-    /// enables an application to force a non-zero starting balance.
-    /// Though of course other nodes must agree.  This could for
-    /// example be used to pre-fund a "MINT" account that spends
-    /// money into existence (in other accounts) over time.
-    pub fn open_account(&self, owner: A, balance: Money) -> Op<A> {
-        Op::OpenAccount { owner, balance }
+/// Returns whether a transfer transitively depends on itself, e.g. a
+/// crafted transfer listing a copy of itself with other deps among its
+/// nested deps.
+///
+/// Transfers are identified without their deps, as compacted deps are,
+/// so a transfer and its copies are the same node of the dependency
+/// graph.  `path` holds the transfers being explored and `acyclic` those
+/// already found free of cycles, so each is only explored once.
+fn has_dependency_cycle<A: Ord + Hash + Clone, M: MoneyT>(
+    transfer: &Transfer<A, M>,
+    path: &mut BTreeSet<Transfer<A, M>>,
+    acyclic: &mut BTreeSet<Transfer<A, M>>,
+) -> bool {
+    let node = transfer.without_deps();
+    if acyclic.contains(&node) {
+        return false;
     }
-
-    /// Returns an account's starting balance, prior to any transfers in or out.
-    pub fn initial_balance(&self, actor: &A) -> Money {
-        self.initial_balances
-            .get(&actor)
-            .cloned()
-            .unwrap_or_else(|| panic!("[ERROR] No initial balance for {:?}", actor))
+    if !path.insert(node.clone()) {
+        return true;
     }
+    if transfer
+        .deps
+        .iter()
+        .any(|d| has_dependency_cycle(d, path, acyclic))
+    {
+        return true;
+    }
+    path.remove(&node);
+    acyclic.insert(node);
+    false
+}
 
-    /// Returns an account's present balance.
-    ///
-    /// This is presently a slow operation as the entire history of all
-    /// transfers is iterated.  i.e., it degrades O(n) with the size of the history.
-    pub fn balance(&self, actor: &A) -> Money {
-        // PERF: Can we make this function faster?  perhaps even O(1)?
-
-        // TODO: in the paper, when we read from an actor, we union the actor
-        //       history with the deps, I don't see a use for this since anything
-        //       in deps is already in the actor history. Think this through a
-        //       bit more carefully.
-        let h = self.history(actor);
+/// Converts an amount of money to an i128, saturating amounts beyond its range
+fn to_i128<M: MoneyT>(amount: M) -> i128 {
+    wide_to_i128(amount.as_u128())
+}
 
-        let outgoing: Money = h
-            .iter()
-            .filter(|t| &t.from == actor)
-            .map(|t| t.amount)
-            .sum();
-        let incoming: Money = h.iter().filter(|t| &t.to == actor).map(|t| t.amount).sum();
+/// Converts a u128 to an i128, saturating values beyond its range
+fn wide_to_i128(value: u128) -> i128 {
+    i128::try_from(value).unwrap_or(i128::MAX)
+}
 
-        // We compute differences in a larger space since we need to move to signed numbers
-        // and hence we lose a bit.
-        let balance_delta: i128 = (incoming as i128) - (outgoing as i128);
-        let balance: i128 = self.initial_balance(actor) as i128 + balance_delta;
+/// Converts a balance within the range of amounts to an amount, reading a
+/// liability's negative balance as zero
+/// What the transfers of a MultiTransfer checked so far do to the accounts
+/// involved, so that each transfer is checked against the balances left by
+/// those before it without applying them
+struct PriorLegs<'a, A> {
+    /// Net amount credited (positive) or debited (negative), by account
+    moved: BTreeMap<&'a A, i128>,
+    /// Number of transfers, by sender
+    sent: BTreeMap<&'a A, u64>,
+    /// Ids of the transfers
+    ids: BTreeSet<TransferId>,
+}
 
-        assert!(balance >= 0); // sanity check that we haven't violated our balance constraint
-        assert!(balance <= Money::max_value() as i128); // sanity check that it's safe to downcast
+impl<'a, A: Ord + Hash + Serialize> PriorLegs<'a, A> {
+    fn new() -> Self {
+        PriorLegs {
+            moved: BTreeMap::new(),
+            sent: BTreeMap::new(),
+            ids: BTreeSet::new(),
+        }
+    }
 
-        balance as Money
+    fn moved(&self, account: &A) -> i128 {
+        self.moved.get(account).copied().unwrap_or(0)
     }
 
-    /// Returns complete history of transfers for provided actor
-    fn history(&self, actor: &A) -> BTreeSet<Transfer<A>> {
-        // PERF: can we make this faster, without need to clone?
-        self.hist.get(&actor).cloned().unwrap_or_default()
+    fn sent(&self, sender: &A) -> u64 {
+        self.sent.get(sender).copied().unwrap_or(0)
     }
 
-    /// Generates a new Transfer operation (but does not apply it)
-    pub fn transfer(&self, from: A, to: A, amount: Money) -> Option<Op<A>> {
-        // PERF: balance() is presently an expensive call.
-        let balance = self.balance(&from);
-        // TODO: we should leave this validation to the self.validate logic, no need to duplicate it here
-        if balance < amount {
-            warn!(
-                "{:?} does not have enough money to transfer ${} to {:?}. (balance: ${})",
-                from, amount, to, balance
-            );
-            None
-        } else {
-            let deps = self.deps.clone();
-            Some(Op::Transfer(Transfer {
-                from,
-                to,
-                amount,
-                deps,
-            }))
+    /// Tallies a transfer, crediting its fee to `collector`, if any
+    fn record<M: MoneyT>(&mut self, transfer: &'a Transfer<A, M>, collector: Option<&'a A>) {
+        let mut credit = |account: &'a A, amount: i128| {
+            let moved = self.moved.entry(account).or_insert(0);
+            *moved = moved.saturating_add(amount);
+        };
+        credit(&transfer.from, -to_i128(transfer.amount));
+        credit(&transfer.from, -to_i128(transfer.fee));
+        credit(&transfer.to, to_i128(transfer.amount));
+        if let Some(collector) = collector {
+            credit(collector, to_i128(transfer.fee));
         }
+        *self.sent.entry(&transfer.from).or_insert(0) += 1;
+        self.ids.insert(transfer.id());
     }
 }
 
+fn to_money<M: MoneyT>(balance: i128) -> M {
+    M::from_u128(balance.max(0) as u128).unwrap_or_else(M::max_value)
+}
+
 /// Enumeration of AT2 validation errors
+///
+/// Variants carry the actors involved where relevant.  Since actors are not
+/// required to implement `Display`, they are formatted with `Debug`.
+///
+/// New validation rules bring new variants, so this enum is `non_exhaustive`:
+/// matches outside this crate need a wildcard arm.
 #[derive(Error, Debug, PartialEq, Eq)]
-pub enum ValidationError {
+#[non_exhaustive]
+pub enum ValidationError<A: Debug, M: MoneyT = Money> {
     /// The actor that initiated the operation does not match the account owner
-    #[error("The actor that initiated the operation does not match the account owner")]
-    NotInitiatedByAccountOwner,
+    #[error("The actor that initiated the operation ({initiator:?}) does not match the account owner ({owner:?})")]
+    NotInitiatedByAccountOwner {
+        /// Actor that initiated the operation
+        initiator: A,
+        /// Account owner
+        owner: A,
+    },
 
     /// The From account does not exist
-    #[error("The From account does not exist")]
-    FromAccountDoesNotExist,
+    #[error("The From account ({from:?}) does not exist")]
+    FromAccountDoesNotExist {
+        /// The From account
+        from: A,
+    },
 
     /// The To account does not exist
-    #[error("The To account does not exist")]
-    ToAccountDoesNotExist,
+    #[error("The To account ({to:?}) does not exist")]
+    ToAccountDoesNotExist {
+        /// The To account
+        to: A,
+    },
 
     /// Insufficient funds
     #[error("Insufficient funds")]
     InsufficientFunds {
         /// Account balance
-        balance: Money,
-        /// Transfer amount
-        transfer_amount: Money,
+        balance: M,
+        /// Transfer amount, including any fee
+        transfer_amount: M,
     },
 
     /// Missing dependent ops
     #[error("Missing dependent ops")]
     MissingDependentOps,
 
+    /// The transfer's sequence number is not the sender's next one
+    #[error("The transfer's sequence number ({seq}) is not the sender's next one ({expected})")]
+    BadSequence {
+        /// Sequence number of the transfer
+        seq: u64,
+        /// Sequence number of the sender's next transfer
+        expected: u64,
+    },
+
+    /// A dependency of the transfer does not credit the sender
+    #[error("A dependency of the transfer does not credit the sender")]
+    InvalidDependency,
+
+    /// The transfer transitively depends on itself
+    #[error("The transfer transitively depends on itself")]
+    DependencyCycle,
+
+    /// The transfer carries more dependencies than allowed
+    #[error("The transfer carries more dependencies than allowed")]
+    TooManyDeps {
+        /// Number of dependencies carried by the transfer
+        deps: usize,
+        /// Maximum allowed number of dependencies
+        max_deps: usize,
+    },
+
+    /// The dependencies chosen for a transfer don't prove enough funds
+    #[error("The dependencies chosen for the transfer only cover {covered} of {transfer_amount}")]
+    DepsDoNotCoverAmount {
+        /// Amount covered by the dependencies and the balance needing no proof
+        covered: M,
+        /// Transfer amount, including any fee
+        transfer_amount: M,
+    },
+
+    /// The actor that initiated the operation is not the configured admin
+    #[error("The actor that initiated the operation ({initiator:?}) is not the configured admin")]
+    NotInitiatedByAdmin {
+        /// Actor that initiated the operation
+        initiator: A,
+    },
+
+    /// The transfer's currency differs from that of an account involved
+    #[error("The transfer's currency ({transfer_currency}) differs from that of account {account:?} ({account_currency})")]
+    CurrencyMismatch {
+        /// The account in a different currency
+        account: A,
+        /// Currency of the account
+        account_currency: Currency,
+        /// Currency of the transfer
+        transfer_currency: Currency,
+    },
+
+    /// The account is frozen
+    #[error("The account ({account:?}) is frozen")]
+    AccountFrozen {
+        /// The frozen account
+        account: A,
+    },
+
+    /// The account was closed, so it can't be unfrozen
+    #[error("The account ({account:?}) is closed")]
+    AccountClosed {
+        /// The closed account
+        account: A,
+    },
+
+    /// The account can't be closed while it has pending transfers
+    #[error("The account ({account:?}) has pending transfers")]
+    AccountHasPendingTransfers {
+        /// The account being closed
+        account: A,
+    },
+
+    /// An account can't be swept into itself as it is closed
+    #[error("The account ({account:?}) can't be swept into itself")]
+    SweepToClosingAccount {
+        /// The account being closed
+        account: A,
+    },
+
+    /// A liability account can't be closed or recovered while its balance
+    /// is negative
+    #[error("The account ({account:?}) has a negative balance ({balance})")]
+    AccountInDebt {
+        /// The account being closed or recovered
+        account: A,
+        /// The account's balance
+        balance: i128,
+    },
+
+    /// The delegated transfer exceeds the spender's allowance
+    #[error("The delegated transfer exceeds the spender's allowance")]
+    AllowanceExceeded {
+        /// Remaining allowance of the spender
+        allowance: M,
+        /// Transfer amount, including any fee
+        transfer_amount: M,
+    },
+
+    /// The transfer would overflow the recipient's balance
+    #[error("The transfer would overflow the recipient's balance")]
+    RecipientBalanceOverflow {
+        /// Recipient account balance
+        balance: M,
+        /// Transfer amount
+        transfer_amount: M,
+    },
+
     /// Owner already has an account
     #[error("Owner already has an account")]
     OwnerAlreadyHasAnAccount,
+
+    /// A batch lists the same account twice
+    #[error("The account ({account:?}) is listed twice in the batch")]
+    AccountListedTwice {
+        /// The account listed twice
+        account: A,
+    },
+
+    /// The account's initial balance exceeds the configured cap
+    #[error("The account's initial balance exceeds the configured cap")]
+    InitialBalanceTooHigh {
+        /// Requested initial balance
+        balance: M,
+        /// Largest balance an account may be opened with
+        max_initial_balance: M,
+    },
+
+    /// The transfer carries a fee but no fee collector account is configured
+    #[error("The transfer carries a fee but no fee collector account is configured")]
+    NoFeeCollector,
+
+    /// The transfer is time-locked and the bank has not yet applied enough ops
+    #[error("The transfer is time-locked and the bank has not yet applied enough ops")]
+    TimeLockNotElapsed {
+        /// Number of applied ops the transfer is locked until
+        not_before: u64,
+        /// Number of ops applied by the bank
+        applied_ops: u64,
+    },
+
+    /// The sender has made as many transfers as allowed in the current window
+    #[error(
+        "The sender has made {max_transfers} transfers in the last {window} ops, the most allowed"
+    )]
+    RateLimited {
+        /// Maximum number of transfers per window
+        max_transfers: usize,
+        /// Size of the window, in applied ops
+        window: u64,
+    },
+
+    /// The transfer was already applied or initiated
+    #[error("Transfer {id:?} was already applied or initiated")]
+    DuplicateTransfer {
+        /// Id of the transfer
+        id: TransferId,
+    },
+
+    /// No pending transfer has the given id
+    #[error("No pending transfer has id {id:?}")]
+    PendingTransferDoesNotExist {
+        /// Id of the transfer
+        id: TransferId,
+    },
+
+    /// Account metadata exceeds the maximum allowed size
+    #[error("Account metadata exceeds the maximum allowed size")]
+    MetadataTooLarge {
+        /// Size of the account metadata had the op been applied
+        size: usize,
+        /// Maximum allowed size
+        max_size: usize,
+    },
+
+    /// The alias is already registered
+    #[error("The alias {alias:?} is already registered")]
+    AliasTaken {
+        /// The alias
+        alias: String,
+    },
+
+    /// The alias is empty, too long, or has characters other than lowercase
+    /// ASCII letters, digits, `-` and `_`
+    #[error("Invalid alias {alias:?}")]
+    InvalidAlias {
+        /// The alias
+        alias: String,
+    },
+
+    /// No account is registered under the alias
+    #[error("No account is registered under the alias {alias:?}")]
+    UnknownAlias {
+        /// The alias
+        alias: String,
+    },
+
+    /// The actor that initiated the recovery is not the account's recovery actor
+    #[error("The actor that initiated the recovery ({initiator:?}) is not the recovery actor of account {owner:?}")]
+    NotInitiatedByRecoveryActor {
+        /// Actor that initiated the recovery
+        initiator: A,
+        /// Owner of the account being recovered
+        owner: A,
+    },
+
+    /// The ledger already holds as many accounts as allowed
+    #[error("The ledger already holds {max_accounts} accounts, the most allowed")]
+    AccountLimitReached {
+        /// Maximum number of accounts
+        max_accounts: usize,
+    },
+
+    /// The op was rejected by the bank's validation policy
+    #[error("Rejected by validation policy: {0}")]
+    PolicyRejected(String),
 }
 
-impl<A: Ord + Hash + Debug + Clone + 'static + Serialize> BRBDataType<A> for Bank<A> {
-    type Op = Op<A>;
-    type ValidationError = ValidationError;
+/// Enumeration of advisory warnings reported by `Bank::validate_verbose`
+///
+/// Unlike validation errors, warnings don't make an op invalid.  New
+/// warnings may be added, so this enum is `non_exhaustive`.
+#[derive(Error, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ValidationWarning<A: Debug> {
+    /// The transfer moves no money and pays no fee
+    #[error("The transfer moves no money")]
+    ZeroAmount,
 
-    fn new(id: A) -> Self {
-        Bank {
-            id,
-            deps: Default::default(),
-            initial_balances: Default::default(),
-            hist: Default::default(),
-        }
+    /// The transfer is to the sending account itself
+    #[error("The transfer is from {account:?} to itself")]
+    SelfTransfer {
+        /// The sending and receiving account
+        account: A,
+    },
+
+    /// The transfer, fee included, leaves the sending account empty
+    #[error("The transfer leaves the balance of {account:?} at zero")]
+    DrainsAccount {
+        /// The sending account
+        account: A,
+    },
+
+    /// The transfer carries more than `MANY_DEPS_WARNING_THRESHOLD`
+    /// dependencies, making it large on the wire; see `compact`
+    #[error("The transfer carries {deps} dependencies")]
+    ManyDeps {
+        /// Number of dependencies carried
+        deps: usize,
+    },
+}
+
+/// Whether an op can be applied to the current state, as reported by
+/// `Bank::classify_ops`
+#[derive(Debug, PartialEq, Eq)]
+pub enum OpStatus<A: Debug, M: MoneyT = Money> {
+    /// The op is valid and would be applied
+    Applicable,
+    /// The op was already applied, so applying it again would be skipped
+    AlreadyApplied,
+    /// The op fails validation
+    Blocked(ValidationError<A, M>),
+}
+
+/// The result of `Bank::validate_verbose`: the validation result, along
+/// with advisory warnings
+pub type VerboseValidation<A, M = Money> =
+    (Result<(), ValidationError<A, M>>, Vec<ValidationWarning<A>>);
+
+/// Enumeration of errors encountered importing a `LedgerExport`
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum ImportError<A: Debug, M: MoneyT = Money> {
+    /// A transfer involves an account missing from the export
+    #[error("A transfer involves an account ({account:?}) missing from the export")]
+    UnknownAccount {
+        /// The missing account
+        account: A,
+    },
+
+    /// The exported balance doesn't match the balance computed from the transfers
+    #[error("The exported balance of {account:?} ({exported}) doesn't match the balance computed from the transfers ({computed:?})")]
+    BalanceMismatch {
+        /// The account
+        account: A,
+        /// Exported balance
+        exported: M,
+        /// Balance computed from the transfers, `None` if it drops below zero or overflows
+        computed: Option<M>,
+    },
+}
+
+/// Enumeration of inconsistencies found by `Bank::check_invariants`
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum InvariantViolation<A: Debug> {
+    /// A transfer involves an account that was never opened
+    #[error("Transfer {transfer:?} involves an account ({account:?}) that was never opened")]
+    UnknownAccount {
+        /// Id of the transfer
+        transfer: TransferId,
+        /// The missing account
+        account: A,
+    },
+
+    /// A transfer is missing from the history of its sender or recipient
+    #[error("Transfer {transfer:?} is missing from the history of {account:?}")]
+    MissingFromHistory {
+        /// Id of the transfer
+        transfer: TransferId,
+        /// The account whose history lacks the transfer
+        account: A,
+    },
+
+    /// A dependency of a transfer is missing from its sender's history
+    #[error(
+        "Dependency {dependency:?} of transfer {transfer:?} is missing from the sender's history"
+    )]
+    MissingDependency {
+        /// Id of the transfer
+        transfer: TransferId,
+        /// Id of the missing dependency
+        dependency: TransferId,
+    },
+
+    /// The account's history drives its balance below zero
+    #[error("The balance of {account:?} is below zero")]
+    NegativeBalance {
+        /// The overdrawn account
+        account: A,
+    },
+
+    /// The account's history overflows its balance
+    #[error("The balance of {account:?} overflows")]
+    BalanceOverflow {
+        /// The overflowing account
+        account: A,
+    },
+}
+
+/// Enumeration of errors preventing `Bank::merge` from merging two replicas
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum MergeError<A: Debug> {
+    /// The replicas disagree on the initial balance of an account
+    #[error("The replicas disagree on the initial balance of {account:?}")]
+    InitialBalanceMismatch {
+        /// The account
+        account: A,
+    },
+
+    /// The replicas disagree on the currency of an account
+    #[error("The replicas disagree on the currency of {account:?}")]
+    CurrencyMismatch {
+        /// The account
+        account: A,
+    },
+
+    /// The replicas disagree on the recovery actor of an account
+    #[error("The replicas disagree on the recovery actor of {account:?}")]
+    RecoveryActorMismatch {
+        /// The account
+        account: A,
+    },
+
+    /// The replicas resolve an alias to different accounts
+    #[error("The replicas resolve the alias {alias} to different accounts")]
+    AliasMismatch {
+        /// The alias
+        alias: String,
+    },
+
+    /// The merged history drives the account's balance below zero
+    #[error("The merged history drives the balance of {account:?} below zero")]
+    NegativeBalance {
+        /// The overdrawn account
+        account: A,
+    },
+
+    /// The merged history overflows the account's balance
+    #[error("The merged history overflows the balance of {account:?}")]
+    BalanceOverflow {
+        /// The overflowing account
+        account: A,
+    },
+}
+
+/// Enumeration of errors preventing an op from being applied
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum ApplyError<A: Debug> {
+    /// The account does not exist
+    #[error("The account ({account:?}) does not exist")]
+    AccountDoesNotExist {
+        /// The missing account
+        account: A,
+    },
+
+    /// The account already exists
+    #[error("The account ({account:?}) already exists")]
+    AccountAlreadyExists {
+        /// The existing account
+        account: A,
+    },
+
+    /// Applying the op would drive the account's balance below zero
+    #[error("Applying the op would drive the balance of {account:?} below zero")]
+    BalanceUnderflow {
+        /// The overdrawn account
+        account: A,
+    },
+
+    /// Applying the op would overflow the account's balance
+    #[error("Applying the op would overflow the balance of {account:?}")]
+    BalanceOverflow {
+        /// The overflowing account
+        account: A,
+    },
+
+    /// No pending transfer has the given id
+    #[error("No pending transfer has id {id:?}")]
+    PendingTransferDoesNotExist {
+        /// Id of the transfer
+        id: TransferId,
+    },
+
+    /// The delegated transfer exceeds the spender's allowance
+    #[error("The delegated transfer exceeds the allowance of {spender:?} over {owner:?}")]
+    AllowanceExceeded {
+        /// Account owner
+        owner: A,
+        /// Actor spending from the account
+        spender: A,
+    },
+
+    /// The account is frozen, so it can't be credited
+    #[error("The account ({account:?}) is frozen")]
+    AccountFrozen {
+        /// The frozen account
+        account: A,
+    },
+}
+
+// Validation and application are inherent, rather than only provided by
+// `BRBDataType`, so that they are available without `std`.
+impl<A: Ord + Hash + Debug + Clone + 'static + Serialize, M: MoneyT> Bank<A, M> {
+    /// Creates a bank for `id` with the default configuration
+    pub fn new(id: A) -> Self {
+        Self::with_config(id, BankConfig::default())
     }
 
     /// Protection against Byzantines
-    fn validate(&self, source: &A, op: &Self::Op) -> Result<(), Self::ValidationError> {
+    pub fn validate(&self, source: &A, op: &Op<A, M>) -> Result<(), ValidationError<A, M>> {
         match op {
-            Op::Transfer(transfer) => {
-                if source != &transfer.from {
-                    Err(ValidationError::NotInitiatedByAccountOwner)
-                } else if !self.initial_balances.contains_key(&transfer.from) {
-                    Err(ValidationError::FromAccountDoesNotExist)
-                } else if !self.initial_balances.contains_key(&transfer.to) {
-                    Err(ValidationError::ToAccountDoesNotExist)
-                } else if self.balance(&transfer.from) < transfer.amount {
-                    Err(ValidationError::InsufficientFunds {
-                        balance: self.balance(&transfer.from),
-                        transfer_amount: transfer.amount,
+            Op::Transfer(transfer) | Op::InitiateTransfer(transfer) => {
+                self.validate_transfer(source, transfer)
+            }
+            Op::OpenAccount { owner, balance, .. } => {
+                if source != owner {
+                    Err(ValidationError::NotInitiatedByAccountOwner {
+                        initiator: source.clone(),
+                        owner: owner.clone(),
+                    })
+                } else if self.initial_balances.contains_key(owner) {
+                    Err(ValidationError::OwnerAlreadyHasAnAccount)
+                } else if *balance > self.max_initial_balance {
+                    Err(ValidationError::InitialBalanceTooHigh {
+                        balance: *balance,
+                        max_initial_balance: self.max_initial_balance,
+                    })
+                } else {
+                    self.check_account_limit(1)
+                }
+            }
+            Op::BatchOpen { accounts } => {
+                self.check_admin(source)?;
+                let mut owners = BTreeSet::new();
+                for (owner, balance) in accounts.iter() {
+                    if !owners.insert(owner) {
+                        return Err(ValidationError::AccountListedTwice {
+                            account: owner.clone(),
+                        });
+                    }
+                    if self.initial_balances.contains_key(owner) {
+                        return Err(ValidationError::OwnerAlreadyHasAnAccount);
+                    }
+                    if *balance > self.max_initial_balance {
+                        return Err(ValidationError::InitialBalanceTooHigh {
+                            balance: *balance,
+                            max_initial_balance: self.max_initial_balance,
+                        });
+                    }
+                }
+                self.check_account_limit(accounts.len())
+            }
+            Op::SetAccountMetadata { owner, key, value } => {
+                let size = self.metadata_size_with(owner, key, value);
+                if source != owner {
+                    Err(ValidationError::NotInitiatedByAccountOwner {
+                        initiator: source.clone(),
+                        owner: owner.clone(),
+                    })
+                } else if !self.account_exists(owner) {
+                    Err(ValidationError::FromAccountDoesNotExist {
+                        from: owner.clone(),
+                    })
+                } else if size > MAX_ACCOUNT_METADATA_SIZE {
+                    Err(ValidationError::MetadataTooLarge {
+                        size,
+                        max_size: MAX_ACCOUNT_METADATA_SIZE,
                     })
-                } else if !transfer.deps.is_subset(&self.history(&transfer.from)) {
-                    Err(ValidationError::MissingDependentOps)
                 } else {
                     Ok(())
                 }
             }
-            Op::OpenAccount { owner, .. } => {
+            Op::TransferFrom { spender, transfer } => {
+                self.validate_transfer_from(source, spender, transfer)
+            }
+            Op::Approve { owner, .. } => {
                 if source != owner {
-                    Err(ValidationError::NotInitiatedByAccountOwner)
-                } else if self.initial_balances.contains_key(owner) {
-                    Err(ValidationError::OwnerAlreadyHasAnAccount)
+                    Err(ValidationError::NotInitiatedByAccountOwner {
+                        initiator: source.clone(),
+                        owner: owner.clone(),
+                    })
+                } else if !self.account_exists(owner) {
+                    // The spender needs no account of its own
+                    Err(ValidationError::FromAccountDoesNotExist {
+                        from: owner.clone(),
+                    })
                 } else {
                     Ok(())
                 }
             }
-        }
+            Op::FinalizeTransfer { id } => {
+                let transfer = self.check_pending_sender(source, id)?;
+                // The recipient may have been frozen, or closed, since the
+                // transfer was initiated.  The sender can still cancel it.
+                if self.is_frozen(&transfer.to) {
+                    return Err(ValidationError::AccountFrozen {
+                        account: transfer.to.clone(),
+                    });
+                }
+                self.check_recipient_balance(transfer)
+            }
+            Op::CancelTransfer { id } => self.check_pending_sender(source, id).map(|_| ()),
+            Op::SetFrozen { owner, frozen } => {
+                self.check_admin(source)?;
+                if !self.account_exists(owner) {
+                    Err(ValidationError::FromAccountDoesNotExist {
+                        from: owner.clone(),
+                    })
+                } else if !*frozen && self.is_closed(owner) {
+                    Err(ValidationError::AccountClosed {
+                        account: owner.clone(),
+                    })
+                } else {
+                    Ok(())
+                }
+            }
+            Op::CloseAndSweep { owner, destination } => {
+                if source != owner {
+                    return Err(ValidationError::NotInitiatedByAccountOwner {
+                        initiator: source.clone(),
+                        owner: owner.clone(),
+                    });
+                }
+                if !self.account_exists(owner) {
+                    return Err(ValidationError::FromAccountDoesNotExist {
+                        from: owner.clone(),
+                    });
+                }
+                if !self.account_exists(destination) {
+                    return Err(ValidationError::ToAccountDoesNotExist {
+                        to: destination.clone(),
+                    });
+                }
+                if owner == destination {
+                    return Err(ValidationError::SweepToClosingAccount {
+                        account: owner.clone(),
+                    });
+                }
+                for account in [owner, destination].iter() {
+                    if self.is_frozen(account) {
+                        return Err(ValidationError::AccountFrozen {
+                            account: (*account).clone(),
+                        });
+                    }
+                }
+                if self
+                    .pending_transfers
+                    .values()
+                    .any(|t| &t.from == owner || &t.to == owner)
+                {
+                    return Err(ValidationError::AccountHasPendingTransfers {
+                        account: owner.clone(),
+                    });
+                }
+                // The sweep only moves a positive balance, so closing would
+                // write off a liability's debt
+                match self.signed_balance(owner) {
+                    Some(balance) if balance < 0 => {
+                        return Err(ValidationError::AccountInDebt {
+                            account: owner.clone(),
+                            balance,
+                        })
+                    }
+                    _ => (),
+                }
+                match self.sweep_transfer(owner, destination.clone()) {
+                    Some(sweep) => {
+                        self.check_currency(&sweep)?;
+                        self.check_recipient_balance(&sweep)
+                    }
+                    None => Ok(()),
+                }
+            }
+            Op::AdjustBalance { owner, .. } => {
+                self.check_admin(source)?;
+                if self.account_exists(owner) {
+                    Ok(())
+                } else {
+                    Err(ValidationError::FromAccountDoesNotExist {
+                        from: owner.clone(),
+                    })
+                }
+            }
+            Op::RecoverAccount { owner, new_owner } => {
+                if !self.account_exists(owner) {
+                    Err(ValidationError::FromAccountDoesNotExist {
+                        from: owner.clone(),
+                    })
+                } else if self.recovery_actors.get(owner) != Some(source) {
+                    Err(ValidationError::NotInitiatedByRecoveryActor {
+                        initiator: source.clone(),
+                        owner: owner.clone(),
+                    })
+                } else if self.is_frozen(owner) {
+                    Err(ValidationError::AccountFrozen {
+                        account: owner.clone(),
+                    })
+                } else if self.account_exists(new_owner) {
+                    Err(ValidationError::OwnerAlreadyHasAnAccount)
+                } else {
+                    match self.signed_balance(owner) {
+                        // The sweep only moves a positive balance
+                        Some(balance) if balance < 0 => Err(ValidationError::AccountInDebt {
+                            account: owner.clone(),
+                            balance,
+                        }),
+                        _ => self.check_account_limit(1),
+                    }
+                }
+            }
+            Op::RegisterAlias { owner, alias } => {
+                if source != owner {
+                    Err(ValidationError::NotInitiatedByAccountOwner {
+                        initiator: source.clone(),
+                        owner: owner.clone(),
+                    })
+                } else if !self.account_exists(owner) {
+                    Err(ValidationError::FromAccountDoesNotExist {
+                        from: owner.clone(),
+                    })
+                } else if self.is_frozen(owner) {
+                    Err(ValidationError::AccountFrozen {
+                        account: owner.clone(),
+                    })
+                } else {
+                    self.check_alias(alias)
+                }
+            }
+            Op::MultiTransfer { transfers } => self.validate_multi_transfer(source, transfers),
+        }?;
+
+        // Custom rules only see ops that passed the built-in checks
+        self.check_policy(source, op)
     }
 
     /// Executed once an op has been validated
-    fn apply(&mut self, op: Self::Op) {
-        match op {
-            Op::Transfer(transfer) => {
-                // Update the history for the outgoing account
-                self.hist
-                    .entry(transfer.from.clone())
-                    .or_default()
-                    .insert(transfer.clone());
-
-                // Update the history for the incoming account
-                self.hist
-                    .entry(transfer.to.clone())
-                    .or_default()
-                    .insert(transfer.clone());
-
-                // Add this transfer to self.deps only if we are recipient.
-                if transfer.to == self.id {
-                    self.deps.insert(transfer.clone());
-                }
-
-                // remove transfer.deps from self.deps only if we are sender.
-                if transfer.from == self.id {
-                    // In the paper, deps are cleared after the broadcast completes in
-                    // self.transfer.
-                    // Here we break up the initiation of the transfer from the completion.
-                    // We move the clearing of the deps here since this is where we now know
-                    // the transfer was successfully validated and applied by the network.
-                    for prior_transfer in transfer.deps.iter() {
-                        // for each dependency listed in the transfer
-                        // we remove it from the set of dependencies for a transfer
-                        self.deps.remove(prior_transfer);
+    ///
+    /// Delegates to `apply_with_effects`, passing the effects on to
+    /// observers, if any.  An op that would corrupt state is logged and
+    /// dropped, leaving the bank untouched.
+    pub fn apply(&mut self, op: Op<A, M>) {
+        #[cfg(debug_assertions)]
+        if self.debug_validation {
+            self.assert_valid(&op);
+        }
+
+        let observed = if self.observers.is_empty() {
+            None
+        } else {
+            Some((op.clone(), self.applied_ops))
+        };
+        match self.apply_with_effects(op) {
+            Ok(effect) => {
+                if let Some((op, applied_ops)) = observed {
+                    // Duplicates are skipped without counting as applied
+                    if self.applied_ops != applied_ops {
+                        self.observers.notify(&op, &effect);
                     }
                 }
             }
-            Op::OpenAccount { owner, balance } => {
-                info!(
-                    "[BANK] opening new account for {:?} with ${}",
-                    owner, balance
-                );
-                self.initial_balances.insert(owner, balance);
-            }
+            Err(e) => error!(error:% = e; "[BANK] refusing to apply op"),
         }
     }
 }
+
+#[cfg(feature = "std")]
+impl<A: Ord + Hash + Debug + Clone + 'static + Serialize, M: MoneyT> BRBDataType<A> for Bank<A, M> {
+    type Op = Op<A, M>;
+    type ValidationError = ValidationError<A, M>;
+
+    fn new(id: A) -> Self {
+        Bank::new(id)
+    }
+
+    fn validate(&self, source: &A, op: &Self::Op) -> Result<(), Self::ValidationError> {
+        Bank::validate(self, source, op)
+    }
+
+    fn apply(&mut self, op: Self::Op) {
+        Bank::apply(self, op)
+    }
+}
@@ -0,0 +1,50 @@
+//! AT2 BankConfig
+
+use serde::{Deserialize, Serialize};
+
+use super::{ArithmeticMode, Money, MoneyT};
+
+/// Construction-time configuration of a `Bank`, as taken by
+/// `Bank::with_config` and returned by `Bank::config`.
+///
+/// Replicas that disagree on their configuration reach different verdicts
+/// on the same ops and diverge, so all replicas must be configured alike.
+/// The config is serializable so that it can be distributed to them and
+/// compared.  The validation policy is code rather than data, so it is not
+/// part of the config and is configured with `Bank::with_validation_policy`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BankConfig<A, M: MoneyT = Money> {
+    /// Account credited with transfer fees, see `Bank::with_fee_collector`
+    pub fee_collector: Option<A>,
+    /// Actor allowed to perform administrative operations, see `Bank::with_admin`
+    pub admin: Option<A>,
+    /// Maximum number of dependencies a transfer may carry, see `Bank::with_max_deps`
+    pub max_deps: usize,
+    /// Largest balance an account may be opened with, see `Bank::with_max_initial_balance`
+    pub max_initial_balance: M,
+    /// Maximum number of accounts, if capped, see `Bank::with_max_accounts`
+    pub max_accounts: Option<usize>,
+    /// How balance arithmetic handles out-of-range results, see `Bank::with_arithmetic_mode`
+    pub arithmetic_mode: ArithmeticMode,
+    /// Maximum number of transfers a sender may make within a window of
+    /// applied ops, and the size of that window, see `Bank::with_rate_limit`
+    pub rate_limit: Option<(usize, u64)>,
+    /// Whether `apply` re-validates ops in debug builds, see `Bank::with_debug_validation`
+    pub debug_validation: bool,
+}
+
+impl<A, M: MoneyT> Default for BankConfig<A, M> {
+    /// The configuration of a bank created with `Bank::new`
+    fn default() -> Self {
+        Self {
+            fee_collector: None,
+            admin: None,
+            max_deps: usize::MAX,
+            max_initial_balance: M::zero(),
+            max_accounts: None,
+            arithmetic_mode: ArithmeticMode::default(),
+            rate_limit: None,
+            debug_validation: false,
+        }
+    }
+}
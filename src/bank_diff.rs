@@ -0,0 +1,79 @@
+//! AT2 BankDiff
+
+use alloc::collections::{BTreeMap, BTreeSet};
+use core::{
+    fmt::{self, Debug},
+    hash::Hash,
+};
+
+use super::{Money, MoneyT, Transfer};
+
+/// The divergence between two `Bank` replicas, as reported by `Bank::diff`.
+///
+/// "self" refers to the bank `diff` was called on and "other" to its argument.
+#[derive(Clone, PartialEq, Eq)]
+pub struct BankDiff<A: Ord + Hash, M: MoneyT = Money> {
+    /// Accounts opened in self but not in other
+    pub accounts_only_in_self: BTreeSet<A>,
+
+    /// Accounts opened in other but not in self
+    pub accounts_only_in_other: BTreeSet<A>,
+
+    /// Accounts present in both whose balances differ, as (self, other) balances
+    pub balance_mismatches: BTreeMap<A, (M, M)>,
+
+    /// Transfers recorded in self but not in other
+    pub transfers_only_in_self: BTreeSet<Transfer<A, M>>,
+
+    /// Transfers recorded in other but not in self
+    pub transfers_only_in_other: BTreeSet<Transfer<A, M>>,
+}
+
+impl<A: Ord + Hash, M: MoneyT> BankDiff<A, M> {
+    /// Returns true if the two banks agree.
+    pub fn is_empty(&self) -> bool {
+        self.accounts_only_in_self.is_empty()
+            && self.accounts_only_in_other.is_empty()
+            && self.balance_mismatches.is_empty()
+            && self.transfers_only_in_self.is_empty()
+            && self.transfers_only_in_other.is_empty()
+    }
+}
+
+impl<A: Ord + Hash + Debug, M: MoneyT> Debug for BankDiff<A, M> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_empty() {
+            return write!(f, "BankDiff: banks agree");
+        }
+
+        writeln!(f, "BankDiff:")?;
+        for actor in self.accounts_only_in_self.iter() {
+            writeln!(f, "  account {:?} only in self", actor)?;
+        }
+        for actor in self.accounts_only_in_other.iter() {
+            writeln!(f, "  account {:?} only in other", actor)?;
+        }
+        for (actor, (ours, theirs)) in self.balance_mismatches.iter() {
+            writeln!(
+                f,
+                "  balance of {:?} is ${} in self but ${} in other",
+                actor, ours, theirs
+            )?;
+        }
+        for t in self.transfers_only_in_self.iter() {
+            writeln!(
+                f,
+                "  transfer of ${} from {:?} to {:?} only in self",
+                t.amount, t.from, t.to
+            )?;
+        }
+        for t in self.transfers_only_in_other.iter() {
+            writeln!(
+                f,
+                "  transfer of ${} from {:?} to {:?} only in other",
+                t.amount, t.from, t.to
+            )?;
+        }
+        Ok(())
+    }
+}
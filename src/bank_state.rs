@@ -0,0 +1,108 @@
+//! AT2 BankState
+
+use alloc::{
+    collections::{BTreeMap, BTreeSet},
+    string::String,
+    vec::Vec,
+};
+use core::hash::Hash;
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    BalanceAdjustment, BankConfig, Currency, Money, MoneyT, SnapshotDelta, Transfer, TransferId,
+};
+
+/// A serializable snapshot of the ledger held by a `Bank`, along with the
+/// configuration all replicas agree on.
+///
+/// Unlike `Bank`, this carries no replica-specific state (the owning actor
+/// and its pending dependencies), so it can be exchanged between replicas.
+/// The validation policy is code rather than data, so it is not part of it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BankState<A: Ord + Hash, M: MoneyT = Money> {
+    /// The initial balances of every open account
+    pub initial_balances: BTreeMap<A, M>,
+
+    /// The currency of every open account
+    pub currencies: BTreeMap<A, Currency>,
+
+    /// Position of every open account in the order accounts were opened
+    pub open_indices: BTreeMap<A, u64>,
+
+    /// Set of all transfers, by actor
+    pub hist: BTreeMap<A, BTreeSet<Transfer<A, M>>>,
+
+    /// The id each transfer stored compacted in `hist` was applied with,
+    /// by the id of its compacted form
+    #[serde(default)]
+    pub transfer_ids: BTreeMap<TransferId, TransferId>,
+
+    /// Account metadata, by actor
+    pub metadata: BTreeMap<A, BTreeMap<String, String>>,
+
+    /// The account each registered alias resolves to
+    pub aliases: BTreeMap<String, A>,
+
+    /// Number of ops applied, the logical clock for time-locked transfers
+    pub applied_ops: u64,
+
+    /// Accounts that can neither send nor receive transfers
+    pub frozen: BTreeSet<A>,
+
+    /// Number of times each account was frozen or unfrozen
+    #[serde(default)]
+    pub frozen_versions: BTreeMap<A, u64>,
+
+    /// Accounts closed for good
+    pub closed: BTreeSet<A>,
+
+    /// Accounts opened as liabilities, whose balance may be negative
+    pub liabilities: BTreeSet<A>,
+
+    /// Transfers initiated but not yet finalized or cancelled, by id
+    pub pending_transfers: BTreeMap<TransferId, Transfer<A, M>>,
+
+    /// Ids of the pending transfers that were cancelled
+    #[serde(default)]
+    pub cancelled_transfers: BTreeSet<TransferId>,
+
+    /// Amounts spenders may transfer out of accounts, by (owner, spender)
+    pub allowances: BTreeMap<(A, A), M>,
+
+    /// Number of times each allowance was approved, by (owner, spender)
+    #[serde(default)]
+    pub allowance_versions: BTreeMap<(A, A), u64>,
+
+    /// The applied op count right after each of a sender's latest
+    /// transfers, oldest first, tracked for rate limiting
+    pub recent_transfers: BTreeMap<A, Vec<u64>>,
+
+    /// Actor allowed to recover each account that designated one
+    pub recovery_actors: BTreeMap<A, A>,
+
+    /// Sequence number of the next outgoing transfer of each checkpointed
+    /// account at the time of its checkpoint
+    pub checkpoints: BTreeMap<A, u64>,
+    /// Administrative corrections of account balances, by actor
+    pub adjustments: BTreeMap<A, BTreeSet<BalanceAdjustment<M>>>,
+
+    /// The configuration of the bank, restored along with the ledger so
+    /// that a restored bank validates ops like its peers
+    #[serde(default)]
+    pub config: BankConfig<A, M>,
+}
+
+impl<A: Ord + Hash + Clone, M: MoneyT> BankState<A, M> {
+    /// Returns what changed since `base`, an earlier snapshot of the ledger,
+    /// so a replica holding `base` can catch up without a full snapshot.
+    pub fn diff(&self, base: &BankState<A, M>) -> SnapshotDelta<A, M> {
+        SnapshotDelta::between(base, self)
+    }
+
+    /// Applies a delta computed by `diff` against this snapshot, bringing it
+    /// up to date with the snapshot the delta was computed from.
+    pub fn apply_delta(&mut self, delta: SnapshotDelta<A, M>) {
+        delta.apply_to(self)
+    }
+}
@@ -0,0 +1,53 @@
+//! AT2 BankView
+
+use core::{fmt::Debug, hash::Hash};
+
+use serde::Serialize;
+
+use super::{Bank, Money, MoneyT, Transfer};
+
+/// A read-only view of a `Bank`, as returned by `Bank::view`.
+///
+/// The view borrows the bank rather than cloning its history, and only
+/// exposes read methods, so it can be handed to readers, including other
+/// threads, without risk of mutation.
+#[derive(Debug)]
+pub struct BankView<'a, A: Ord + Hash, M: MoneyT = Money> {
+    bank: &'a Bank<A, M>,
+}
+
+impl<'a, A: Ord + Hash, M: MoneyT> Clone for BankView<'a, A, M> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'a, A: Ord + Hash, M: MoneyT> Copy for BankView<'a, A, M> {}
+
+impl<'a, A: Ord + Hash + Debug + Clone + Serialize, M: MoneyT> BankView<'a, A, M> {
+    pub(crate) fn new(bank: &'a Bank<A, M>) -> Self {
+        Self { bank }
+    }
+
+    /// Returns an account's present balance.
+    ///
+    /// Panics if the account does not exist, see `Bank::balance`.
+    pub fn balance(&self, actor: &A) -> M {
+        self.bank.balance(actor)
+    }
+
+    /// Returns every open account, ordered by actor.
+    pub fn accounts(&self) -> impl Iterator<Item = &'a A> {
+        self.bank.accounts()
+    }
+
+    /// Returns the transfers sent or received by an account.
+    pub fn transfers_for(&self, actor: &A) -> impl Iterator<Item = &'a Transfer<A, M>> {
+        self.bank.history_iter(actor)
+    }
+
+    /// Returns the total amount of money held across all accounts.
+    pub fn total_supply(&self) -> M {
+        self.bank.total_supply()
+    }
+}
@@ -0,0 +1,59 @@
+//! AT2 JSON encoding
+//!
+//! Helpers converting `Op` to and from JSON, e.g. for HTTP APIs, with
+//! errors telling an unknown kind of op apart from a malformed one.  Ops
+//! are encoded with their serde representation.  Only built with the
+//! `json` feature.
+
+use alloc::string::{String, ToString};
+use core::hash::Hash;
+
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::error::Category;
+use thiserror::Error;
+
+use super::{MoneyT, Op};
+
+/// Enumeration of errors encountered parsing an `Op` from JSON
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum OpParseError {
+    /// The input is not valid JSON
+    #[error("Invalid JSON: {0}")]
+    Syntax(String),
+
+    /// The input is not a kind of op this crate knows of
+    #[error("Unknown op: {0}")]
+    UnknownVariant(String),
+
+    /// A field of the op is missing, unknown or of the wrong type
+    #[error("Malformed op: {0}")]
+    InvalidField(String),
+}
+
+impl From<serde_json::Error> for OpParseError {
+    fn from(e: serde_json::Error) -> Self {
+        match e.classify() {
+            Category::Data if e.to_string().starts_with("unknown variant") => {
+                OpParseError::UnknownVariant(e.to_string())
+            }
+            Category::Data => OpParseError::InvalidField(e.to_string()),
+            Category::Io | Category::Syntax | Category::Eof => OpParseError::Syntax(e.to_string()),
+        }
+    }
+}
+
+impl<A, M> Op<A, M>
+where
+    A: Ord + Hash + Serialize + DeserializeOwned,
+    M: MoneyT + DeserializeOwned,
+{
+    /// Parses an op from JSON.
+    pub fn from_json(s: &str) -> Result<Self, OpParseError> {
+        Ok(serde_json::from_str(s)?)
+    }
+
+    /// Returns the JSON encoding of the op.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("[ERROR] Failed to serialize")
+    }
+}
@@ -0,0 +1,44 @@
+//! AT2 LedgerExport
+
+use alloc::vec::Vec;
+use core::hash::Hash;
+
+use serde::{Deserialize, Serialize};
+
+use super::{AccountKind, Currency, Money, MoneyT, Transfer};
+
+/// A flat export of the ledger held by a `Bank`, for consumption by
+/// external tools such as accounting software.
+///
+/// Unlike `BankState`, which is meant for syncing replicas, this lists each
+/// account's current balance alongside a single, de-duplicated list of
+/// transfers.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LedgerExport<A: Ord + Hash, M: MoneyT = Money> {
+    /// Every open account, ordered by actor
+    pub accounts: Vec<AccountExport<A, M>>,
+
+    /// Every transfer in the ledger, each listed once
+    pub transfers: Vec<Transfer<A, M>>,
+}
+
+/// An account entry of a `LedgerExport`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AccountExport<A, M: MoneyT = Money> {
+    /// Account owner
+    pub actor: A,
+
+    /// Currency the account is denominated in
+    pub currency: Currency,
+
+    /// Balance the account was opened with
+    pub initial_balance: M,
+
+    /// Balance after all transfers in the export, read as zero while a
+    /// liability account's balance is negative
+    pub current_balance: M,
+
+    /// Whether the account holds assets or liabilities
+    #[serde(default)]
+    pub kind: AccountKind,
+}
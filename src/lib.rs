@@ -10,7 +10,7 @@
 //! This library contains:
 //!
 //! 1. An implementation of AT2: Asynchronous Trustworthy Transfers
-//! 2. A BRBDataType wrapper around AT2
+//! 2. A BRBDataType wrapper around AT2, with the default `std` feature
 //!
 //! The wrapper enables AT2 operations to be transmitted in a BFT manner using
 //! Byzantine Reliable Broadcast.
@@ -27,16 +27,68 @@
 //! performance bottlenecks if run with any significant number of transfers.
 //! Some of these bottlenecks are commented in the code.
 
+#![no_std]
 #![deny(missing_docs)]
 
+// The ledger core only needs `alloc`, so that validators can run it in
+// constrained environments.  The `std` feature adds the BRB integration.
+extern crate alloc;
+
 pub mod money;
-pub use money::Money;
+pub use money::{ArithmeticMode, Currency, Money, MoneyT, ParseMoneyError};
+
+pub mod account_kind;
+pub use account_kind::AccountKind;
+
+pub mod account_activity;
+pub use account_activity::AccountActivity;
+
+pub mod applied_effect;
+pub use applied_effect::AppliedEffect;
+
+pub mod balance_adjustment;
+pub use balance_adjustment::BalanceAdjustment;
 
 pub mod bank;
 pub use bank::Bank;
 
+pub mod bank_config;
+pub use bank_config::BankConfig;
+
+pub mod bank_state;
+pub use bank_state::BankState;
+
+pub mod bank_diff;
+pub use bank_diff::BankDiff;
+
+pub mod bank_view;
+pub use bank_view::BankView;
+
+pub mod ledger_export;
+pub use ledger_export::{AccountExport, LedgerExport};
+
 pub mod op;
 pub use op::Op;
 
+mod op_serde;
+
+pub mod op_observer;
+pub use op_observer::OpObserver;
+
+pub mod snapshot_delta;
+pub use snapshot_delta::SnapshotDelta;
+
 pub mod transfer;
-pub use transfer::Transfer;
+pub use transfer::{Transfer, TransferBuilder, TransferId};
+
+pub mod validation_policy;
+pub use validation_policy::ValidationPolicy;
+
+#[cfg(feature = "proto")]
+pub mod proto;
+
+#[cfg(feature = "json")]
+pub mod json;
+
+#[cfg(feature = "testing")]
+mod arbitrary;
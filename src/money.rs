@@ -1,5 +1,169 @@
 //! AT2 Money
 
+use alloc::{format, string::String};
+use core::{
+    fmt::{Debug, Display},
+    hash::Hash,
+};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
 /// We define Money as a u64.  This is a common choice for cryptocurrencies
 /// and good enough for our purposes.
 pub type Money = u64;
+
+/// Identifies the asset an account is denominated in.
+pub type Currency = u32;
+
+/// The currency accounts are denominated in unless specified otherwise
+pub const DEFAULT_CURRENCY: Currency = 0;
+
+/// How balance arithmetic handles a result below zero or above the maximum
+/// amount, which a consistent ledger never produces.
+///
+/// Only `Panic` preserves the invariant checking the ledger has always done:
+/// an out-of-range balance means the history is corrupt, and panicking stops
+/// the replica before it acts on it.  `Saturate` and `Error` are for
+/// resilience, keeping a replica running over state it cannot fully trust.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum ArithmeticMode {
+    /// Panic, treating the out-of-range balance as a broken invariant.
+    /// This is the default.
+    #[default]
+    Panic,
+
+    /// Clamp the balance to zero on underflow, or to the maximum amount on overflow
+    Saturate,
+
+    /// Surface a recoverable error to the caller
+    Error,
+}
+
+/// An unsigned integer type used to represent amounts of money.
+///
+/// `Money` (u64) is the default everywhere, but a ledger may pick a narrower
+/// type to save space or a wider one for high-value or high-precision use.
+pub trait MoneyT: Copy + Ord + Hash + Default + Debug + Display + Serialize + 'static {
+    /// The zero amount
+    fn zero() -> Self;
+
+    /// The largest representable amount
+    fn max_value() -> Self;
+
+    /// Addition, returning `None` on overflow
+    fn checked_add(self, other: Self) -> Option<Self>;
+
+    /// Subtraction, returning `None` on underflow
+    fn checked_sub(self, other: Self) -> Option<Self>;
+
+    /// Widens the amount to a u128
+    fn as_u128(self) -> u128;
+
+    /// Narrows a u128 to an amount, returning `None` if out of range
+    fn from_u128(value: u128) -> Option<Self>;
+
+    /// Formats an amount of minor units as a decimal string with exactly
+    /// `decimals` fractional digits, e.g. 1234 as "12.34" for 2 decimals,
+    /// 1200 as "12.00", and 12 as "12" for 0 decimals.
+    fn to_decimal_string(self, decimals: u8) -> String {
+        let decimals = usize::from(decimals);
+        let digits = format!("{:0>width$}", self.as_u128(), width = decimals + 1);
+        if decimals == 0 {
+            return digits;
+        }
+        let (whole, fraction) = digits.split_at(digits.len() - decimals);
+        format!("{}.{}", whole, fraction)
+    }
+
+    /// Parses a decimal string such as "12.34" into an amount of minor
+    /// units, with `decimals` fractional digits.
+    ///
+    /// Amounts are never rounded: fewer fractional digits than `decimals`
+    /// are padded with zeros, and extra ones are accepted only if they are
+    /// zeros, so "12.3" and "12.340" both parse as 1234 for 2 decimals while
+    /// "12.345" is rejected.  Signs, whitespace, exponents and digit
+    /// separators are rejected, as is a decimal point without digits on
+    /// both sides.
+    fn from_decimal_string(s: &str, decimals: u8) -> Result<Self, ParseMoneyError> {
+        let (whole, fraction) = match s.find('.') {
+            Some(point) => (&s[..point], Some(&s[point + 1..])),
+            None => (s, None),
+        };
+        let is_number = |part: &str| !part.is_empty() && part.bytes().all(|b| b.is_ascii_digit());
+        let fraction = match fraction {
+            Some(fraction) if !is_number(fraction) => return Err(ParseMoneyError::Invalid),
+            Some(fraction) => fraction,
+            None => "",
+        };
+        if !is_number(whole) {
+            return Err(ParseMoneyError::Invalid);
+        }
+
+        let decimals = usize::from(decimals);
+        if fraction.len() > decimals && fraction[decimals..].bytes().any(|b| b != b'0') {
+            return Err(ParseMoneyError::TooPrecise);
+        }
+        // Pad the fraction to exactly `decimals` digits
+        let fraction = format!("{:0<width$.width$}", fraction, width = decimals);
+
+        let mut value: u128 = 0;
+        for digit in whole.bytes().chain(fraction.bytes()) {
+            value = value
+                .checked_mul(10)
+                .and_then(|value| value.checked_add(u128::from(digit - b'0')))
+                .ok_or(ParseMoneyError::Overflow)?;
+        }
+        Self::from_u128(value).ok_or(ParseMoneyError::Overflow)
+    }
+}
+
+/// Enumeration of errors parsing a decimal amount of money
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseMoneyError {
+    /// The string is not a plain decimal number
+    #[error("Not a decimal amount of money")]
+    Invalid,
+
+    /// The amount has more fractional digits than the currency allows
+    #[error("The amount has more fractional digits than allowed")]
+    TooPrecise,
+
+    /// The amount is too large to be represented
+    #[error("The amount is too large")]
+    Overflow,
+}
+
+macro_rules! impl_money {
+    ($($t:ty),*) => {
+        $(
+            impl MoneyT for $t {
+                fn zero() -> Self {
+                    0
+                }
+
+                fn max_value() -> Self {
+                    <$t>::MAX
+                }
+
+                fn checked_add(self, other: Self) -> Option<Self> {
+                    <$t>::checked_add(self, other)
+                }
+
+                fn checked_sub(self, other: Self) -> Option<Self> {
+                    <$t>::checked_sub(self, other)
+                }
+
+                fn as_u128(self) -> u128 {
+                    self as u128
+                }
+
+                fn from_u128(value: u128) -> Option<Self> {
+                    core::convert::TryFrom::try_from(value).ok()
+                }
+            }
+        )*
+    };
+}
+
+impl_money!(u8, u16, u32, u64, u128);
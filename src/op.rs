@@ -1,21 +1,227 @@
 //! AT2 Op
 
-use core::hash::Hash;
+use alloc::{string::String, vec::Vec};
+use core::{
+    fmt::{self, Display},
+    hash::Hash,
+};
 
-use serde::{Deserialize, Serialize};
+use serde::Serialize;
 
-use super::{Money, Transfer};
+use super::{
+    money::DEFAULT_CURRENCY, transfer::canonical_bytes, AccountKind, Currency, Money, MoneyT,
+    Transfer, TransferId,
+};
 
 /// An AT2 operation
-#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
-pub enum Op<A: Ord + Hash> {
+///
+/// New kinds of operations are added from time to time, so this enum is
+/// `non_exhaustive`: matches outside this crate need a wildcard arm, and
+/// adding a variant is not a breaking change.
+///
+/// In human-readable formats such as JSON, ops are tagged with a `type`
+/// field and named in camelCase, e.g. `{"type":"transfer","from":0,...}`.
+/// Binary formats such as bincode keep serde's default representation.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Op<A: Ord + Hash, M: MoneyT = Money> {
     /// Transfer money between 2 accounts
-    Transfer(Transfer<A>), // Split out Transfer into it's own struct to get some more type safety in Bank struct
+    Transfer(Transfer<A, M>), // Split out Transfer into it's own struct to get some more type safety in Bank struct
     /// Open a new account
     OpenAccount {
         /// Account owner
         owner: A,
         /// Account initial balance.  typically 0.
-        balance: Money,
+        balance: M,
+        /// Currency the account is denominated in
+        currency: Currency,
+        /// Actor allowed to move the account to a new owner with
+        /// `RecoverAccount`, e.g. should the owner lose its key
+        recovery: Option<A>,
+        /// Whether the account holds assets, or liabilities whose balance
+        /// may be negative
+        kind: AccountKind,
     },
+    /// Open many accounts at once in the default currency, e.g. genesis
+    /// accounts.  Only the admin may initiate it, and either every account
+    /// is opened or none is.
+    BatchOpen {
+        /// Owner and initial balance of each account
+        accounts: Vec<(A, M)>,
+    },
+    /// Set a metadata entry on an account
+    SetAccountMetadata {
+        /// Account owner
+        owner: A,
+        /// Metadata key, e.g. "name"
+        key: String,
+        /// Metadata value
+        value: String,
+    },
+    /// Freeze or unfreeze an account
+    SetFrozen {
+        /// Account owner
+        owner: A,
+        /// Whether the account can no longer send or receive transfers
+        frozen: bool,
+    },
+    /// Set an account's balance directly, e.g. to reconcile the ledger after
+    /// a bug.  Only the admin may initiate it, so it is disabled unless an
+    /// admin is configured.  The adjustment is kept in the account's audit
+    /// trail, see `Bank::balance_adjustments`.
+    ///
+    /// The balance is set relative to the balance when the op is applied,
+    /// so replicas must apply it after the same transfers of the account,
+    /// e.g. by freezing the account first, or they diverge.
+    AdjustBalance {
+        /// Account owner
+        owner: A,
+        /// Balance of the account once adjusted
+        new_balance: M,
+    },
+    /// Authorize `spender` to transfer up to `amount` out of `owner`'s
+    /// account, replacing any previous allowance
+    Approve {
+        /// Account owner
+        owner: A,
+        /// Actor allowed to spend from the account
+        spender: A,
+        /// Total amount, fees included, the spender may transfer
+        amount: M,
+    },
+    /// Transfer money out of an account on behalf of its owner, within the
+    /// allowance granted to the spender
+    TransferFrom {
+        /// Actor spending from the account, who initiates the op
+        spender: A,
+        /// The transfer, out of the owner's account
+        transfer: Transfer<A, M>,
+    },
+    /// Start a cancellable transfer.  The sender is debited immediately but
+    /// the recipient is only credited once the transfer is finalized.
+    InitiateTransfer(Transfer<A, M>),
+    /// Credit the recipient of a pending transfer
+    FinalizeTransfer {
+        /// Id of the pending transfer
+        id: TransferId,
+    },
+    /// Cancel a pending transfer, returning the money to the sender
+    CancelTransfer {
+        /// Id of the pending transfer
+        id: TransferId,
+    },
+    /// Move an account's balance to a newly opened account, on behalf of an
+    /// owner that lost control of it.  Only the recovery actor designated
+    /// when the account was opened may initiate it.
+    RecoverAccount {
+        /// Owner of the account being recovered
+        owner: A,
+        /// Owner of the account opened to receive the balance
+        new_owner: A,
+    },
+    /// Move an account's whole balance to `destination`'s account and close
+    /// it, in a single op.  Only the account's owner may initiate it.
+    CloseAndSweep {
+        /// Owner of the account being closed
+        owner: A,
+        /// Owner of the account receiving the balance
+        destination: A,
+    },
+    /// Reserve a human-readable alias resolving to an account
+    RegisterAlias {
+        /// Account owner
+        owner: A,
+        /// The alias, e.g. "alice"
+        alias: String,
+    },
+    /// Transfer money from one account to several at once, e.g. to pay
+    /// out a payroll.  The transfers share a sender and are applied in
+    /// order, with consecutive sequence numbers, and either every transfer
+    /// is applied or none is.
+    MultiTransfer {
+        /// The transfers, out of the sender's account
+        transfers: Vec<Transfer<A, M>>,
+    },
+}
+
+impl<A: Ord + Hash + Serialize, M: MoneyT> Op<A, M> {
+    /// Returns the canonical encoding of the op, identical on every node,
+    /// for hashing and signing.
+    pub fn to_canonical_bytes(&self) -> Vec<u8> {
+        canonical_bytes(self)
+    }
+}
+
+/// Formats a one-line summary such as `OpenAccount owner=X balance=5`, for
+/// logs and command line tools.  `Debug` shows every field.
+impl<A: Ord + Hash + Display, M: MoneyT> Display for Op<A, M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Op::Transfer(transfer) => transfer.fmt_summary(f, "Transfer", None),
+            Op::OpenAccount {
+                owner,
+                balance,
+                currency,
+                recovery,
+                kind,
+            } => {
+                write!(f, "OpenAccount owner={} balance={}", owner, balance)?;
+                if *currency != DEFAULT_CURRENCY {
+                    write!(f, " currency={}", currency)?;
+                }
+                if let Some(recovery) = recovery {
+                    write!(f, " recovery={}", recovery)?;
+                }
+                if *kind == AccountKind::Liability {
+                    write!(f, " liability")?;
+                }
+                Ok(())
+            }
+            Op::BatchOpen { accounts } => write!(f, "BatchOpen accounts={}", accounts.len()),
+            Op::SetAccountMetadata { owner, key, value } => write!(
+                f,
+                "SetAccountMetadata owner={} key={} value={}",
+                owner, key, value
+            ),
+            Op::SetFrozen { owner, frozen } => {
+                write!(f, "SetFrozen owner={} frozen={}", owner, frozen)
+            }
+            Op::AdjustBalance { owner, new_balance } => write!(
+                f,
+                "AdjustBalance owner={} new_balance={}",
+                owner, new_balance
+            ),
+            Op::Approve {
+                owner,
+                spender,
+                amount,
+            } => write!(
+                f,
+                "Approve owner={} spender={} amount={}",
+                owner, spender, amount
+            ),
+            Op::TransferFrom { spender, transfer } => {
+                transfer.fmt_summary(f, "TransferFrom", Some(spender))
+            }
+            Op::InitiateTransfer(transfer) => transfer.fmt_summary(f, "InitiateTransfer", None),
+            Op::FinalizeTransfer { id } => write!(f, "FinalizeTransfer id={}", id),
+            Op::CancelTransfer { id } => write!(f, "CancelTransfer id={}", id),
+            Op::RecoverAccount { owner, new_owner } => {
+                write!(f, "RecoverAccount owner={} new_owner={}", owner, new_owner)
+            }
+            Op::CloseAndSweep { owner, destination } => {
+                write!(
+                    f,
+                    "CloseAndSweep owner={} destination={}",
+                    owner, destination
+                )
+            }
+            Op::RegisterAlias { owner, alias } => {
+                write!(f, "RegisterAlias owner={} alias={}", owner, alias)
+            }
+            Op::MultiTransfer { transfers } => {
+                write!(f, "MultiTransfer transfers={}", transfers.len())
+            }
+        }
+    }
 }
@@ -0,0 +1,65 @@
+//! AT2 OpObserver
+
+use alloc::{boxed::Box, vec::Vec};
+use core::{
+    fmt::{self, Debug},
+    hash::Hash,
+};
+
+use super::{AppliedEffect, Money, MoneyT, Op};
+
+/// A callback notified of each op a bank applies, along with its effect,
+/// registered with `Bank::subscribe`.
+///
+/// Observers run synchronously inside `apply`, so they must be quick, must
+/// not panic, and can't reach the bank to mutate it.
+pub type OpObserver<A, M = Money> = Box<dyn Fn(&Op<A, M>, &AppliedEffect<A, M>) + Send + Sync>;
+
+/// The observers registered on a bank.
+///
+/// Observers are not part of the bank's state: they are left out of
+/// comparisons, and a clone of the bank, e.g. one used to try ops out,
+/// starts without any.
+pub(crate) struct Observers<A: Ord + Hash, M: MoneyT>(Vec<OpObserver<A, M>>);
+
+impl<A: Ord + Hash, M: MoneyT> Observers<A, M> {
+    pub(crate) fn push(&mut self, observer: OpObserver<A, M>) {
+        self.0.push(observer);
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub(crate) fn notify(&self, op: &Op<A, M>, effect: &AppliedEffect<A, M>) {
+        for observer in self.0.iter() {
+            observer(op, effect);
+        }
+    }
+}
+
+impl<A: Ord + Hash, M: MoneyT> Default for Observers<A, M> {
+    fn default() -> Self {
+        Self(Vec::new())
+    }
+}
+
+impl<A: Ord + Hash, M: MoneyT> Clone for Observers<A, M> {
+    fn clone(&self) -> Self {
+        Self::default()
+    }
+}
+
+impl<A: Ord + Hash, M: MoneyT> PartialEq for Observers<A, M> {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl<A: Ord + Hash, M: MoneyT> Eq for Observers<A, M> {}
+
+impl<A: Ord + Hash, M: MoneyT> Debug for Observers<A, M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} observers", self.0.len())
+    }
+}
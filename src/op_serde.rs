@@ -0,0 +1,171 @@
+//! Serde representations of `Op`.
+//!
+//! Human-readable formats such as JSON get a stable external schema: ops are
+//! internally tagged with a `type` field, and variants and fields are named
+//! in camelCase, e.g. `{"type":"setFrozen","owner":1,"frozen":true}`.
+//!
+//! Binary formats such as bincode keep serde's default, externally tagged,
+//! representation.  Bincode can't deserialize internally tagged enums, and
+//! the canonical bytes of an op, which are hashed and signed, must not
+//! change.
+
+use alloc::{string::String, vec::Vec};
+use core::hash::Hash;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use super::{AccountKind, Currency, MoneyT, Op, Transfer, TransferId};
+
+impl<A: Ord + Hash + Serialize, M: MoneyT> Serialize for Op<A, M> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            Readable::serialize(self, serializer)
+        } else {
+            Compact::serialize(self, serializer)
+        }
+    }
+}
+
+impl<'de, A, M> Deserialize<'de> for Op<A, M>
+where
+    A: Ord + Hash + Deserialize<'de>,
+    M: MoneyT + Deserialize<'de>,
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            Readable::deserialize(deserializer)
+        } else {
+            Compact::deserialize(deserializer)
+        }
+    }
+}
+
+// The two mirrors below must list the variants and fields of `Op` in the
+// same order, see `Op` for their documentation.  A variant missing from
+// either fails to compile.
+
+/// The representation of `Op` in binary formats
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "Op")]
+enum Compact<A: Ord + Hash, M: MoneyT> {
+    Transfer(Transfer<A, M>),
+    OpenAccount {
+        owner: A,
+        balance: M,
+        currency: Currency,
+        recovery: Option<A>,
+        #[serde(default)]
+        kind: AccountKind,
+    },
+    BatchOpen {
+        accounts: Vec<(A, M)>,
+    },
+    SetAccountMetadata {
+        owner: A,
+        key: String,
+        value: String,
+    },
+    SetFrozen {
+        owner: A,
+        frozen: bool,
+    },
+    AdjustBalance {
+        owner: A,
+        new_balance: M,
+    },
+    Approve {
+        owner: A,
+        spender: A,
+        amount: M,
+    },
+    TransferFrom {
+        spender: A,
+        transfer: Transfer<A, M>,
+    },
+    InitiateTransfer(Transfer<A, M>),
+    FinalizeTransfer {
+        id: TransferId,
+    },
+    CancelTransfer {
+        id: TransferId,
+    },
+    RecoverAccount {
+        owner: A,
+        new_owner: A,
+    },
+    CloseAndSweep {
+        owner: A,
+        destination: A,
+    },
+    RegisterAlias {
+        owner: A,
+        alias: String,
+    },
+    MultiTransfer {
+        transfers: Vec<Transfer<A, M>>,
+    },
+}
+
+/// The representation of `Op` in human-readable formats
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "Op", tag = "type", rename_all = "camelCase")]
+enum Readable<A: Ord + Hash, M: MoneyT> {
+    Transfer(Transfer<A, M>),
+    OpenAccount {
+        owner: A,
+        balance: M,
+        currency: Currency,
+        recovery: Option<A>,
+        #[serde(default)]
+        kind: AccountKind,
+    },
+    BatchOpen {
+        accounts: Vec<(A, M)>,
+    },
+    SetAccountMetadata {
+        owner: A,
+        key: String,
+        value: String,
+    },
+    SetFrozen {
+        owner: A,
+        frozen: bool,
+    },
+    #[serde(rename_all = "camelCase")]
+    AdjustBalance {
+        owner: A,
+        new_balance: M,
+    },
+    Approve {
+        owner: A,
+        spender: A,
+        amount: M,
+    },
+    TransferFrom {
+        spender: A,
+        transfer: Transfer<A, M>,
+    },
+    InitiateTransfer(Transfer<A, M>),
+    FinalizeTransfer {
+        id: TransferId,
+    },
+    CancelTransfer {
+        id: TransferId,
+    },
+    #[serde(rename_all = "camelCase")]
+    RecoverAccount {
+        owner: A,
+        new_owner: A,
+    },
+    CloseAndSweep {
+        owner: A,
+        destination: A,
+    },
+    RegisterAlias {
+        owner: A,
+        alias: String,
+    },
+    MultiTransfer {
+        transfers: Vec<Transfer<A, M>>,
+    },
+}
@@ -0,0 +1,557 @@
+//! AT2 protobuf encoding
+//!
+//! A stable wire format for `Op` and `Transfer`, for interop with gRPC
+//! services.  Serde remains the crate's primary encoding; this one is only
+//! built with the `proto` feature.
+//!
+//! The messages here mirror `proto/at2.proto`, and the two must be kept in
+//! sync.  Actors are encoded as opaque bytes, so they must implement
+//! `AsRef<[u8]>` and `TryFrom<Vec<u8>>`.  Amounts are encoded as `uint64`,
+//! so only ledgers using the default `Money` type are supported.
+
+use alloc::{collections::BTreeSet, vec::Vec};
+use core::{convert::TryFrom, hash::Hash};
+
+use prost::Message;
+use thiserror::Error;
+
+use super::{AccountKind, Money, Op, Transfer, TransferId};
+
+/// Protobuf messages, as defined in `proto/at2.proto`
+pub mod messages {
+    use alloc::{string::String, vec::Vec};
+
+    /// An AT2 transfer between two accounts
+    #[derive(Clone, PartialEq, ::prost::Message)]
+    pub struct Transfer {
+        /// Sending account
+        #[prost(bytes = "vec", tag = "1")]
+        pub from: Vec<u8>,
+        /// Receiving account
+        #[prost(bytes = "vec", tag = "2")]
+        pub to: Vec<u8>,
+        /// Amount transferred
+        #[prost(uint64, tag = "3")]
+        pub amount: u64,
+        /// Currency of both accounts
+        #[prost(uint32, tag = "4")]
+        pub currency: u32,
+        /// Fee paid to the fee collector, on top of `amount`
+        #[prost(uint64, tag = "5")]
+        pub fee: u64,
+        /// Number of ops the bank must have applied before the transfer is accepted
+        #[prost(uint64, optional, tag = "6")]
+        pub not_before: Option<u64>,
+        /// Unix time in milliseconds, as claimed by the sender
+        #[prost(uint64, optional, tag = "7")]
+        pub timestamp: Option<u64>,
+        /// Position among the sender's outgoing transfers
+        #[prost(uint64, tag = "8")]
+        pub seq: u64,
+        /// Proof of funds
+        #[prost(message, repeated, tag = "9")]
+        pub deps: Vec<Transfer>,
+    }
+
+    /// Open a new account
+    #[derive(Clone, PartialEq, ::prost::Message)]
+    pub struct OpenAccount {
+        /// Account owner
+        #[prost(bytes = "vec", tag = "1")]
+        pub owner: Vec<u8>,
+        /// Account initial balance
+        #[prost(uint64, tag = "2")]
+        pub balance: u64,
+        /// Currency the account is denominated in
+        #[prost(uint32, tag = "3")]
+        pub currency: u32,
+        /// Actor allowed to recover the account
+        #[prost(bytes = "vec", optional, tag = "4")]
+        pub recovery: Option<Vec<u8>>,
+        /// Kind of the account
+        #[prost(enumeration = "AccountKind", tag = "5")]
+        pub kind: i32,
+    }
+
+    /// Kind of an account
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+    #[repr(i32)]
+    pub enum AccountKind {
+        /// An account whose balance is never negative
+        Asset = 0,
+        /// An account whose balance may be negative
+        Liability = 1,
+    }
+
+    /// Move an account's balance to a new owner
+    #[derive(Clone, PartialEq, ::prost::Message)]
+    pub struct RecoverAccount {
+        /// Owner of the account being recovered
+        #[prost(bytes = "vec", tag = "1")]
+        pub owner: Vec<u8>,
+        /// Owner of the account receiving the balance
+        #[prost(bytes = "vec", tag = "2")]
+        pub new_owner: Vec<u8>,
+    }
+
+    /// Move an account's balance to another account and close it
+    #[derive(Clone, PartialEq, ::prost::Message)]
+    pub struct CloseAndSweep {
+        /// Owner of the account being closed
+        #[prost(bytes = "vec", tag = "1")]
+        pub owner: Vec<u8>,
+        /// Owner of the account receiving the balance
+        #[prost(bytes = "vec", tag = "2")]
+        pub destination: Vec<u8>,
+    }
+
+    /// An account opened by a batch
+    #[derive(Clone, PartialEq, ::prost::Message)]
+    pub struct AccountOpening {
+        /// Account owner
+        #[prost(bytes = "vec", tag = "1")]
+        pub owner: Vec<u8>,
+        /// Account initial balance
+        #[prost(uint64, tag = "2")]
+        pub balance: u64,
+    }
+
+    /// Open many accounts at once
+    #[derive(Clone, PartialEq, ::prost::Message)]
+    pub struct BatchOpen {
+        /// Accounts to open, in order
+        #[prost(message, repeated, tag = "1")]
+        pub accounts: Vec<AccountOpening>,
+    }
+
+    /// Transfer money from one account to several at once
+    #[derive(Clone, PartialEq, ::prost::Message)]
+    pub struct MultiTransfer {
+        /// Transfers to apply, in order
+        #[prost(message, repeated, tag = "1")]
+        pub transfers: Vec<Transfer>,
+    }
+
+    /// Set a metadata entry on an account
+    #[derive(Clone, PartialEq, ::prost::Message)]
+    pub struct SetAccountMetadata {
+        /// Account owner
+        #[prost(bytes = "vec", tag = "1")]
+        pub owner: Vec<u8>,
+        /// Metadata key
+        #[prost(string, tag = "2")]
+        pub key: String,
+        /// Metadata value
+        #[prost(string, tag = "3")]
+        pub value: String,
+    }
+
+    /// Freeze or unfreeze an account
+    #[derive(Clone, PartialEq, ::prost::Message)]
+    pub struct SetFrozen {
+        /// Account owner
+        #[prost(bytes = "vec", tag = "1")]
+        pub owner: Vec<u8>,
+        /// Whether the account can no longer send or receive transfers
+        #[prost(bool, tag = "2")]
+        pub frozen: bool,
+    }
+
+    /// Set an account's balance directly
+    #[derive(Clone, PartialEq, ::prost::Message)]
+    pub struct AdjustBalance {
+        /// Account owner
+        #[prost(bytes = "vec", tag = "1")]
+        pub owner: Vec<u8>,
+        /// Balance of the account once adjusted
+        #[prost(uint64, tag = "2")]
+        pub new_balance: u64,
+    }
+
+    /// Authorize a spender to transfer out of an account
+    #[derive(Clone, PartialEq, ::prost::Message)]
+    pub struct Approve {
+        /// Account owner
+        #[prost(bytes = "vec", tag = "1")]
+        pub owner: Vec<u8>,
+        /// Actor allowed to spend from the account
+        #[prost(bytes = "vec", tag = "2")]
+        pub spender: Vec<u8>,
+        /// Total amount, fees included, the spender may transfer
+        #[prost(uint64, tag = "3")]
+        pub amount: u64,
+    }
+
+    /// Transfer money out of an account on behalf of its owner
+    #[derive(Clone, PartialEq, ::prost::Message)]
+    pub struct TransferFrom {
+        /// Actor spending from the account
+        #[prost(bytes = "vec", tag = "1")]
+        pub spender: Vec<u8>,
+        /// The transfer, out of the owner's account
+        #[prost(message, optional, tag = "2")]
+        pub transfer: Option<Transfer>,
+    }
+
+    /// Reserve a human-readable alias for an account
+    #[derive(Clone, PartialEq, ::prost::Message)]
+    pub struct RegisterAlias {
+        /// Account owner
+        #[prost(bytes = "vec", tag = "1")]
+        pub owner: Vec<u8>,
+        /// The alias
+        #[prost(string, tag = "2")]
+        pub alias: String,
+    }
+
+    /// Refers to a pending transfer by id
+    #[derive(Clone, PartialEq, ::prost::Message)]
+    pub struct PendingTransfer {
+        /// The 32 byte transfer id
+        #[prost(bytes = "vec", tag = "1")]
+        pub id: Vec<u8>,
+    }
+
+    /// An AT2 operation
+    #[derive(Clone, PartialEq, ::prost::Message)]
+    pub struct Op {
+        /// The kind of operation
+        #[prost(
+            oneof = "op::Kind",
+            tags = "1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15"
+        )]
+        pub kind: Option<op::Kind>,
+    }
+
+    /// Nested types of `Op`
+    pub mod op {
+        /// The kind of operation
+        #[derive(Clone, PartialEq, ::prost::Oneof)]
+        pub enum Kind {
+            /// Transfer money between 2 accounts
+            #[prost(message, tag = "1")]
+            Transfer(super::Transfer),
+            /// Open a new account
+            #[prost(message, tag = "2")]
+            OpenAccount(super::OpenAccount),
+            /// Set a metadata entry on an account
+            #[prost(message, tag = "3")]
+            SetAccountMetadata(super::SetAccountMetadata),
+            /// Freeze or unfreeze an account
+            #[prost(message, tag = "4")]
+            SetFrozen(super::SetFrozen),
+            /// Authorize a spender to transfer out of an account
+            #[prost(message, tag = "5")]
+            Approve(super::Approve),
+            /// Transfer money out of an account on behalf of its owner
+            #[prost(message, tag = "6")]
+            TransferFrom(super::TransferFrom),
+            /// Start a cancellable transfer
+            #[prost(message, tag = "7")]
+            InitiateTransfer(super::Transfer),
+            /// Credit the recipient of a pending transfer
+            #[prost(message, tag = "8")]
+            FinalizeTransfer(super::PendingTransfer),
+            /// Cancel a pending transfer
+            #[prost(message, tag = "9")]
+            CancelTransfer(super::PendingTransfer),
+            /// Reserve a human-readable alias for an account
+            #[prost(message, tag = "10")]
+            RegisterAlias(super::RegisterAlias),
+            /// Move an account's balance to a new owner
+            #[prost(message, tag = "11")]
+            RecoverAccount(super::RecoverAccount),
+            /// Set an account's balance directly
+            #[prost(message, tag = "12")]
+            AdjustBalance(super::AdjustBalance),
+            /// Move an account's balance to another account and close it
+            #[prost(message, tag = "13")]
+            CloseAndSweep(super::CloseAndSweep),
+            /// Open many accounts at once
+            #[prost(message, tag = "14")]
+            BatchOpen(super::BatchOpen),
+            /// Transfer money from one account to several at once
+            #[prost(message, tag = "15")]
+            MultiTransfer(super::MultiTransfer),
+        }
+    }
+}
+
+use messages::op::Kind;
+
+/// Enumeration of errors decoding protobuf messages
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum ProtoError {
+    /// The bytes are not a valid protobuf message
+    #[error("Failed to decode protobuf message: {0}")]
+    Decode(#[from] prost::DecodeError),
+
+    /// The bytes of an actor could not be converted to an actor
+    #[error("Invalid actor encoding")]
+    InvalidActor,
+
+    /// A transfer id is not 32 bytes long
+    #[error("Invalid transfer id: expected 32 bytes, got {len}")]
+    InvalidTransferId {
+        /// Length of the encoded id
+        len: usize,
+    },
+
+    /// An account kind is not one of the known kinds
+    #[error("Invalid account kind: {kind}")]
+    InvalidAccountKind {
+        /// The encoded kind
+        kind: i32,
+    },
+
+    /// A required message field is missing
+    #[error("Missing field: {0}")]
+    MissingField(&'static str),
+}
+
+impl<A> Transfer<A, Money>
+where
+    A: Ord + Hash + AsRef<[u8]> + TryFrom<Vec<u8>>,
+{
+    /// Converts the transfer to its protobuf message.
+    pub fn to_proto(&self) -> messages::Transfer {
+        messages::Transfer {
+            from: self.from.as_ref().to_vec(),
+            to: self.to.as_ref().to_vec(),
+            amount: self.amount,
+            currency: self.currency,
+            fee: self.fee,
+            not_before: self.not_before,
+            timestamp: self.timestamp,
+            seq: self.seq,
+            deps: self.deps.iter().map(Transfer::to_proto).collect(),
+        }
+    }
+
+    /// Converts a protobuf message back to a transfer.
+    pub fn from_proto(msg: messages::Transfer) -> Result<Self, ProtoError> {
+        let deps = msg
+            .deps
+            .into_iter()
+            .map(Transfer::from_proto)
+            .collect::<Result<BTreeSet<_>, _>>()?;
+
+        Ok(Self {
+            from: actor_from_proto(msg.from)?,
+            to: actor_from_proto(msg.to)?,
+            amount: msg.amount,
+            currency: msg.currency,
+            fee: msg.fee,
+            not_before: msg.not_before,
+            timestamp: msg.timestamp,
+            seq: msg.seq,
+            deps,
+        })
+    }
+
+    /// Returns the protobuf encoding of the transfer.
+    pub fn to_proto_bytes(&self) -> Vec<u8> {
+        self.to_proto().encode_to_vec()
+    }
+
+    /// Decodes a transfer from its protobuf encoding.
+    pub fn from_proto_bytes(bytes: &[u8]) -> Result<Self, ProtoError> {
+        Self::from_proto(messages::Transfer::decode(bytes)?)
+    }
+}
+
+impl<A> Op<A, Money>
+where
+    A: Ord + Hash + AsRef<[u8]> + TryFrom<Vec<u8>>,
+{
+    /// Converts the op to its protobuf message.
+    pub fn to_proto(&self) -> messages::Op {
+        let kind = match self {
+            Op::Transfer(transfer) => Kind::Transfer(transfer.to_proto()),
+            Op::OpenAccount {
+                owner,
+                balance,
+                currency,
+                recovery,
+                kind,
+            } => Kind::OpenAccount(messages::OpenAccount {
+                owner: owner.as_ref().to_vec(),
+                balance: *balance,
+                currency: *currency,
+                recovery: recovery.as_ref().map(|r| r.as_ref().to_vec()),
+                kind: match kind {
+                    AccountKind::Asset => messages::AccountKind::Asset,
+                    AccountKind::Liability => messages::AccountKind::Liability,
+                } as i32,
+            }),
+            Op::BatchOpen { accounts } => Kind::BatchOpen(messages::BatchOpen {
+                accounts: accounts
+                    .iter()
+                    .map(|(owner, balance)| messages::AccountOpening {
+                        owner: owner.as_ref().to_vec(),
+                        balance: *balance,
+                    })
+                    .collect(),
+            }),
+            Op::MultiTransfer { transfers } => Kind::MultiTransfer(messages::MultiTransfer {
+                transfers: transfers.iter().map(Transfer::to_proto).collect(),
+            }),
+            Op::SetAccountMetadata { owner, key, value } => {
+                Kind::SetAccountMetadata(messages::SetAccountMetadata {
+                    owner: owner.as_ref().to_vec(),
+                    key: key.clone(),
+                    value: value.clone(),
+                })
+            }
+            Op::SetFrozen { owner, frozen } => Kind::SetFrozen(messages::SetFrozen {
+                owner: owner.as_ref().to_vec(),
+                frozen: *frozen,
+            }),
+            Op::AdjustBalance { owner, new_balance } => {
+                Kind::AdjustBalance(messages::AdjustBalance {
+                    owner: owner.as_ref().to_vec(),
+                    new_balance: *new_balance,
+                })
+            }
+            Op::Approve {
+                owner,
+                spender,
+                amount,
+            } => Kind::Approve(messages::Approve {
+                owner: owner.as_ref().to_vec(),
+                spender: spender.as_ref().to_vec(),
+                amount: *amount,
+            }),
+            Op::TransferFrom { spender, transfer } => Kind::TransferFrom(messages::TransferFrom {
+                spender: spender.as_ref().to_vec(),
+                transfer: Some(transfer.to_proto()),
+            }),
+            Op::InitiateTransfer(transfer) => Kind::InitiateTransfer(transfer.to_proto()),
+            Op::FinalizeTransfer { id } => {
+                Kind::FinalizeTransfer(messages::PendingTransfer { id: id.0.to_vec() })
+            }
+            Op::CancelTransfer { id } => {
+                Kind::CancelTransfer(messages::PendingTransfer { id: id.0.to_vec() })
+            }
+            Op::RegisterAlias { owner, alias } => Kind::RegisterAlias(messages::RegisterAlias {
+                owner: owner.as_ref().to_vec(),
+                alias: alias.clone(),
+            }),
+            Op::RecoverAccount { owner, new_owner } => {
+                Kind::RecoverAccount(messages::RecoverAccount {
+                    owner: owner.as_ref().to_vec(),
+                    new_owner: new_owner.as_ref().to_vec(),
+                })
+            }
+            Op::CloseAndSweep { owner, destination } => {
+                Kind::CloseAndSweep(messages::CloseAndSweep {
+                    owner: owner.as_ref().to_vec(),
+                    destination: destination.as_ref().to_vec(),
+                })
+            }
+        };
+
+        messages::Op { kind: Some(kind) }
+    }
+
+    /// Converts a protobuf message back to an op.
+    pub fn from_proto(msg: messages::Op) -> Result<Self, ProtoError> {
+        let op = match msg.kind.ok_or(ProtoError::MissingField("Op.kind"))? {
+            Kind::Transfer(transfer) => Op::Transfer(Transfer::from_proto(transfer)?),
+            Kind::OpenAccount(msg) => Op::OpenAccount {
+                owner: actor_from_proto(msg.owner)?,
+                balance: msg.balance,
+                currency: msg.currency,
+                recovery: msg.recovery.map(actor_from_proto).transpose()?,
+                kind: match messages::AccountKind::from_i32(msg.kind) {
+                    Some(messages::AccountKind::Asset) => AccountKind::Asset,
+                    Some(messages::AccountKind::Liability) => AccountKind::Liability,
+                    None => return Err(ProtoError::InvalidAccountKind { kind: msg.kind }),
+                },
+            },
+            Kind::BatchOpen(msg) => Op::BatchOpen {
+                accounts: msg
+                    .accounts
+                    .into_iter()
+                    .map(|a| Ok((actor_from_proto(a.owner)?, a.balance)))
+                    .collect::<Result<_, ProtoError>>()?,
+            },
+            Kind::MultiTransfer(msg) => Op::MultiTransfer {
+                transfers: msg
+                    .transfers
+                    .into_iter()
+                    .map(Transfer::from_proto)
+                    .collect::<Result<_, ProtoError>>()?,
+            },
+            Kind::SetAccountMetadata(msg) => Op::SetAccountMetadata {
+                owner: actor_from_proto(msg.owner)?,
+                key: msg.key,
+                value: msg.value,
+            },
+            Kind::SetFrozen(msg) => Op::SetFrozen {
+                owner: actor_from_proto(msg.owner)?,
+                frozen: msg.frozen,
+            },
+            Kind::AdjustBalance(msg) => Op::AdjustBalance {
+                owner: actor_from_proto(msg.owner)?,
+                new_balance: msg.new_balance,
+            },
+            Kind::Approve(msg) => Op::Approve {
+                owner: actor_from_proto(msg.owner)?,
+                spender: actor_from_proto(msg.spender)?,
+                amount: msg.amount,
+            },
+            Kind::TransferFrom(msg) => Op::TransferFrom {
+                spender: actor_from_proto(msg.spender)?,
+                transfer: Transfer::from_proto(
+                    msg.transfer
+                        .ok_or(ProtoError::MissingField("TransferFrom.transfer"))?,
+                )?,
+            },
+            Kind::InitiateTransfer(transfer) => {
+                Op::InitiateTransfer(Transfer::from_proto(transfer)?)
+            }
+            Kind::FinalizeTransfer(msg) => Op::FinalizeTransfer {
+                id: transfer_id_from_proto(msg.id)?,
+            },
+            Kind::CancelTransfer(msg) => Op::CancelTransfer {
+                id: transfer_id_from_proto(msg.id)?,
+            },
+            Kind::RegisterAlias(msg) => Op::RegisterAlias {
+                owner: actor_from_proto(msg.owner)?,
+                alias: msg.alias,
+            },
+            Kind::RecoverAccount(msg) => Op::RecoverAccount {
+                owner: actor_from_proto(msg.owner)?,
+                new_owner: actor_from_proto(msg.new_owner)?,
+            },
+            Kind::CloseAndSweep(msg) => Op::CloseAndSweep {
+                owner: actor_from_proto(msg.owner)?,
+                destination: actor_from_proto(msg.destination)?,
+            },
+        };
+
+        Ok(op)
+    }
+
+    /// Returns the protobuf encoding of the op.
+    pub fn to_proto_bytes(&self) -> Vec<u8> {
+        self.to_proto().encode_to_vec()
+    }
+
+    /// Decodes an op from its protobuf encoding.
+    pub fn from_proto_bytes(bytes: &[u8]) -> Result<Self, ProtoError> {
+        Self::from_proto(messages::Op::decode(bytes)?)
+    }
+}
+
+fn actor_from_proto<A: TryFrom<Vec<u8>>>(bytes: Vec<u8>) -> Result<A, ProtoError> {
+    A::try_from(bytes).map_err(|_| ProtoError::InvalidActor)
+}
+
+fn transfer_id_from_proto(bytes: Vec<u8>) -> Result<TransferId, ProtoError> {
+    let len = bytes.len();
+    <[u8; 32]>::try_from(bytes)
+        .map(TransferId)
+        .map_err(|_| ProtoError::InvalidTransferId { len })
+}
@@ -0,0 +1,235 @@
+//! AT2 SnapshotDelta
+
+use alloc::{
+    collections::{BTreeMap, BTreeSet},
+    string::String,
+    vec::Vec,
+};
+use core::hash::Hash;
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    BalanceAdjustment, BankConfig, BankState, Currency, Money, MoneyT, Transfer, TransferId,
+};
+
+/// Entries of a map that changed, by key: the new value, or `None` if the
+/// entry was removed
+pub type Changes<K, V> = BTreeMap<K, Option<V>>;
+
+/// What changed between two `BankState` snapshots, as computed by
+/// `BankState::diff`.
+///
+/// Histories are diffed transfer by transfer, so a replica catching up only
+/// receives the transfers it misses.  Every other field only carries the
+/// entries that changed.  Sets of accounts carry `true` for accounts added
+/// to the set and `false` for those removed from it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnapshotDelta<A: Ord + Hash, M: MoneyT = Money> {
+    /// Changed initial balances, including those of newly opened accounts
+    pub initial_balances: Changes<A, M>,
+
+    /// Currencies of newly opened accounts
+    pub currencies: Changes<A, Currency>,
+
+    /// Open indices of newly opened accounts
+    pub open_indices: Changes<A, u64>,
+
+    /// Transfers missing from the base, by actor
+    pub added_transfers: BTreeMap<A, BTreeSet<Transfer<A, M>>>,
+
+    /// Transfers of the base no longer in history, e.g. collapsed into a
+    /// checkpoint, by actor
+    pub removed_transfers: BTreeMap<A, BTreeSet<Transfer<A, M>>>,
+
+    /// Changed ids of compacted transfers
+    #[serde(default)]
+    pub transfer_ids: Changes<TransferId, TransferId>,
+
+    /// Changed account metadata, by actor
+    pub metadata: Changes<A, BTreeMap<String, String>>,
+
+    /// Changed aliases
+    pub aliases: Changes<String, A>,
+
+    /// Number of ops applied
+    pub applied_ops: u64,
+
+    /// Accounts frozen (`true`) or unfrozen (`false`)
+    pub frozen: BTreeMap<A, bool>,
+
+    /// Changed frozen versions
+    #[serde(default)]
+    pub frozen_versions: Changes<A, u64>,
+
+    /// Accounts closed
+    pub closed: BTreeMap<A, bool>,
+
+    /// Accounts opened as liabilities
+    pub liabilities: BTreeMap<A, bool>,
+
+    /// Pending transfers initiated (`Some`) or settled (`None`), by id
+    pub pending_transfers: Changes<TransferId, Transfer<A, M>>,
+
+    /// Pending transfers cancelled, by id
+    #[serde(default)]
+    pub cancelled_transfers: BTreeMap<TransferId, bool>,
+
+    /// Changed allowances, by (owner, spender)
+    pub allowances: Changes<(A, A), M>,
+
+    /// Changed allowance versions, by (owner, spender)
+    #[serde(default)]
+    pub allowance_versions: Changes<(A, A), u64>,
+
+    /// Changed rate limiting records, by sender
+    pub recent_transfers: Changes<A, Vec<u64>>,
+
+    /// Changed recovery actors
+    pub recovery_actors: Changes<A, A>,
+
+    /// Changed checkpoints
+    pub checkpoints: Changes<A, u64>,
+
+    /// Changed balance adjustments, by actor
+    pub adjustments: Changes<A, BTreeSet<BalanceAdjustment<M>>>,
+
+    /// The new configuration, if it changed
+    pub config: Option<BankConfig<A, M>>,
+}
+
+impl<A: Ord + Hash + Clone, M: MoneyT> SnapshotDelta<A, M> {
+    /// Computes the changes turning `base` into `current`.
+    pub(crate) fn between(base: &BankState<A, M>, current: &BankState<A, M>) -> Self {
+        let empty = BTreeSet::new();
+        let mut added_transfers = BTreeMap::new();
+        let mut removed_transfers = BTreeMap::new();
+        for (actor, transfers) in current.hist.iter() {
+            let base_transfers = base.hist.get(actor);
+            let added: BTreeSet<_> = transfers
+                .difference(base_transfers.unwrap_or(&empty))
+                .cloned()
+                .collect();
+            if !added.is_empty() || base_transfers.is_none() {
+                added_transfers.insert(actor.clone(), added);
+            }
+        }
+        for (actor, base_transfers) in base.hist.iter() {
+            let transfers = current.hist.get(actor).unwrap_or(&empty);
+            let removed: BTreeSet<_> = base_transfers.difference(transfers).cloned().collect();
+            if !removed.is_empty() {
+                removed_transfers.insert(actor.clone(), removed);
+            }
+        }
+
+        Self {
+            initial_balances: changes(&base.initial_balances, &current.initial_balances),
+            currencies: changes(&base.currencies, &current.currencies),
+            open_indices: changes(&base.open_indices, &current.open_indices),
+            added_transfers,
+            removed_transfers,
+            transfer_ids: changes(&base.transfer_ids, &current.transfer_ids),
+            metadata: changes(&base.metadata, &current.metadata),
+            aliases: changes(&base.aliases, &current.aliases),
+            applied_ops: current.applied_ops,
+            frozen: set_changes(&base.frozen, &current.frozen),
+            frozen_versions: changes(&base.frozen_versions, &current.frozen_versions),
+            closed: set_changes(&base.closed, &current.closed),
+            liabilities: set_changes(&base.liabilities, &current.liabilities),
+            pending_transfers: changes(&base.pending_transfers, &current.pending_transfers),
+            cancelled_transfers: set_changes(
+                &base.cancelled_transfers,
+                &current.cancelled_transfers,
+            ),
+            allowances: changes(&base.allowances, &current.allowances),
+            allowance_versions: changes(&base.allowance_versions, &current.allowance_versions),
+            recent_transfers: changes(&base.recent_transfers, &current.recent_transfers),
+            recovery_actors: changes(&base.recovery_actors, &current.recovery_actors),
+            checkpoints: changes(&base.checkpoints, &current.checkpoints),
+            adjustments: changes(&base.adjustments, &current.adjustments),
+            config: Some(current.config.clone()).filter(|config| config != &base.config),
+        }
+    }
+
+    /// Applies the changes to `state`.
+    pub(crate) fn apply_to(self, state: &mut BankState<A, M>) {
+        apply_changes(&mut state.initial_balances, self.initial_balances);
+        apply_changes(&mut state.currencies, self.currencies);
+        apply_changes(&mut state.open_indices, self.open_indices);
+        for (actor, removed) in self.removed_transfers {
+            if let Some(transfers) = state.hist.get_mut(&actor) {
+                transfers.retain(|t| !removed.contains(t));
+            }
+        }
+        for (actor, added) in self.added_transfers {
+            state.hist.entry(actor).or_default().extend(added);
+        }
+        apply_changes(&mut state.transfer_ids, self.transfer_ids);
+        apply_changes(&mut state.metadata, self.metadata);
+        apply_changes(&mut state.aliases, self.aliases);
+        state.applied_ops = self.applied_ops;
+        apply_set_changes(&mut state.frozen, self.frozen);
+        apply_changes(&mut state.frozen_versions, self.frozen_versions);
+        apply_set_changes(&mut state.closed, self.closed);
+        apply_set_changes(&mut state.liabilities, self.liabilities);
+        apply_changes(&mut state.pending_transfers, self.pending_transfers);
+        apply_set_changes(&mut state.cancelled_transfers, self.cancelled_transfers);
+        apply_changes(&mut state.allowances, self.allowances);
+        apply_changes(&mut state.allowance_versions, self.allowance_versions);
+        apply_changes(&mut state.recent_transfers, self.recent_transfers);
+        apply_changes(&mut state.recovery_actors, self.recovery_actors);
+        apply_changes(&mut state.checkpoints, self.checkpoints);
+        apply_changes(&mut state.adjustments, self.adjustments);
+        if let Some(config) = self.config {
+            state.config = config;
+        }
+    }
+
+    /// Returns the accounts opened since the base.
+    pub fn added_accounts(&self) -> impl Iterator<Item = &A> {
+        self.open_indices
+            .iter()
+            .filter(|(_, open_index)| open_index.is_some())
+            .map(|(actor, _)| actor)
+    }
+}
+
+fn changes<K: Ord + Clone, V: PartialEq + Clone>(
+    base: &BTreeMap<K, V>,
+    current: &BTreeMap<K, V>,
+) -> Changes<K, V> {
+    let updated = current
+        .iter()
+        .filter(|(key, value)| base.get(key) != Some(value))
+        .map(|(key, value)| (key.clone(), Some(value.clone())));
+    let removed = base
+        .keys()
+        .filter(|key| !current.contains_key(key))
+        .map(|key| (key.clone(), None));
+    updated.chain(removed).collect()
+}
+
+fn apply_changes<K: Ord, V>(map: &mut BTreeMap<K, V>, changes: Changes<K, V>) {
+    for (key, value) in changes {
+        match value {
+            Some(value) => map.insert(key, value),
+            None => map.remove(&key),
+        };
+    }
+}
+
+fn set_changes<T: Ord + Clone>(base: &BTreeSet<T>, current: &BTreeSet<T>) -> BTreeMap<T, bool> {
+    let added = current.difference(base).map(|item| (item.clone(), true));
+    let removed = base.difference(current).map(|item| (item.clone(), false));
+    added.chain(removed).collect()
+}
+
+fn apply_set_changes<T: Ord>(set: &mut BTreeSet<T>, changes: BTreeMap<T, bool>) {
+    for (item, present) in changes {
+        if present {
+            set.insert(item);
+        } else {
+            set.remove(&item);
+        }
+    }
+}
@@ -1,21 +1,60 @@
 //! AT2 Transfer
 
-use core::hash::Hash;
-use std::collections::BTreeSet;
+use alloc::collections::BTreeSet;
+use core::{
+    fmt::{self, Display},
+    hash::Hash,
+};
 
+use alloc::vec::Vec;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 
-use super::Money;
+use super::{money::DEFAULT_CURRENCY, Currency, Money, MoneyT};
 
 // TODO: introduce decomp. of Account from Actor
 // pub type Account = Actor; // In the paper, Actor and Account are synonymous
 
+/// A content-addressed transfer identifier: the SHA-256 hash of the
+/// transfer's canonical serialization.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct TransferId(pub [u8; 32]);
+
+impl Display for TransferId {
+    /// Formats the id as lowercase hex.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.iter().try_for_each(|byte| write!(f, "{:02x}", byte))
+    }
+}
+
 /// An AT2 transfer between two accounts
+///
+/// Fields are named in camelCase, for a stable JSON schema.
 #[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
-pub struct Transfer<A: Ord + Hash> {
+#[serde(rename_all = "camelCase")]
+pub struct Transfer<A: Ord + Hash, M: MoneyT = Money> {
     pub(crate) from: A,
     pub(crate) to: A,
-    pub(crate) amount: Money,
+    pub(crate) amount: M,
+
+    /// currency of both accounts, and thus of `amount` and `fee`
+    pub(crate) currency: Currency,
+
+    /// fee paid to the bank's fee collector, on top of `amount`
+    pub(crate) fee: M,
+
+    /// number of ops the bank must have applied before this transfer is accepted
+    // Also accepts the name used before fields were renamed
+    #[serde(alias = "not_before")]
+    pub(crate) not_before: Option<u64>,
+
+    /// unix time in milliseconds, as claimed by the sender.  Advisory only,
+    /// it is never trusted during validation.
+    pub(crate) timestamp: Option<u64>,
+
+    /// position of this transfer among the sender's outgoing transfers,
+    /// starting at 0.  Shared by a transfer and its fee payment leg.
+    pub(crate) seq: u64,
 
     // PERF: BTreeSet<Transfer> is conceptually simple and elegant, but bloated in
     //       memory and on the wire as each Transfer recursively includes all Transfers
@@ -23,5 +62,191 @@ pub struct Transfer<A: Ord + Hash> {
     //       many incoming transfers in a row. Room for big improvement here.
     /// set of transactions that need to be applied before this transfer can be validated
     /// ie. a proof of funds
-    pub(crate) deps: BTreeSet<Transfer<A>>,
+    pub(crate) deps: BTreeSet<Transfer<A, M>>,
+}
+
+impl<A: Ord + Hash + Clone, M: MoneyT> Transfer<A, M> {
+    /// Returns this transfer with the nested dependencies of its `deps` removed.
+    /// The result only records which transfers this one depends on.
+    pub(crate) fn compacted(&self) -> Self {
+        Self {
+            from: self.from.clone(),
+            to: self.to.clone(),
+            amount: self.amount,
+            currency: self.currency,
+            fee: self.fee,
+            not_before: self.not_before,
+            timestamp: self.timestamp,
+            seq: self.seq,
+            deps: self.deps.iter().map(Self::without_deps).collect(),
+        }
+    }
+
+    /// Returns this transfer with all of its dependencies removed.
+    pub(crate) fn without_deps(&self) -> Self {
+        Self {
+            from: self.from.clone(),
+            to: self.to.clone(),
+            amount: self.amount,
+            currency: self.currency,
+            fee: self.fee,
+            not_before: self.not_before,
+            timestamp: self.timestamp,
+            seq: self.seq,
+            deps: Default::default(),
+        }
+    }
+}
+
+impl<A: Ord + Hash + Serialize, M: MoneyT> Transfer<A, M> {
+    /// Returns the transfer's stable identifier: the SHA-256 hash of its
+    /// canonical bytes.
+    pub fn id(&self) -> TransferId {
+        TransferId(Sha256::digest(&self.to_canonical_bytes()).into())
+    }
+
+    /// Returns the canonical encoding of the transfer, identical on every
+    /// node, for hashing and signing.
+    pub fn to_canonical_bytes(&self) -> Vec<u8> {
+        canonical_bytes(self)
+    }
+
+    /// Returns the size in bytes of the transfer's canonical encoding,
+    /// which grows with its dependencies.
+    pub fn serialized_size(&self) -> usize {
+        self.to_canonical_bytes().len()
+    }
+}
+
+impl<A: Ord + Hash + Display, M: MoneyT> Transfer<A, M> {
+    /// Writes a one-line summary of the transfer, naming it `kind`, along
+    /// with the spender moving the money on the sender's behalf, if any.
+    pub(crate) fn fmt_summary(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+        kind: &str,
+        spender: Option<&A>,
+    ) -> fmt::Result {
+        write!(
+            f,
+            "{} {} from {} to {}",
+            kind, self.amount, self.from, self.to
+        )?;
+        if let Some(spender) = spender {
+            write!(f, " by {}", spender)?;
+        }
+        if self.fee != M::zero() {
+            write!(f, " fee {}", self.fee)?;
+        }
+        match self.deps.len() {
+            1 => write!(f, " (1 dep)"),
+            n => write!(f, " ({} deps)", n),
+        }
+    }
+}
+
+/// Formats a one-line summary such as `Transfer 10 from X to Y (3 deps)`,
+/// for logs and command line tools.  `Debug` shows every field.
+impl<A: Ord + Hash + Display, M: MoneyT> Display for Transfer<A, M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_summary(f, "Transfer", None)
+    }
+}
+
+/// Builds a `Transfer` field by field.
+///
+/// Fields that aren't set default to the default currency, no fee, time
+/// lock, timestamp or dependencies, and sequence number 0.  Transfers
+/// meant for the network are best generated by `Bank::transfer` and
+/// friends, which fill in the sequence number and proof of funds.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TransferBuilder<A: Ord + Hash, M: MoneyT = Money> {
+    pub(crate) transfer: Transfer<A, M>,
+}
+
+impl<A: Ord + Hash, M: MoneyT> TransferBuilder<A, M> {
+    /// Starts building a transfer of `amount` from `from` to `to`.
+    pub fn new(from: A, to: A, amount: M) -> Self {
+        Self {
+            transfer: Transfer {
+                from,
+                to,
+                amount,
+                currency: DEFAULT_CURRENCY,
+                fee: M::zero(),
+                not_before: None,
+                timestamp: None,
+                seq: 0,
+                deps: Default::default(),
+            },
+        }
+    }
+
+    /// Sets the sending account.
+    pub fn from(mut self, from: A) -> Self {
+        self.transfer.from = from;
+        self
+    }
+
+    /// Sets the receiving account.
+    pub fn to(mut self, to: A) -> Self {
+        self.transfer.to = to;
+        self
+    }
+
+    /// Sets the amount transferred.
+    pub fn amount(mut self, amount: M) -> Self {
+        self.transfer.amount = amount;
+        self
+    }
+
+    /// Sets the currency of both accounts.
+    pub fn currency(mut self, currency: Currency) -> Self {
+        self.transfer.currency = currency;
+        self
+    }
+
+    /// Sets the fee paid to the fee collector, on top of the amount.
+    pub fn fee(mut self, fee: M) -> Self {
+        self.transfer.fee = fee;
+        self
+    }
+
+    /// Sets the number of ops a bank must have applied before accepting the transfer.
+    pub fn not_before(mut self, not_before: u64) -> Self {
+        self.transfer.not_before = Some(not_before);
+        self
+    }
+
+    /// Sets the unix time in milliseconds the sender claims to send the transfer at.
+    pub fn timestamp(mut self, timestamp: u64) -> Self {
+        self.transfer.timestamp = Some(timestamp);
+        self
+    }
+
+    /// Sets the position of the transfer among the sender's outgoing transfers.
+    pub fn seq(mut self, seq: u64) -> Self {
+        self.transfer.seq = seq;
+        self
+    }
+
+    /// Sets the transfers that must be applied before this one, its proof of funds.
+    pub fn deps(mut self, deps: impl IntoIterator<Item = Transfer<A, M>>) -> Self {
+        self.transfer.deps = deps.into_iter().collect();
+        self
+    }
+
+    /// Returns the transfer.
+    pub fn build(self) -> Transfer<A, M> {
+        self.transfer
+    }
+}
+
+/// Serializes a value with bincode's legacy configuration, that of bincode
+/// 1.x: fixed-width little endian integers, and collections in iteration
+/// order, which for the `BTreeSet`s and `BTreeMap`s used throughout is
+/// sorted order.
+pub(crate) fn canonical_bytes<T: Serialize>(value: &T) -> Vec<u8> {
+    bincode::serde::encode_to_vec(value, bincode::config::legacy())
+        .expect("[ERROR] Failed to serialize")
 }
@@ -0,0 +1,50 @@
+//! AT2 ValidationPolicy
+
+use alloc::{string::String, sync::Arc};
+use core::{
+    fmt::{self, Debug},
+    hash::Hash,
+};
+
+use super::{Bank, Money, MoneyT, Op};
+
+/// Deployment-specific validation rules, such as KYC lists or rate limits,
+/// checked by `Bank::validate` after its built-in checks.
+///
+/// Every replica must reach the same verdict on the same op, or replicas
+/// will diverge: a policy must be deterministic, depending only on the
+/// bank, the source and the op.  It must not read clocks, randomness,
+/// files, the network or any state that differs between nodes, and all
+/// replicas must be configured with the same policy.
+pub trait ValidationPolicy<A: Ord + Hash, M: MoneyT = Money>: Send + Sync {
+    /// Checks an op initiated by `source`, returning the reason for
+    /// rejecting it, if any.
+    fn check(&self, bank: &Bank<A, M>, source: &A, op: &Op<A, M>) -> Result<(), String>;
+}
+
+/// A validation policy shared by a bank and its clones.
+///
+/// Two handles are equal when they refer to the same policy.
+pub(crate) struct SharedPolicy<A: Ord + Hash, M: MoneyT>(
+    pub(crate) Arc<dyn ValidationPolicy<A, M>>,
+);
+
+impl<A: Ord + Hash, M: MoneyT> Clone for SharedPolicy<A, M> {
+    fn clone(&self) -> Self {
+        Self(Arc::clone(&self.0))
+    }
+}
+
+impl<A: Ord + Hash, M: MoneyT> PartialEq for SharedPolicy<A, M> {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl<A: Ord + Hash, M: MoneyT> Eq for SharedPolicy<A, M> {}
+
+impl<A: Ord + Hash, M: MoneyT> Debug for SharedPolicy<A, M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ValidationPolicy")
+    }
+}
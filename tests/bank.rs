@@ -0,0 +1,3157 @@
+use brb_dt_at2::{
+    bank::{
+        ApplyError, ImportError, InvariantViolation, MergeError, OpStatus, ValidationError,
+        ValidationWarning, MANY_DEPS_WARNING_THRESHOLD, MAX_ACCOUNT_METADATA_SIZE, MAX_ALIAS_LEN,
+    },
+    AccountActivity, AccountKind, AppliedEffect, ArithmeticMode, Bank, BankConfig, BankState,
+    Money, MoneyT, Op, ParseMoneyError, TransferBuilder, ValidationPolicy,
+};
+
+type Actor = u8;
+
+/// A set of banks, one per actor, that all see every op, standing in for the network.
+struct Ledger(Vec<Bank<Actor>>);
+
+impl Ledger {
+    pub fn new(balances: &[Money]) -> Self {
+        let mut ledger = Self(
+            (0..balances.len() as Actor)
+                .map(|a| Bank::new(a).with_max_initial_balance(Money::MAX))
+                .collect(),
+        );
+        for (owner, balance) in (0..).zip(balances.iter().cloned()) {
+            let op = ledger.bank(owner).open_account(owner, balance);
+            ledger.exec(owner, op);
+        }
+        ledger
+    }
+
+    /// Applies the same configuration to every bank.
+    pub fn configure(&mut self, config: impl Fn(Bank<Actor>) -> Bank<Actor>) {
+        self.0 = self.0.drain(..).map(config).collect();
+    }
+
+    pub fn bank(&self, actor: Actor) -> &Bank<Actor> {
+        &self.0[actor as usize]
+    }
+
+    /// Validates the op against every bank and applies it everywhere.
+    pub fn exec(&mut self, source: Actor, op: Op<Actor>) {
+        for bank in self.0.iter_mut() {
+            bank.validate(&source, &op).unwrap();
+            bank.apply(op.clone());
+        }
+    }
+
+    pub fn transfer(&mut self, from: Actor, to: Actor, amount: Money) -> Op<Actor> {
+        let op = self.bank(from).transfer(from, to, amount).unwrap();
+        self.exec(from, op.clone());
+        op
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crdts::quickcheck::{quickcheck, TestResult};
+
+    #[test]
+    fn test_compact_preserves_balances_and_validation() {
+        let mut ledger = Ledger::new(&[100, 0, 0, 0]);
+        ledger.transfer(0, 1, 60);
+        ledger.transfer(1, 2, 50);
+        ledger.transfer(2, 3, 40);
+        ledger.transfer(0, 1, 40);
+
+        let balances: Vec<Money> = (0..4).map(|a| ledger.bank(0).balance(&a)).collect();
+        assert_eq!(balances, vec![0, 50, 10, 40]);
+
+        let mut compacted = ledger.bank(3).clone();
+        compacted.compact();
+        assert_ne!(&compacted, ledger.bank(3));
+
+        // Compacting twice changes nothing
+        let once = compacted.clone();
+        compacted.compact();
+        assert_eq!(compacted, once);
+
+        for actor in 0..4 {
+            assert_eq!(compacted.balance(&actor), balances[actor as usize]);
+        }
+
+        // A transfer carrying full (uncompacted) deps is still accepted
+        let op = ledger.bank(3).transfer(3, 0, 40).unwrap();
+        assert_eq!(compacted.validate(&3, &op), Ok(()));
+        compacted.apply(op);
+        assert_eq!(compacted.balance(&0), 40);
+        assert_eq!(compacted.balance(&3), 0);
+    }
+
+    #[test]
+    fn test_snapshot_round_trip() {
+        let mut ledger = Ledger::new(&[100, 0, 0]);
+        ledger.configure(|bank| {
+            bank.with_fee_collector(2)
+                .with_max_deps(8)
+                .with_rate_limit(10, 100)
+        });
+        ledger.transfer(0, 1, 60);
+        ledger.transfer(1, 2, 50);
+        ledger.transfer(0, 2, 40);
+
+        for actor in 0..3 {
+            let bank = ledger.bank(actor);
+            let state: BankState<Actor> = bank.snapshot();
+            let restored = Bank::from_snapshot(actor, state.clone());
+
+            assert_eq!(restored.config(), bank.config());
+            assert_eq!(&restored, bank);
+            assert_eq!(restored.snapshot(), state);
+        }
+
+        // The restored bank carries the same proof of funds as the original
+        let restored = Bank::from_snapshot(2, ledger.bank(2).snapshot());
+        assert_eq!(
+            restored.transfer(2, 0, 90),
+            ledger.bank(2).transfer(2, 0, 90)
+        );
+    }
+
+    #[test]
+    fn test_snapshot_keeps_compacted_transfer_ids() {
+        let mut ledger = Ledger::new(&[10, 0, 0, 0]);
+        ledger.transfer(0, 1, 10);
+        ledger.transfer(1, 2, 10);
+        let last = ledger.transfer(2, 3, 10);
+
+        let mut compacted = ledger.bank(3).clone();
+        compacted.compact();
+        let restored = Bank::from_snapshot(3, compacted.snapshot());
+
+        // Compacted transfers are found under the id they were applied with
+        assert_eq!(restored.known_transfers(), compacted.known_transfers());
+        assert_eq!(restored.known_transfers(), ledger.bank(3).known_transfers());
+        assert!(restored
+            .missing_from(&compacted.known_transfers())
+            .is_empty());
+        assert!(compacted
+            .missing_from(&restored.known_transfers())
+            .is_empty());
+        match last {
+            Op::Transfer(transfer) => assert!(restored.find_transfer(&transfer.id()).is_some()),
+            _ => panic!("expected a Transfer op"),
+        }
+
+        // Restoring again keeps the ids
+        assert_eq!(restored.snapshot(), compacted.snapshot());
+        let delta = ledger.bank(3).snapshot().diff(&compacted.snapshot());
+        let mut state = compacted.snapshot();
+        state.apply_delta(delta);
+        assert_eq!(state, ledger.bank(3).snapshot());
+    }
+
+    #[test]
+    fn test_account_metadata() {
+        let mut ledger = Ledger::new(&[0, 0]);
+        assert_eq!(ledger.bank(1).account_metadata(&0), None);
+
+        let op = ledger
+            .bank(0)
+            .set_account_metadata(0, "name".to_string(), "alice".to_string());
+        assert_eq!(
+            ledger.bank(1).validate(&1, &op),
+            Err(ValidationError::NotInitiatedByAccountOwner {
+                initiator: 1,
+                owner: 0
+            })
+        );
+        ledger.exec(0, op);
+
+        // Only open accounts carry metadata
+        let unopened =
+            ledger
+                .bank(0)
+                .set_account_metadata(2, "name".to_string(), "carol".to_string());
+        assert_eq!(
+            ledger.bank(1).validate(&2, &unopened),
+            Err(ValidationError::FromAccountDoesNotExist { from: 2 })
+        );
+        assert_eq!(
+            ledger.bank(1).account_metadata(&0).unwrap().get("name"),
+            Some(&"alice".to_string())
+        );
+
+        // Overwriting an entry only counts its new size
+        let value = "a".repeat(MAX_ACCOUNT_METADATA_SIZE - "name".len());
+        ledger.exec(
+            0,
+            Op::SetAccountMetadata {
+                owner: 0,
+                key: "name".to_string(),
+                value,
+            },
+        );
+
+        let op = ledger
+            .bank(0)
+            .set_account_metadata(0, "k".to_string(), String::new());
+        assert_eq!(
+            ledger.bank(1).validate(&0, &op),
+            Err(ValidationError::MetadataTooLarge {
+                size: MAX_ACCOUNT_METADATA_SIZE + 1,
+                max_size: MAX_ACCOUNT_METADATA_SIZE
+            })
+        );
+    }
+
+    #[test]
+    fn test_transfer_with_fee() {
+        let mut ledger = Ledger::new(&[100, 0, 0]);
+        assert_eq!(
+            ledger.bank(0).transfer_with_fee(0, 1, 50, 5),
+            Err(ValidationError::NoFeeCollector)
+        );
+
+        ledger.configure(|bank| bank.with_fee_collector(2));
+        assert_eq!(
+            ledger.bank(0).transfer_with_fee(0, 1, 96, 5),
+            Err(ValidationError::InsufficientFunds {
+                balance: 100,
+                transfer_amount: 101
+            })
+        );
+
+        let op = ledger.bank(0).transfer_with_fee(0, 1, 50, 5).unwrap();
+        ledger.exec(0, op);
+        let op = ledger.bank(0).transfer_with_fee(0, 1, 40, 5).unwrap();
+        ledger.exec(0, op);
+
+        for bank in ledger.0.iter() {
+            assert_eq!(bank.balance(&0), 0);
+            assert_eq!(bank.balance(&1), 90);
+            assert_eq!(bank.balance(&2), 10);
+        }
+
+        // The collector can spend its fees
+        ledger.transfer(2, 1, 10);
+        assert_eq!(ledger.bank(1).balance(&1), 100);
+
+        // A fee paid out of received funds is backed by the same deps as
+        // the transfer paying it
+        let op = ledger.bank(1).transfer_with_fee(1, 0, 60, 5).unwrap();
+        ledger.exec(1, op);
+        for bank in ledger.0.iter() {
+            assert_eq!(bank.balance(&1), 35);
+            assert_eq!(bank.check_invariants(), Ok(()));
+        }
+        assert_eq!(ledger.bank(1).transfer(1, 0, 35).map(|_| ()), Ok(()));
+    }
+
+    #[test]
+    fn test_balance_checked_of_unknown_account() {
+        let ledger = Ledger::new(&[100]);
+        assert!(ledger.bank(0).account_exists(&0));
+        assert!(!ledger.bank(0).account_exists(&1));
+        assert_eq!(ledger.bank(0).balance_checked(&0), Some(100));
+        assert_eq!(ledger.bank(0).balance_checked(&1), None);
+    }
+
+    #[test]
+    fn test_transfer_count_and_volume() {
+        let mut ledger = Ledger::new(&[100, 50, 0]);
+        assert_eq!(ledger.bank(2).transfer_count(), 0);
+        assert_eq!(ledger.bank(2).total_volume(), 0);
+
+        ledger.transfer(0, 1, 30);
+        ledger.transfer(1, 2, 70);
+        ledger.transfer(0, 0, 10);
+
+        for bank in ledger.0.iter() {
+            assert_eq!(bank.transfer_count(), 3);
+            assert_eq!(bank.total_volume(), 110);
+        }
+
+        let restored = Bank::from_snapshot(2, ledger.bank(2).snapshot());
+        assert_eq!(restored.transfer_count(), 3);
+        assert_eq!(restored.total_volume(), 110);
+    }
+
+    #[test]
+    fn test_transfer_locked() {
+        let mut ledger = Ledger::new(&[100, 0]);
+        assert_eq!(ledger.bank(1).applied_ops(), 2);
+
+        let op = ledger.bank(0).transfer_locked(0, 1, 50, 3).unwrap();
+        assert_eq!(
+            ledger.bank(1).validate(&0, &op),
+            Err(ValidationError::TimeLockNotElapsed {
+                not_before: 3,
+                applied_ops: 2
+            })
+        );
+
+        let op_meta = ledger
+            .bank(1)
+            .set_account_metadata(1, "name".to_string(), "bob".to_string());
+        ledger.exec(1, op_meta);
+        ledger.exec(0, op);
+        assert_eq!(ledger.bank(1).balance(&1), 50);
+        assert_eq!(ledger.bank(1).applied_ops(), 4);
+    }
+
+    #[test]
+    fn test_applied_ops_after_catching_up() {
+        let mut ledger = Ledger::new(&[100, 0, 0]);
+        let lagging = ledger.bank(2).clone();
+        ledger.transfer(0, 1, 10);
+        ledger.transfer(1, 0, 5);
+        let op = ledger.bank(0).transfer_locked(0, 1, 10, 5).unwrap();
+        ledger.exec(0, op);
+
+        let mut absorbed = lagging.clone();
+        let missing = ledger.bank(0).missing_from(&absorbed.known_transfers());
+        assert_eq!(absorbed.absorb(missing), vec![]);
+        let mut merged = lagging;
+        merged.merge(ledger.bank(0)).unwrap();
+
+        // Both ways of catching up agree with the peers on the time lock
+        let op = ledger.bank(0).transfer_locked(0, 2, 1, 7).unwrap();
+        for bank in &[ledger.bank(2), &absorbed, &merged] {
+            assert_eq!(bank.applied_ops(), 6);
+            assert_eq!(
+                bank.validate(&0, &op),
+                Err(ValidationError::TimeLockNotElapsed {
+                    not_before: 7,
+                    applied_ops: 6
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn test_transfer_deps_closure() {
+        let mut ledger = Ledger::new(&[100, 0, 0, 0]);
+        let t0 = ledger.transfer(0, 1, 60);
+        let t1 = ledger.transfer(1, 2, 50);
+        let t2 = ledger.transfer(2, 3, 40);
+
+        let transfer = match ledger.bank(3).transfer(3, 0, 40).unwrap() {
+            Op::Transfer(transfer) => transfer,
+            _ => unreachable!(),
+        };
+
+        let closure: Vec<Op<Actor>> = ledger
+            .bank(3)
+            .transfer_deps_closure(&transfer)
+            .into_iter()
+            .map(Op::Transfer)
+            .collect();
+        assert_eq!(closure.len(), 3);
+        for op in [t0, t1, t2].iter() {
+            assert!(closure.contains(op));
+        }
+
+        // Dependencies stripped by compaction are resolved against history
+        let mut compacted = ledger.bank(3).clone();
+        compacted.compact();
+        assert_eq!(compacted.transfer_deps_closure(&transfer).len(), 3);
+    }
+
+    #[test]
+    fn test_validate_batch() {
+        let ledger = Ledger::new(&[100, 0]);
+        let bank = ledger.bank(1);
+        let ops = vec![
+            bank.open_account(2, 0),
+            bank.set_account_metadata(2, "name".to_string(), "carol".to_string()),
+            bank.open_account(2, 0),
+        ];
+
+        assert_eq!(bank.validate_batch(&2, &ops[..2]), Ok(()));
+        assert_eq!(
+            bank.validate_batch(&2, &ops),
+            Err((2, ValidationError::OwnerAlreadyHasAnAccount))
+        );
+        assert!(!bank.account_exists(&2));
+    }
+
+    #[test]
+    fn test_wide_money() {
+        let mut bank: Bank<Actor, u128> = Bank::new(1).with_max_initial_balance(u128::MAX);
+        let big = u64::MAX as u128 * 4;
+
+        for (owner, balance) in [(0, big), (1, 0)] {
+            let op = bank.open_account(owner, balance);
+            bank.validate(&owner, &op).unwrap();
+            bank.apply(op);
+        }
+
+        let op = bank.transfer(0, 1, big - 1).unwrap();
+        bank.validate(&0, &op).unwrap();
+        bank.apply(op);
+
+        assert_eq!(bank.balance(&0), 1);
+        assert_eq!(bank.balance(&1), big - 1);
+    }
+
+    #[test]
+    fn test_rebuild_from_ops() {
+        let mut ledger = Ledger::new(&[100, 0, 0]);
+        let mut log: Vec<(Actor, Op<Actor>)> = (0..3)
+            .map(|owner| {
+                (
+                    owner,
+                    ledger
+                        .bank(owner)
+                        .open_account(owner, [100, 0, 0][owner as usize]),
+                )
+            })
+            .collect();
+        log.push((0, ledger.transfer(0, 1, 60)));
+        log.push((1, ledger.transfer(1, 2, 50)));
+        log.push((0, ledger.transfer(0, 2, 40)));
+
+        for actor in 0..3 {
+            let rebuilt = Bank::new(actor)
+                .with_max_initial_balance(Money::MAX)
+                .replay_ops(log.clone())
+                .unwrap();
+            assert_eq!(&rebuilt, ledger.bank(actor));
+        }
+
+        // With the default configuration, accounts must open empty
+        assert_eq!(
+            Bank::rebuild_from_ops(0, log.clone()),
+            Err(ValidationError::InitialBalanceTooHigh {
+                balance: 100,
+                max_initial_balance: 0
+            })
+        );
+
+        // Replaying the log out of order fails on the first invalid op
+        log.swap(0, 3);
+        assert_eq!(
+            Bank::rebuild_from_ops(0, log),
+            Err(ValidationError::FromAccountDoesNotExist { from: 0 })
+        );
+    }
+
+    #[test]
+    fn test_recipient_balance_overflow() {
+        let mut ledger = Ledger::new(&[Money::MAX, 0, 2]);
+        let op = ledger.bank(2).transfer(2, 1, 2).unwrap();
+        ledger.transfer(0, 1, Money::MAX - 1);
+
+        let overflow = || ValidationError::RecipientBalanceOverflow {
+            balance: Money::MAX - 1,
+            transfer_amount: 2,
+        };
+        assert_eq!(ledger.bank(1).validate(&2, &op), Err(overflow()));
+        assert_eq!(ledger.bank(2).transfer(2, 1, 2), Err(overflow()));
+
+        ledger.transfer(2, 1, 1);
+        assert_eq!(ledger.bank(1).balance(&1), Money::MAX);
+    }
+
+    #[test]
+    fn test_transfer_id() {
+        let mut ledger = Ledger::new(&[100, 0]);
+        let first = match ledger.transfer(0, 1, 10) {
+            Op::Transfer(transfer) => transfer,
+            _ => unreachable!(),
+        };
+        let second = match ledger.transfer(0, 1, 20) {
+            Op::Transfer(transfer) => transfer,
+            _ => unreachable!(),
+        };
+
+        assert_eq!(first.id(), first.clone().id());
+        assert_ne!(first.id(), second.id());
+    }
+
+    #[test]
+    fn test_transfers_causal_order() {
+        let mut ledger = Ledger::new(&[100, 100, 0]);
+        let t0 = ledger.transfer(1, 2, 90);
+        let t1 = ledger.transfer(0, 2, 10);
+        let t2 = ledger.transfer(2, 1, 95);
+        let t3 = ledger.transfer(1, 2, 5);
+
+        let order: Vec<Op<Actor>> = ledger
+            .bank(0)
+            .transfers_causal_order(&2)
+            .into_iter()
+            .map(Op::Transfer)
+            .collect();
+        assert_eq!(order.len(), 4);
+
+        let position = |op: &Op<Actor>| order.iter().position(|o| o == op).unwrap();
+        assert!(position(&t0) < position(&t2));
+        assert!(position(&t1) < position(&t2));
+        assert!(position(&t2) < position(&t3));
+    }
+
+    #[test]
+    fn test_freeze_account() {
+        let mut ledger = Ledger::new(&[100, 0, 0]);
+        assert_eq!(
+            ledger.bank(2).freeze(1),
+            Err(ValidationError::NotInitiatedByAdmin { initiator: 2 })
+        );
+
+        ledger.configure(|bank| bank.with_admin(2));
+        let op = ledger.bank(2).freeze(1).unwrap();
+        assert_eq!(
+            ledger.bank(0).validate(&0, &op),
+            Err(ValidationError::NotInitiatedByAdmin { initiator: 0 })
+        );
+        ledger.exec(2, op);
+        assert!(ledger.bank(0).is_frozen(&1));
+
+        let op = ledger.bank(2).freeze(3).unwrap();
+        assert_eq!(
+            ledger.bank(0).validate(&2, &op),
+            Err(ValidationError::FromAccountDoesNotExist { from: 3 })
+        );
+
+        let frozen = || ValidationError::AccountFrozen { account: 1 };
+        let op = ledger.bank(1).transfer(0, 1, 10);
+        assert_eq!(op, Err(frozen()));
+
+        // Frozen state survives snapshot/restore
+        let restored = Bank::from_snapshot(0, ledger.bank(0).snapshot());
+        assert_eq!(restored.id(), &0);
+        assert!(restored.is_frozen(&1));
+
+        let op = ledger.bank(2).unfreeze(1).unwrap();
+        ledger.exec(2, op);
+        ledger.transfer(0, 1, 10);
+        assert_eq!(ledger.bank(0).balance(&1), 10);
+    }
+
+    #[test]
+    fn test_diff() {
+        let mut ledger = Ledger::new(&[100, 0]);
+        let stale = ledger.bank(1).clone();
+        ledger.transfer(0, 1, 10);
+        ledger.exec(2, ledger.bank(1).open_account(2, 5));
+
+        assert!(ledger.bank(0).diff(ledger.bank(1)).is_empty());
+
+        let diff = ledger.bank(0).diff(&stale);
+        assert_eq!(
+            diff.accounts_only_in_self.into_iter().collect::<Vec<_>>(),
+            vec![2]
+        );
+        assert!(diff.accounts_only_in_other.is_empty());
+        assert_eq!(
+            diff.balance_mismatches.into_iter().collect::<Vec<_>>(),
+            vec![(0, (90, 100)), (1, (10, 0))]
+        );
+        assert_eq!(diff.transfers_only_in_self.len(), 1);
+        assert!(diff.transfers_only_in_other.is_empty());
+    }
+
+    #[test]
+    fn test_apply_transfer_twice() {
+        let mut ledger = Ledger::new(&[100, 0]);
+        let op = ledger.transfer(0, 1, 10);
+        let before = ledger.bank(1).clone();
+
+        let mut bank = ledger.bank(1).clone();
+        bank.apply(op);
+        assert_eq!(bank, before);
+        assert_eq!(bank.balance(&0), 90);
+        assert_eq!(bank.balance(&1), 10);
+        assert_eq!(bank.transfer_count(), 1);
+        assert_eq!(bank.total_volume(), 10);
+        assert_eq!(bank.applied_ops(), 3);
+    }
+
+    #[test]
+    fn test_richest_accounts() {
+        let mut ledger = Ledger::new(&[10, 40, 20, 30, 40]);
+        ledger.transfer(0, 2, 5);
+
+        let bank = ledger.bank(0);
+        assert_eq!(bank.richest_accounts(3), vec![(1, 40), (4, 40), (3, 30)]);
+        assert_eq!(bank.richest_accounts(10).len(), 5);
+        assert_eq!(bank.richest_accounts(10)[4], (0, 5));
+        assert_eq!(bank.richest_accounts(0), vec![]);
+    }
+
+    #[test]
+    fn test_apply_checked_rejects_unknown_sender() {
+        let mut ledger = Ledger::new(&[100, 0]);
+        let op = ledger.transfer(0, 1, 10);
+
+        let mut bank: Bank<Actor> = Bank::new(1);
+        bank.apply(bank.open_account(1, 0));
+        let before = bank.clone();
+
+        assert_eq!(
+            bank.apply_checked(op.clone()),
+            Err(ApplyError::AccountDoesNotExist { account: 0 })
+        );
+        bank.apply(op);
+        assert_eq!(bank, before);
+    }
+
+    #[test]
+    fn test_cancellable_transfer() {
+        let mut ledger = Ledger::new(&[100, 0]);
+        let pending_id = |op: &Op<Actor>| match op {
+            Op::InitiateTransfer(transfer) => transfer.id(),
+            _ => panic!("expected an InitiateTransfer op"),
+        };
+
+        // Initiating debits the sender but does not credit the recipient
+        let op = ledger.bank(0).initiate_transfer(0, 1, 30).unwrap();
+        let id = pending_id(&op);
+        ledger.exec(0, op);
+        assert_eq!(ledger.bank(1).balance(&0), 70);
+        assert_eq!(ledger.bank(1).balance(&1), 0);
+        assert!(ledger.bank(1).pending_transfer(&id).is_some());
+
+        // Pending amounts can't be spent twice
+        assert_eq!(
+            ledger.bank(0).transfer(0, 1, 80),
+            Err(ValidationError::InsufficientFunds {
+                balance: 70,
+                transfer_amount: 80
+            })
+        );
+
+        // Only the sender may cancel
+        assert_eq!(
+            ledger.bank(1).validate(&1, &Op::CancelTransfer { id }),
+            Err(ValidationError::NotInitiatedByAccountOwner {
+                initiator: 1,
+                owner: 0
+            })
+        );
+        let op = ledger.bank(0).cancel_transfer(id).unwrap();
+        ledger.exec(0, op);
+        assert_eq!(ledger.bank(1).balance(&0), 100);
+        assert_eq!(ledger.bank(1).pending_transfer(&id), None);
+
+        // Finalizing credits the recipient, after which it can't be cancelled
+        let op = ledger.bank(0).initiate_transfer(0, 1, 40).unwrap();
+        let id = pending_id(&op);
+        ledger.exec(0, op);
+        let op = ledger.bank(0).finalize_transfer(id).unwrap();
+        ledger.exec(0, op);
+        assert_eq!(ledger.bank(1).balance(&0), 60);
+        assert_eq!(ledger.bank(1).balance(&1), 40);
+        assert_eq!(
+            ledger.bank(0).cancel_transfer(id),
+            Err(ValidationError::PendingTransferDoesNotExist { id })
+        );
+        assert_eq!(ledger.bank(1).transfer_count(), 1);
+    }
+
+    #[test]
+    fn test_finalize_to_frozen_recipient() {
+        let mut ledger = Ledger::new(&[100, 0, 0]);
+        ledger.configure(|bank| bank.with_admin(2));
+        let op = ledger.bank(0).initiate_transfer(0, 1, 30).unwrap();
+        let id = match &op {
+            Op::InitiateTransfer(transfer) => transfer.id(),
+            _ => panic!("expected an InitiateTransfer op"),
+        };
+        ledger.exec(0, op);
+        let op = ledger.bank(2).freeze(1).unwrap();
+        ledger.exec(2, op);
+
+        // The frozen recipient can't be credited, but the sender can cancel
+        assert_eq!(
+            ledger.bank(0).validate(&0, &Op::FinalizeTransfer { id }),
+            Err(ValidationError::AccountFrozen { account: 1 })
+        );
+        let mut bank = ledger.bank(0).clone();
+        assert_eq!(
+            bank.apply_checked(Op::FinalizeTransfer { id }),
+            Err(ApplyError::AccountFrozen { account: 1 })
+        );
+        assert_eq!(&bank, ledger.bank(0));
+        let op = ledger.bank(0).cancel_transfer(id).unwrap();
+        ledger.exec(0, op);
+        assert_eq!(ledger.bank(0).balance(&0), 100);
+        assert_eq!(ledger.bank(0).balance(&1), 0);
+    }
+
+    #[test]
+    fn test_confirmed_and_projected_balances() {
+        let mut ledger = Ledger::new(&[100, 0]);
+        ledger.transfer(0, 1, 10);
+
+        // Without pending transfers, all balances agree
+        let bank = ledger.bank(1);
+        assert_eq!(bank.confirmed_balance(&1), 10);
+        assert_eq!(bank.projected_balance(&1), 10);
+
+        let op = ledger.bank(0).initiate_transfer(0, 1, 30).unwrap();
+        ledger.exec(0, op);
+
+        let bank = ledger.bank(1);
+        assert_eq!(bank.confirmed_balance(&0), 90);
+        assert_eq!(bank.balance(&0), 60);
+        assert_eq!(bank.projected_balance(&0), 60);
+
+        assert_eq!(bank.confirmed_balance(&1), 10);
+        assert_eq!(bank.balance(&1), 10);
+        assert_eq!(bank.projected_balance(&1), 40);
+    }
+
+    #[test]
+    fn test_net_flow() {
+        let mut ledger = Ledger::new(&[100, 100, 100]);
+        ledger.transfer(0, 1, 30);
+        ledger.transfer(1, 0, 10);
+        ledger.transfer(0, 1, 5);
+        ledger.transfer(2, 1, 50);
+
+        let bank = ledger.bank(2);
+        assert_eq!(bank.net_flow(&0, &1), 25);
+        assert_eq!(bank.net_flow(&1, &0), -25);
+        assert_eq!(bank.net_flow(&2, &1), 50);
+        assert_eq!(bank.net_flow(&0, &2), 0);
+    }
+
+    #[test]
+    fn test_ensure_account() {
+        let mut ledger = Ledger::new(&[100]);
+        assert_eq!(ledger.bank(0).ensure_account(0, 100), None);
+
+        let mut bank: Bank<Actor> = Bank::new(1);
+        let op = bank.ensure_account(1, 0).unwrap();
+        assert_eq!(op, bank.open_account(1, 0));
+        bank.apply(op);
+        assert_eq!(bank.ensure_account(1, 0), None);
+
+        // validate remains strict
+        assert_eq!(
+            ledger
+                .bank(0)
+                .validate(&0, &ledger.bank(0).open_account(0, 100)),
+            Err(ValidationError::OwnerAlreadyHasAnAccount)
+        );
+        let op = ledger.bank(0).ensure_account(1, 0).unwrap();
+        ledger.exec(1, op);
+        assert!(ledger.bank(0).account_exists(&1));
+    }
+
+    #[test]
+    fn test_max_deps() {
+        let mut ledger = Ledger::new(&[10, 10, 10, 0]);
+        let incoming: Vec<_> = (0..3)
+            .map(|from| match ledger.transfer(from, 3, 10) {
+                Op::Transfer(transfer) => transfer,
+                _ => panic!("expected a Transfer op"),
+            })
+            .collect();
+
+        // A transfer built without the limit carries all three deps
+        let unbounded = ledger.bank(3).transfer(3, 0, 30).unwrap();
+
+        ledger.configure(|bank| bank.with_max_deps(2));
+        assert_eq!(
+            ledger.bank(0).validate(&3, &unbounded),
+            Err(ValidationError::TooManyDeps {
+                deps: 3,
+                max_deps: 2
+            })
+        );
+
+        // The sender can still spend everything, carrying the leftover
+        // dep in its next transfer
+        ledger.transfer(3, 0, 30);
+        assert_eq!(ledger.bank(0).balance(&3), 0);
+        let next = match ledger.transfer(0, 3, 5) {
+            Op::Transfer(transfer) => transfer,
+            _ => panic!("expected a Transfer op"),
+        };
+        let bank = ledger.bank(3);
+        let carried: Vec<_> = incoming
+            .into_iter()
+            .filter(|leftover| {
+                let deps = vec![leftover.clone(), next.clone()].into_iter().collect();
+                bank.transfer(3, 0, 5) == bank.transfer_with_deps(3, 0, 5, deps)
+            })
+            .collect();
+        assert_eq!(carried.len(), 1);
+        ledger.transfer(3, 0, 5);
+        assert_eq!(ledger.bank(0).balance(&0), 30);
+    }
+
+    #[test]
+    fn test_incoming_transfer_clears_spent_deps() {
+        let mut ledger = Ledger::new(&[10, 0, 0, 0]);
+        ledger.transfer(0, 1, 10);
+        ledger.transfer(1, 2, 10);
+        ledger.transfer(2, 3, 10);
+
+        // Another replica of account 3, restored from compacted history,
+        // references the incoming transfer in its compacted form
+        let mut restored = ledger.bank(3).clone();
+        restored.compact();
+        let restored = restored.clone_for(3);
+        let op = restored.transfer(3, 0, 5).unwrap();
+        for bank in ledger.0.iter_mut() {
+            bank.apply(op.clone());
+        }
+
+        // The next incoming transfer clears the spent dep, so only the new
+        // one is carried
+        let next = match ledger.transfer(0, 3, 1) {
+            Op::Transfer(transfer) => transfer,
+            _ => panic!("expected a Transfer op"),
+        };
+        let bank = ledger.bank(3);
+        let deps = vec![next].into_iter().collect();
+        assert_eq!(
+            bank.transfer(3, 0, 1),
+            bank.transfer_with_deps(3, 0, 1, deps)
+        );
+    }
+
+    #[test]
+    fn test_balance_before_time() {
+        let mut ledger = Ledger::new(&[100, 0]);
+        let op = ledger.bank(0).transfer_at(0, 1, 10, 1_000).unwrap();
+        ledger.exec(0, op);
+        let op = ledger.bank(0).transfer_at(0, 1, 20, 2_000).unwrap();
+        ledger.exec(0, op);
+        ledger.transfer(0, 1, 40);
+
+        let bank = ledger.bank(1);
+        assert_eq!(bank.balance_before_time(&1, 1_000), Some(0));
+        assert_eq!(bank.balance_before_time(&1, 1_001), Some(10));
+        assert_eq!(bank.balance_before_time(&0, 5_000), Some(70));
+        assert_eq!(bank.balance(&0), 30);
+        assert_eq!(bank.balance_before_time(&2, 5_000), None);
+    }
+
+    #[test]
+    fn test_ledger_export_round_trip() {
+        let mut ledger = Ledger::new(&[100, 0, 0]);
+        ledger.transfer(0, 1, 60);
+        ledger.transfer(1, 2, 25);
+
+        let export = ledger.bank(0).export_ledger();
+        assert_eq!(export.transfers.len(), 2);
+        let balances: Vec<Money> = export.accounts.iter().map(|a| a.current_balance).collect();
+        assert_eq!(balances, vec![40, 35, 25]);
+
+        let config = bincode::config::legacy();
+        let bytes = bincode::serde::encode_to_vec(&export, config).unwrap();
+        let (decoded, _) = bincode::serde::decode_from_slice(&bytes, config).unwrap();
+        let imported = Bank::import_ledger(1, decoded).unwrap();
+        assert!(imported.diff(ledger.bank(1)).is_empty());
+
+        // The imported bank can keep transacting
+        let op = imported.transfer(1, 0, 35).unwrap();
+        assert_eq!(ledger.bank(0).validate(&1, &op), Ok(()));
+
+        let mut tampered = export.clone();
+        tampered.accounts[2].current_balance = 30;
+        assert_eq!(
+            Bank::import_ledger(0, tampered),
+            Err(ImportError::BalanceMismatch {
+                account: 2,
+                exported: 30,
+                computed: Some(25)
+            })
+        );
+
+        let mut truncated = export;
+        truncated.accounts.pop();
+        assert_eq!(
+            Bank::import_ledger(0, truncated),
+            Err(ImportError::UnknownAccount { account: 2 })
+        );
+    }
+
+    #[test]
+    fn test_can_transfer() {
+        let mut ledger = Ledger::new(&[100, 0]);
+        ledger.transfer(0, 1, 60);
+
+        let bank = ledger.bank(1);
+        assert_eq!(bank.can_transfer(&1, &0, 60), Ok(()));
+        assert_eq!(
+            bank.can_transfer(&1, &0, 61),
+            Err(ValidationError::InsufficientFunds {
+                balance: 60,
+                transfer_amount: 61
+            })
+        );
+        assert_eq!(
+            bank.can_transfer(&0, &1, 10),
+            Err(ValidationError::NotInitiatedByAccountOwner {
+                initiator: 1,
+                owner: 0
+            })
+        );
+        assert_eq!(
+            bank.can_transfer(&1, &2, 10),
+            Err(ValidationError::ToAccountDoesNotExist { to: 2 })
+        );
+    }
+
+    #[test]
+    fn test_currencies() {
+        let mut ledger = Ledger::new(&[100]);
+        for (owner, balance) in [(1, 50), (2, 0)].iter().cloned() {
+            let op = ledger.bank(0).open_account_in(owner, balance, 7);
+            ledger.exec(owner, op);
+        }
+        assert_eq!(ledger.bank(0).account_currency(&1), Some(7));
+
+        assert_eq!(
+            ledger.bank(0).transfer(0, 1, 10),
+            Err(ValidationError::CurrencyMismatch {
+                account: 1,
+                account_currency: 7,
+                transfer_currency: 0
+            })
+        );
+
+        let op = ledger.bank(0).transfer(1, 2, 20).unwrap();
+        ledger.exec(1, op);
+        assert_eq!(ledger.bank(0).balance_in(&2, 7), Some(20));
+        assert_eq!(ledger.bank(0).balance_in(&2, 0), Some(0));
+        assert_eq!(ledger.bank(0).balance_in(&3, 7), None);
+    }
+
+    #[test]
+    fn test_foreign_dependency_is_rejected() {
+        let mut ledger = Ledger::new(&[100, 0, 0]);
+        ledger.transfer(0, 1, 60);
+
+        // Bank 1 attaches its own proof of funds, the transfer 0 -> 1, which
+        // is in the history of 0 but doesn't credit it.
+        assert_eq!(
+            ledger.bank(1).transfer(0, 2, 10),
+            Err(ValidationError::InvalidDependency)
+        );
+        assert_eq!(ledger.bank(1).can_transfer(&1, &2, 10), Ok(()));
+    }
+
+    #[test]
+    fn test_balances() {
+        let mut ledger = Ledger::new(&[100, 50, 0, 0]);
+        ledger.configure(|bank| bank.with_fee_collector(3));
+        ledger.transfer(0, 1, 60);
+        ledger.transfer(1, 2, 70);
+        ledger.transfer(2, 2, 10);
+        let op = ledger.bank(0).transfer_with_fee(0, 2, 20, 5).unwrap();
+        ledger.exec(0, op);
+        let op = ledger.bank(2).initiate_transfer(2, 0, 30).unwrap();
+        ledger.exec(2, op);
+
+        let bank = ledger.bank(0);
+        let balances = bank.balances();
+        assert_eq!(balances.len(), 4);
+        for (actor, balance) in balances {
+            assert_eq!(balance, bank.balance(&actor));
+        }
+    }
+
+    #[test]
+    fn test_delegated_transfer() {
+        let mut ledger = Ledger::new(&[100, 50, 0]);
+        ledger.transfer(1, 0, 20);
+        let op = ledger.bank(0).approve(0, 2, 50);
+        assert_eq!(
+            ledger.bank(2).validate(&2, &op),
+            Err(ValidationError::NotInitiatedByAccountOwner {
+                initiator: 2,
+                owner: 0
+            })
+        );
+        ledger.exec(0, op);
+        assert_eq!(ledger.bank(1).allowance(&0, &2), 50);
+
+        let op = ledger.bank(0).approve(3, 2, 50);
+        assert_eq!(
+            ledger.bank(1).validate(&3, &op),
+            Err(ValidationError::FromAccountDoesNotExist { from: 3 })
+        );
+
+        let op = ledger.bank(2).transfer_from(0, 1, 30).unwrap();
+        assert_eq!(
+            ledger.bank(1).validate(&1, &op),
+            Err(ValidationError::NotInitiatedByAccountOwner {
+                initiator: 1,
+                owner: 2
+            })
+        );
+        ledger.exec(2, op);
+        assert_eq!(ledger.bank(1).balance(&0), 90);
+        assert_eq!(ledger.bank(1).balance(&1), 60);
+        assert_eq!(ledger.bank(1).allowance(&0, &2), 20);
+
+        assert_eq!(
+            ledger.bank(2).transfer_from(0, 1, 30),
+            Err(ValidationError::AllowanceExceeded {
+                allowance: 20,
+                transfer_amount: 30
+            })
+        );
+        assert_eq!(
+            ledger.bank(1).transfer_from(0, 1, 10),
+            Err(ValidationError::AllowanceExceeded {
+                allowance: 0,
+                transfer_amount: 10
+            })
+        );
+
+        // The owner can still spend, with the proof of funds left over
+        ledger.transfer(0, 2, 90);
+        assert_eq!(ledger.bank(1).balance(&2), 90);
+    }
+
+    #[test]
+    fn test_canonical_bytes_are_stable() {
+        let mut ledger = Ledger::new(&[100, 0]);
+        let op = ledger.transfer(0, 1, 10);
+        let transfer = match &op {
+            Op::Transfer(transfer) => transfer,
+            _ => unreachable!(),
+        };
+
+        #[rustfmt::skip]
+        let expected_transfer: &[u8] = &[
+            0,                       // from
+            1,                       // to
+            10, 0, 0, 0, 0, 0, 0, 0, // amount
+            0, 0, 0, 0,              // currency
+            0, 0, 0, 0, 0, 0, 0, 0,  // fee
+            0,                       // not_before
+            0,                       // timestamp
+            0, 0, 0, 0, 0, 0, 0, 0,  // seq
+            0, 0, 0, 0, 0, 0, 0, 0,  // deps
+        ];
+        assert_eq!(transfer.to_canonical_bytes(), expected_transfer);
+
+        // Op::Transfer is the first variant
+        let mut expected_op = vec![0, 0, 0, 0];
+        expected_op.extend_from_slice(expected_transfer);
+        assert_eq!(op.to_canonical_bytes(), expected_op);
+    }
+
+    #[test]
+    fn test_transfer_sequence() {
+        let mut ledger = Ledger::new(&[100, 0, 0]);
+        ledger.transfer(0, 1, 10);
+        assert_eq!(ledger.bank(2).next_seq(&0), 1);
+        assert_eq!(ledger.bank(2).next_seq(&1), 0);
+
+        // Two concurrent transfers can't both claim the same seq
+        let first = ledger.bank(0).transfer(0, 1, 10).unwrap();
+        let second = ledger.bank(0).transfer(0, 2, 10).unwrap();
+        ledger.exec(0, first);
+        assert_eq!(
+            ledger.bank(2).validate(&0, &second),
+            Err(ValidationError::BadSequence {
+                seq: 1,
+                expected: 2
+            })
+        );
+
+        ledger.transfer(0, 2, 10);
+        assert_eq!(ledger.bank(1).next_seq(&0), 3);
+    }
+
+    #[test]
+    fn test_apply_with_effects() {
+        let ledger = Ledger::new(&[100, 0]);
+        let mut bank = ledger.bank(1).clone();
+
+        let op = bank.open_account(2, 0);
+        assert_eq!(
+            bank.apply_with_effects(op),
+            Ok(AppliedEffect::OpenAccount {
+                owner: 2,
+                balance: 0
+            })
+        );
+
+        let op = bank.transfer(0, 1, 30).unwrap();
+        let effect = AppliedEffect::Transfer {
+            from: 0,
+            to: 1,
+            amount: 30,
+            new_from_balance: 70,
+            new_to_balance: 30,
+        };
+        assert_eq!(bank.apply_with_effects(op.clone()), Ok(effect));
+        assert_eq!(
+            bank.apply_with_effects(op),
+            Ok(AppliedEffect::NoBalanceChange)
+        );
+
+        let op = bank.initiate_transfer(1, 2, 10).unwrap();
+        let id = match &op {
+            Op::InitiateTransfer(transfer) => transfer.id(),
+            _ => unreachable!(),
+        };
+        assert_eq!(
+            bank.apply_with_effects(op),
+            Ok(AppliedEffect::Reserved {
+                from: 1,
+                amount: 10,
+                new_from_balance: 20
+            })
+        );
+        let op = bank.cancel_transfer(id).unwrap();
+        assert_eq!(
+            bank.apply_with_effects(op),
+            Ok(AppliedEffect::Released {
+                from: 1,
+                amount: 10,
+                new_from_balance: 30
+            })
+        );
+    }
+
+    #[test]
+    fn test_arithmetic_mode() {
+        let mut ledger = Ledger::new(&[100, 0]);
+        ledger.transfer(0, 1, 60);
+        let op = ledger.bank(0).transfer(0, 1, 30).unwrap();
+
+        // Corrupt the state so that account 0 has spent more than it ever held
+        let mut state = ledger.bank(0).snapshot();
+        state.initial_balances.insert(0, 10);
+
+        let saturating =
+            Bank::from_snapshot(0, state.clone()).with_arithmetic_mode(ArithmeticMode::Saturate);
+        assert_eq!(saturating.balance(&0), 0);
+        assert_eq!(saturating.balance(&1), 60);
+
+        let erroring = Bank::from_snapshot(0, state).with_arithmetic_mode(ArithmeticMode::Error);
+        assert_eq!(
+            erroring.try_balance(&0),
+            Err(ApplyError::BalanceUnderflow { account: 0 })
+        );
+        assert_eq!(erroring.balance_checked(&0), None);
+        assert_eq!(erroring.balance_checked(&1), Some(60));
+        assert_eq!(
+            erroring.balances().into_iter().collect::<Vec<_>>(),
+            vec![(1, 60)]
+        );
+        assert_eq!(
+            erroring.try_balance(&2),
+            Err(ApplyError::AccountDoesNotExist { account: 2 })
+        );
+
+        // Apply-time checks surface the corruption instead of panicking
+        let mut erroring = erroring;
+        assert_eq!(
+            erroring.apply_checked(op),
+            Err(ApplyError::BalanceUnderflow { account: 0 })
+        );
+    }
+
+    #[test]
+    fn test_open_order() {
+        let mut ledger = Ledger((0..3).map(Bank::new).collect());
+        for owner in [2, 0, 1].iter().copied() {
+            let op = ledger.bank(owner).open_account(owner, 0);
+            ledger.exec(owner, op);
+        }
+
+        let bank = ledger.bank(0);
+        assert_eq!(bank.open_index(&2), Some(0));
+        assert_eq!(bank.open_index(&1), Some(2));
+        assert_eq!(bank.open_index(&3), None);
+        assert_eq!(bank.accounts_by_open_order(), vec![2, 0, 1]);
+
+        // Reopening an existing account is rejected and keeps its index
+        let op = bank.open_account(2, 0);
+        let mut bank = bank.clone();
+        assert!(bank.apply_checked(op).is_err());
+        assert_eq!(bank.open_index(&2), Some(0));
+
+        let restored = Bank::from_snapshot(1, bank.snapshot());
+        assert_eq!(restored.accounts_by_open_order(), vec![2, 0, 1]);
+    }
+
+    #[test]
+    fn test_validation_policy() {
+        /// Only actors that passed KYC may initiate transfers
+        struct Kyc(Vec<Actor>);
+
+        impl ValidationPolicy<Actor> for Kyc {
+            fn check(&self, _: &Bank<Actor>, source: &Actor, op: &Op<Actor>) -> Result<(), String> {
+                match op {
+                    Op::Transfer(_) if !self.0.contains(source) => {
+                        Err(format!("{} has not passed KYC", source))
+                    }
+                    _ => Ok(()),
+                }
+            }
+        }
+
+        let mut ledger = Ledger::new(&[100, 0]);
+        ledger.configure(|bank| bank.with_validation_policy(Kyc(vec![0])));
+        let op = ledger.transfer(0, 1, 60);
+
+        let bank = ledger.bank(1);
+        let refund = bank.transfer(1, 0, 10).unwrap();
+        assert_eq!(
+            bank.validate(&1, &refund),
+            Err(ValidationError::PolicyRejected(
+                "1 has not passed KYC".to_string()
+            ))
+        );
+
+        // The built-in checks come first
+        assert_eq!(
+            bank.validate(&1, &op),
+            Err(ValidationError::NotInitiatedByAccountOwner {
+                initiator: 1,
+                owner: 0
+            })
+        );
+
+        // Clones share the policy
+        assert_eq!(&bank.clone(), bank);
+    }
+
+    #[test]
+    fn test_transfer_builder() {
+        let mut ledger = Ledger::new(&[100, 0]);
+        let bank = ledger.bank(0);
+
+        let transfer = TransferBuilder::new(0, 1, 30).build();
+        assert_eq!(bank.validate(&0, &Op::Transfer(transfer.clone())), Ok(()));
+        assert_eq!(
+            bank.transfer(0, 1, 30).unwrap(),
+            Op::Transfer(transfer.clone())
+        );
+
+        let op = Op::Transfer(TransferBuilder::new(0, 1, 30).seq(1).build());
+        assert_eq!(
+            bank.validate(&0, &op),
+            Err(ValidationError::BadSequence {
+                seq: 1,
+                expected: 0
+            })
+        );
+
+        ledger.exec(0, Op::Transfer(transfer));
+        assert_eq!(ledger.bank(1).balance(&1), 30);
+    }
+
+    #[test]
+    fn test_check_invariants() {
+        let mut ledger = Ledger::new(&[100, 0, 0]);
+        ledger.transfer(0, 1, 60);
+        let op = ledger.transfer(1, 2, 50);
+        assert_eq!(ledger.bank(2).check_invariants(), Ok(()));
+
+        let transfer = match op {
+            Op::Transfer(transfer) => transfer,
+            _ => panic!("expected a Transfer op"),
+        };
+
+        // Forget account 0, lose the transfer to 1 from 1's history, and
+        // lose the transfer from 1 to 2 from 2's history
+        let first = ledger
+            .bank(0)
+            .incoming_transfers(&1)
+            .into_iter()
+            .next()
+            .unwrap();
+        let mut state = ledger.bank(2).snapshot();
+        state.initial_balances.remove(&0);
+        state.hist.get_mut(&1).unwrap().remove(&first);
+        state.hist.get_mut(&2).unwrap().clear();
+
+        let violations = Bank::from_snapshot(2, state)
+            .check_invariants()
+            .unwrap_err();
+        assert_eq!(
+            violations,
+            vec![
+                InvariantViolation::UnknownAccount {
+                    transfer: first.id(),
+                    account: 0
+                },
+                InvariantViolation::MissingFromHistory {
+                    transfer: first.id(),
+                    account: 1
+                },
+                InvariantViolation::MissingFromHistory {
+                    transfer: transfer.id(),
+                    account: 2
+                },
+                InvariantViolation::MissingDependency {
+                    transfer: transfer.id(),
+                    dependency: first.id()
+                },
+                InvariantViolation::NegativeBalance { account: 1 },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rate_limit_after_catching_up() {
+        let mut ledger = Ledger::new(&[100, 0, 0]);
+        ledger.configure(|bank| bank.with_rate_limit(2, 3));
+        let lagging = ledger.bank(2).clone();
+        ledger.transfer(0, 1, 10);
+        ledger.transfer(0, 1, 10);
+
+        let mut absorbed = lagging.clone();
+        let missing = ledger.bank(0).missing_from(&absorbed.known_transfers());
+        assert_eq!(absorbed.absorb(missing), vec![]);
+        let mut merged = lagging;
+        merged.merge(ledger.bank(0)).unwrap();
+
+        // Both ways of catching up agree with the peers on the rate limit,
+        // and on when it lifts
+        let mut replicas = [ledger.bank(2).clone(), absorbed, merged];
+        let op = ledger.bank(0).transfer(0, 1, 10).unwrap();
+        for bank in replicas.iter() {
+            assert_eq!(
+                bank.validate(&0, &op),
+                Err(ValidationError::RateLimited {
+                    max_transfers: 2,
+                    window: 3
+                })
+            );
+        }
+        for bank in replicas.iter_mut() {
+            for key in &["name", "city"] {
+                let op = bank.set_account_metadata(1, key.to_string(), "x".to_string());
+                bank.apply(op);
+            }
+            assert_eq!(bank.validate(&0, &op), Ok(()));
+        }
+    }
+
+    #[test]
+    fn test_rate_limit() {
+        let mut ledger = Ledger::new(&[100, 0]);
+        ledger.configure(|bank| bank.with_rate_limit(2, 3));
+        ledger.transfer(0, 1, 10);
+        ledger.transfer(0, 1, 10);
+
+        assert_eq!(
+            ledger
+                .bank(0)
+                .transfer(0, 1, 10)
+                .and_then(|op| ledger.bank(1).validate(&0, &op)),
+            Err(ValidationError::RateLimited {
+                max_transfers: 2,
+                window: 3
+            })
+        );
+        // Other senders are not limited
+        ledger.transfer(1, 0, 5);
+
+        // Once the window has moved past the first transfer, the sender may transfer again
+        let op = ledger
+            .bank(0)
+            .set_account_metadata(0, "name".to_string(), "alice".to_string());
+        ledger.exec(0, op);
+        ledger.transfer(0, 1, 10);
+        ledger.transfer(0, 1, 10);
+        assert_eq!(ledger.bank(1).balance(&1), 35);
+
+        // The transfers tracked for rate limiting survive a snapshot
+        let restored = Bank::from_snapshot(1, ledger.bank(1).snapshot());
+        let op = ledger.bank(0).transfer(0, 1, 10).unwrap();
+        assert_eq!(
+            restored.validate(&0, &op),
+            Err(ValidationError::RateLimited {
+                max_transfers: 2,
+                window: 3
+            })
+        );
+    }
+
+    #[test]
+    fn test_aliases() {
+        let mut ledger = Ledger::new(&[100, 0, 0]);
+        let op = ledger.bank(1).register_alias(1, "bob".to_string());
+        ledger.exec(1, op);
+        assert_eq!(ledger.bank(0).resolve_alias("bob"), Some(&1));
+        assert_eq!(ledger.bank(0).resolve_alias("carol"), None);
+
+        let op = ledger.bank(0).transfer_to_alias(0, "bob", 30).unwrap();
+        ledger.exec(0, op);
+        assert_eq!(ledger.bank(2).balance(&1), 30);
+        assert_eq!(
+            ledger.bank(0).transfer_to_alias(0, "carol", 30),
+            Err(ValidationError::UnknownAlias {
+                alias: "carol".to_string()
+            })
+        );
+
+        let bank = ledger.bank(2);
+        assert_eq!(
+            bank.validate(&2, &bank.register_alias(2, "bob".to_string())),
+            Err(ValidationError::AliasTaken {
+                alias: "bob".to_string()
+            })
+        );
+        for alias in ["", "Bob", "bob!", &"b".repeat(MAX_ALIAS_LEN + 1)].iter() {
+            assert_eq!(
+                bank.validate(&2, &bank.register_alias(2, alias.to_string())),
+                Err(ValidationError::InvalidAlias {
+                    alias: alias.to_string()
+                })
+            );
+        }
+
+        // Only open, unfrozen accounts can register aliases
+        assert_eq!(
+            bank.validate(&3, &bank.register_alias(3, "dave".to_string())),
+            Err(ValidationError::FromAccountDoesNotExist { from: 3 })
+        );
+        let mut frozen = bank.clone().with_admin(2);
+        let op = frozen.freeze(2).unwrap();
+        frozen.apply(op);
+        assert_eq!(
+            frozen.validate(&2, &frozen.register_alias(2, "carol".to_string())),
+            Err(ValidationError::AccountFrozen { account: 2 })
+        );
+
+        // A concurrent registration never takes over an alias already
+        // registered, and merging replicas that disagree is refused
+        let (mut a, mut b) = (bank.clone(), bank.clone());
+        let (for_0, for_2) = (
+            bank.register_alias(0, "carol".to_string()),
+            bank.register_alias(2, "carol".to_string()),
+        );
+        a.apply(for_0.clone());
+        a.apply(for_2.clone());
+        b.apply(for_2);
+        b.apply(for_0);
+        assert_eq!(a.resolve_alias("carol"), Some(&0));
+        assert_eq!(b.resolve_alias("carol"), Some(&2));
+        assert_eq!(
+            a.merge(&b),
+            Err(MergeError::AliasMismatch {
+                alias: "carol".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn test_transfers_in_range() {
+        let mut ledger = Ledger::new(&[100, 0, 0]);
+        let transfers: Vec<_> = [(0, 1, 1), (0, 2, 50), (2, 1, 20), (0, 1, 10)]
+            .iter()
+            .map(
+                |(from, to, amount)| match ledger.transfer(*from, *to, *amount) {
+                    Op::Transfer(transfer) => transfer,
+                    _ => panic!("expected a Transfer op"),
+                },
+            )
+            .collect();
+
+        let in_range = |min, max| -> Vec<_> {
+            ledger
+                .bank(1)
+                .transfers_in_range(min, max)
+                .cloned()
+                .collect()
+        };
+        assert_eq!(
+            in_range(10, 20),
+            vec![transfers[3].clone(), transfers[2].clone()]
+        );
+        assert_eq!(in_range(50, Money::MAX), vec![transfers[1].clone()]);
+        assert_eq!(in_range(0, Money::MAX).len(), 4);
+        assert_eq!(in_range(2, 9), vec![]);
+    }
+
+    #[test]
+    fn test_bank_view() {
+        let mut ledger = Ledger::new(&[100, 50, 0]);
+        ledger.transfer(0, 1, 30);
+        ledger.transfer(1, 2, 60);
+
+        let view = ledger.bank(2).view();
+        assert_eq!(view.balance(&1), 20);
+        assert_eq!(view.accounts().copied().collect::<Vec<_>>(), vec![0, 1, 2]);
+        assert_eq!(view.transfers_for(&1).count(), 2);
+        assert_eq!(view.transfers_for(&2).count(), 1);
+        assert_eq!(view.total_supply(), 150);
+
+        // Views are shared between readers without cloning the bank
+        std::thread::scope(|s| {
+            let readers: Vec<_> = (0..3)
+                .map(|actor| s.spawn(move || view.balance(&actor)))
+                .collect();
+            let balances: Vec<Money> = readers.into_iter().map(|r| r.join().unwrap()).collect();
+            assert_eq!(balances, vec![70, 20, 60]);
+        });
+    }
+
+    #[test]
+    fn test_checkpoint_account_preserves_balances() {
+        let mut ledger = Ledger::new(&[100, 50, 0]);
+        ledger.transfer(0, 1, 30);
+        let spent = ledger.transfer(1, 2, 60);
+        ledger.transfer(2, 1, 10);
+        ledger.transfer(0, 1, 5);
+        let balances = ledger.bank(0).balances();
+
+        // Every replica checkpoints at the same point.  The transfers 1 has
+        // yet to spend are kept as dependencies of its next transfer.
+        for bank in ledger.0.iter_mut() {
+            assert_eq!(bank.checkpoint_account(&1), 2);
+            assert_eq!(bank.balances(), balances);
+            assert_eq!(bank.initial_balance(&1), 20);
+            assert_eq!(bank.next_seq(&1), 1);
+            assert_eq!(bank.check_invariants(), Ok(()));
+        }
+        assert_eq!(ledger.bank(0).transfer_count(), 4);
+
+        // Re-delivering a collapsed transfer is still a no-op
+        for bank in ledger.0.iter_mut() {
+            bank.apply(spent.clone());
+            assert_eq!(bank.balances(), balances);
+        }
+
+        ledger.transfer(1, 0, 35);
+        for bank in ledger.0.iter() {
+            assert_eq!(bank.balance(&0), 100);
+            assert_eq!(bank.balance(&1), 0);
+            assert_eq!(bank.balance(&2), 50);
+            assert_eq!(bank.check_invariants(), Ok(()));
+        }
+        assert_eq!(ledger.0[1].checkpoint_account(&1), 3);
+        assert_eq!(ledger.bank(1).balance(&1), 0);
+    }
+
+    #[test]
+    fn test_display() {
+        let mut ledger = Ledger::new(&[100, 0, 0]);
+        ledger.transfer(0, 1, 30);
+        ledger.transfer(0, 1, 5);
+        let op = ledger.bank(1).transfer(1, 2, 10).unwrap();
+        assert_eq!(op.to_string(), "Transfer 10 from 1 to 2 (2 deps)");
+        if let Op::Transfer(transfer) = &op {
+            assert_eq!(transfer.to_string(), op.to_string());
+        }
+
+        let transfer = TransferBuilder::new(0, 1, 5).fee(1).build();
+        let op: Op<Actor> = Op::TransferFrom {
+            spender: 2,
+            transfer,
+        };
+        assert_eq!(
+            op.to_string(),
+            "TransferFrom 5 from 0 to 1 by 2 fee 1 (0 deps)"
+        );
+
+        let op = ledger.bank(0).open_account(3, 5);
+        assert_eq!(op.to_string(), "OpenAccount owner=3 balance=5");
+        let op = ledger.bank(0).register_alias(0, "alice".to_string());
+        assert_eq!(op.to_string(), "RegisterAlias owner=0 alias=alice");
+    }
+
+    #[test]
+    fn test_find_transfer() {
+        let mut ledger = Ledger::new(&[100, 0]);
+        let transfers: Vec<_> = [(0, 1, 30), (1, 0, 10)]
+            .iter()
+            .map(
+                |(from, to, amount)| match ledger.transfer(*from, *to, *amount) {
+                    Op::Transfer(transfer) => transfer,
+                    _ => panic!("expected a Transfer op"),
+                },
+            )
+            .collect();
+
+        let restored = Bank::from_snapshot(2, ledger.bank(0).snapshot());
+        for bank in [ledger.bank(0), ledger.bank(1), &restored].iter() {
+            for transfer in transfers.iter() {
+                assert_eq!(bank.find_transfer(&transfer.id()), Some(transfer));
+            }
+        }
+
+        let unknown = TransferBuilder::<Actor>::new(0, 1, 30).seq(5).build();
+        assert_eq!(ledger.bank(0).find_transfer(&unknown.id()), None);
+    }
+
+    #[test]
+    fn test_is_effective() {
+        let mut ledger = Ledger::new(&[100, 0]);
+        let bank = ledger.bank(0);
+        assert!(bank.is_effective(&0, &bank.transfer(0, 1, 10).unwrap()));
+        assert!(!bank.is_effective(&0, &bank.transfer(0, 1, 0).unwrap()));
+        assert!(!bank.is_effective(&0, &bank.transfer(0, 0, 10).unwrap()));
+
+        // Ops that would be rejected have no effect
+        assert!(!bank.is_effective(&1, &bank.transfer(0, 1, 10).unwrap()));
+        assert!(!bank.is_effective(&0, &bank.open_account(0, 0)));
+        assert!(bank.is_effective(&2, &bank.open_account(2, 0)));
+
+        let set_name = |name: &str| Op::SetAccountMetadata {
+            owner: 0,
+            key: "name".to_string(),
+            value: name.to_string(),
+        };
+        assert!(bank.is_effective(&0, &set_name("alice")));
+        ledger.exec(0, set_name("alice"));
+        assert!(!ledger.bank(0).is_effective(&0, &set_name("alice")));
+        assert!(ledger.bank(0).is_effective(&0, &set_name("bob")));
+
+        let approve = ledger.bank(0).approve(0, 1, 20);
+        assert!(ledger.bank(0).is_effective(&0, &approve));
+        ledger.exec(0, approve.clone());
+        assert!(!ledger.bank(0).is_effective(&0, &approve));
+    }
+
+    #[test]
+    fn test_anti_entropy() {
+        let mut ledger = Ledger::new(&[100, 50, 0]);
+        ledger.transfer(0, 1, 30);
+        let mut stale = ledger.bank(2).clone();
+        ledger.transfer(1, 2, 60);
+        ledger.transfer(2, 0, 10);
+        ledger.transfer(0, 2, 5);
+        ledger.transfer(2, 1, 20);
+
+        let mut missing = ledger.bank(0).missing_from(&stale.known_transfers());
+        assert_eq!(missing.len(), 4);
+
+        // Transfers are applied once their dependencies are, whatever the order
+        missing.reverse();
+        assert_eq!(stale.absorb(missing.clone()), vec![]);
+        assert_eq!(stale.balances(), ledger.bank(0).balances());
+        assert_eq!(stale.known_transfers(), ledger.bank(0).known_transfers());
+        assert!(ledger
+            .bank(0)
+            .missing_from(&stale.known_transfers())
+            .is_empty());
+
+        // Absorbing is idempotent
+        assert_eq!(stale.absorb(missing), vec![]);
+        assert_eq!(stale.transfer_count(), 5);
+
+        let forged = TransferBuilder::new(1, 0, 1000).seq(1).build();
+        let rejected = stale.absorb(vec![forged.clone()]);
+        assert_eq!(rejected.len(), 1);
+        assert_eq!(rejected[0].0, forged);
+    }
+
+    #[test]
+    fn test_current_supply() {
+        let mut ledger = Ledger::new(&[100, 50, 0]);
+        ledger.transfer(0, 1, 30);
+        ledger.transfer(1, 2, 60);
+        let pending = ledger.bank(2).initiate_transfer(2, 0, 25).unwrap();
+        ledger.exec(2, pending);
+        let op = ledger.bank(0).open_account(3, 40);
+        ledger.exec(3, op);
+        for bank in ledger.0.iter_mut() {
+            bank.checkpoint_account(&1);
+        }
+
+        // The running supply matches a recomputation from the ledger
+        let bank = ledger.bank(0);
+        let recomputed = Bank::from_snapshot(0, bank.snapshot());
+        assert_eq!(bank.current_supply(), 190);
+        assert_eq!(recomputed.current_supply(), 190);
+        let mut merged = recomputed.clone();
+        merged.merge(bank).unwrap();
+        assert_eq!(merged.current_supply(), 190);
+
+        // Initial balances no longer add up once a checkpoint rewrote one,
+        // and balances leave out the amount reserved by the pending transfer
+        assert_eq!(bank.total_supply(), 160);
+        let balances: Money = bank.balances().values().sum();
+        assert_eq!(balances, 165);
+    }
+
+    #[test]
+    fn test_minimal_deps_for() {
+        let mut ledger = Ledger::new(&[100, 100, 0]);
+        let incoming: Vec<_> = [(0, 20), (1, 50), (0, 30)]
+            .iter()
+            .map(|(from, amount)| match ledger.transfer(*from, 2, *amount) {
+                Op::Transfer(transfer) => transfer,
+                _ => panic!("expected a Transfer op"),
+            })
+            .collect();
+
+        let bank = ledger.bank(2);
+        assert_eq!(bank.minimal_deps_for(0).len(), 0);
+        assert_eq!(
+            bank.minimal_deps_for(50).into_iter().collect::<Vec<_>>(),
+            vec![incoming[1].clone()]
+        );
+        assert_eq!(bank.minimal_deps_for(70).len(), 2);
+        assert_eq!(bank.minimal_deps_for(1000).len(), 3);
+
+        // A transfer carrying fewer deps is accepted, leaving the others
+        // as deps of the next transfer
+        let transfer = TransferBuilder::new(2, 0, 60)
+            .seq(bank.next_seq(&2))
+            .deps(bank.minimal_deps_for(60))
+            .build();
+        ledger.exec(2, Op::Transfer(transfer));
+        assert_eq!(ledger.bank(2).balance(&2), 40);
+        assert_eq!(
+            ledger
+                .bank(2)
+                .minimal_deps_for(1000)
+                .into_iter()
+                .collect::<Vec<_>>(),
+            vec![incoming[0].clone()]
+        );
+    }
+
+    #[test]
+    fn test_same_ledger() {
+        let mut ledger = Ledger::new(&[100, 0]);
+        assert_ne!(ledger.bank(0), ledger.bank(1));
+        assert!(ledger.bank(0).same_ledger(ledger.bank(1)));
+
+        let stale = ledger.bank(1).clone();
+        ledger.transfer(0, 1, 10);
+        assert!(ledger.bank(0).same_ledger(ledger.bank(1)));
+        assert!(!ledger.bank(0).same_ledger(&stale));
+
+        // Compacting history leaves the ledger the same
+        ledger.transfer(1, 0, 5);
+        ledger.transfer(0, 1, 5);
+        let mut compacted = ledger.bank(1).clone();
+        compacted.compact();
+        assert_ne!(&compacted, ledger.bank(1));
+        assert!(ledger.bank(0).same_ledger(&compacted));
+    }
+
+    #[test]
+    fn test_state_hash() {
+        let mut ledger = Ledger::new(&[100, 0]);
+        assert_eq!(ledger.bank(0).state_hash(), ledger.bank(1).state_hash());
+
+        let stale = ledger.bank(1).state_hash();
+        ledger.transfer(0, 1, 10);
+        assert_eq!(ledger.bank(0).state_hash(), ledger.bank(1).state_hash());
+        assert_ne!(ledger.bank(0).state_hash(), stale);
+
+        // Compacting history leaves the digest unchanged
+        ledger.transfer(1, 0, 5);
+        ledger.transfer(0, 1, 5);
+        let mut compacted = ledger.bank(1).clone();
+        compacted.compact();
+        assert_ne!(&compacted, ledger.bank(1));
+        assert_eq!(compacted.state_hash(), ledger.bank(0).state_hash());
+    }
+
+    #[test]
+    fn test_recover_account() {
+        let mut ledger = Ledger::new(&[0, 50]);
+        let op = ledger.bank(0).open_account_with_recovery(2, 100, 1);
+        ledger.exec(2, op);
+        ledger.transfer(1, 2, 20);
+        assert_eq!(ledger.bank(0).recovery_actor(&2), Some(&1));
+        assert_eq!(ledger.bank(0).recovery_actor(&1), None);
+
+        // Only the recovery actor may recover the account
+        let op = ledger.bank(0).recover_account(2, 3);
+        assert_eq!(
+            ledger.bank(0).validate(&2, &op),
+            Err(ValidationError::NotInitiatedByRecoveryActor {
+                initiator: 2,
+                owner: 2
+            })
+        );
+        assert_eq!(
+            ledger
+                .bank(0)
+                .validate(&1, &ledger.bank(0).recover_account(2, 0)),
+            Err(ValidationError::OwnerAlreadyHasAnAccount)
+        );
+
+        let mut bank = ledger.bank(0).clone();
+        assert_eq!(
+            bank.apply_with_effects(op.clone()),
+            Ok(AppliedEffect::Transfer {
+                from: 2,
+                to: 3,
+                amount: 120,
+                new_from_balance: 0,
+                new_to_balance: 120,
+            })
+        );
+        ledger.exec(1, op);
+        for bank in ledger.0.iter() {
+            assert_eq!(bank.balance(&2), 0);
+            assert_eq!(bank.balance(&3), 120);
+            assert!(bank.is_frozen(&2));
+            assert_eq!(bank.recovery_actor(&3), Some(&1));
+            assert_eq!(bank.current_supply(), 150);
+            assert_eq!(bank.check_invariants(), Ok(()));
+        }
+
+        // The old account can't be recovered again
+        let op = ledger.bank(0).recover_account(2, 4);
+        assert_eq!(
+            ledger.bank(0).validate(&1, &op),
+            Err(ValidationError::AccountFrozen { account: 2 })
+        );
+
+        // The new account is usable right away, the sweep being its proof of funds
+        let bank = ledger.bank(0);
+        let transfer = TransferBuilder::new(3, 1, 70)
+            .seq(bank.next_seq(&3))
+            .deps(bank.incoming_transfers(&3))
+            .build();
+        ledger.exec(3, Op::Transfer(transfer));
+        assert_eq!(ledger.bank(0).balance(&1), 100);
+        assert_eq!(ledger.bank(0).balance(&3), 50);
+    }
+
+    #[test]
+    fn test_recover_liability_account() {
+        let mut ledger = Ledger::new(&[0, 100]);
+        let op = match ledger.bank(0).open_account_with_recovery(2, 0, 1) {
+            Op::OpenAccount {
+                owner,
+                balance,
+                currency,
+                recovery,
+                ..
+            } => Op::OpenAccount {
+                owner,
+                balance,
+                currency,
+                recovery,
+                kind: AccountKind::Liability,
+            },
+            _ => panic!("expected an OpenAccount op"),
+        };
+        ledger.exec(2, op);
+        let op = ledger.bank(0).register_alias(2, "treasury".to_string());
+        ledger.exec(2, op);
+        let op = ledger
+            .bank(0)
+            .set_account_metadata(2, "name".to_string(), "Treasury".to_string());
+        ledger.exec(2, op);
+        let bank = ledger.bank(0);
+        let transfer = TransferBuilder::new(2, 1, 30)
+            .seq(bank.next_seq(&2))
+            .deps(bank.incoming_transfers(&2))
+            .build();
+        ledger.exec(2, Op::Transfer(transfer));
+
+        // The debt would be left behind on the frozen account
+        let op = ledger.bank(0).recover_account(2, 3);
+        assert_eq!(
+            ledger.bank(0).validate(&1, &op),
+            Err(ValidationError::AccountInDebt {
+                account: 2,
+                balance: -30
+            })
+        );
+
+        // Once paid back, the account moves over along with its attributes
+        ledger.transfer(1, 2, 50);
+        ledger.exec(1, op);
+        for bank in ledger.0.iter() {
+            assert_eq!(bank.account_kind(&3), Some(AccountKind::Liability));
+            assert_eq!(bank.balance(&3), 20);
+            assert_eq!(bank.resolve_alias("treasury"), Some(&3));
+            assert_eq!(
+                bank.account_metadata(&3).and_then(|m| m.get("name")),
+                Some(&"Treasury".to_string())
+            );
+            assert!(bank.is_frozen(&2));
+            assert_eq!(bank.check_invariants(), Ok(()));
+        }
+    }
+
+    #[test]
+    fn test_max_accounts() {
+        let mut ledger = Ledger::new(&[100]);
+        ledger.configure(|bank| bank.with_max_accounts(3));
+        assert_eq!(ledger.bank(0).max_accounts(), Some(3));
+        for owner in 1..3 {
+            let op = ledger.bank(0).open_account(owner, 0);
+            ledger.exec(owner, op);
+        }
+
+        let op = ledger.bank(0).open_account(3, 0);
+        assert_eq!(
+            ledger.bank(0).validate(&3, &op),
+            Err(ValidationError::AccountLimitReached { max_accounts: 3 })
+        );
+    }
+
+    #[test]
+    fn test_balance_series() {
+        let mut ledger = Ledger::new(&[100, 0, 0]);
+        let transfers: Vec<_> = [(0, 1, 30), (1, 2, 20), (0, 1, 5), (1, 0, 15)]
+            .iter()
+            .map(
+                |(from, to, amount)| match ledger.transfer(*from, *to, *amount) {
+                    Op::Transfer(transfer) => transfer,
+                    _ => panic!("expected a Transfer op"),
+                },
+            )
+            .collect();
+
+        let series = ledger.bank(2).balance_series(&1);
+        let ids: Vec<_> = series.iter().map(|(id, _)| *id).collect();
+        let order: Vec<_> = ledger
+            .bank(2)
+            .transfers_causal_order(&1)
+            .iter()
+            .map(|t| t.id())
+            .collect();
+        assert_eq!(ids, order);
+        assert_eq!(series.len(), 4);
+        assert_eq!(series[0], (transfers[2].id(), 5));
+        assert_eq!(
+            series.last().map(|(_, b)| *b),
+            Some(ledger.bank(2).balance(&1))
+        );
+        assert_eq!(
+            ledger.bank(2).balance_series(&2),
+            vec![(transfers[1].id(), 20)]
+        );
+        assert_eq!(ledger.bank(2).balance_series(&7), vec![]);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "Applying an op that fails validation")]
+    fn test_debug_validation_catches_unvalidated_ops() {
+        let mut ledger = Ledger::new(&[100, 0]);
+        ledger.configure(|bank| bank.with_debug_validation());
+        ledger.transfer(0, 1, 10);
+
+        // An overdraft that skipped validation
+        let mut bank = ledger.bank(1).clone();
+        let overdraft = TransferBuilder::new(1, 0, 50).seq(bank.next_seq(&1));
+        bank.apply(Op::Transfer(overdraft.build()));
+    }
+
+    #[test]
+    fn test_account_activity() {
+        let mut ledger = Ledger::new(&[100, 50, 0]);
+        ledger.transfer(0, 1, 30);
+        ledger.transfer(1, 2, 60);
+        ledger.transfer(0, 1, 5);
+        ledger.transfer(1, 1, 10);
+        ledger.transfer(2, 0, 15);
+
+        assert_eq!(
+            ledger.bank(2).account_activity(&1),
+            AccountActivity {
+                incoming_count: 3,
+                outgoing_count: 2,
+                incoming_volume: 45,
+                outgoing_volume: 70,
+            }
+        );
+        assert_eq!(
+            ledger.bank(2).account_activity(&2),
+            AccountActivity {
+                incoming_count: 1,
+                outgoing_count: 1,
+                incoming_volume: 60,
+                outgoing_volume: 15,
+            }
+        );
+    }
+
+    #[test]
+    fn test_duplicate_transfer() {
+        let mut ledger = Ledger::new(&[100, 0]);
+        let op = ledger.transfer(0, 1, 30);
+        let id = match &op {
+            Op::Transfer(transfer) => transfer.id(),
+            _ => panic!("Expected a transfer op"),
+        };
+
+        assert_eq!(
+            ledger.bank(0).validate(&0, &op),
+            Err(ValidationError::DuplicateTransfer { id })
+        );
+        assert_eq!(ledger.bank(1).balance(&1), 30);
+    }
+
+    #[test]
+    fn test_with_config() {
+        let config = BankConfig {
+            fee_collector: Some(2),
+            admin: Some(3),
+            max_deps: 4,
+            max_initial_balance: 100,
+            max_accounts: Some(5),
+            arithmetic_mode: ArithmeticMode::Saturate,
+            rate_limit: Some((6, 7)),
+            debug_validation: false,
+        };
+        let bank: Bank<Actor> = Bank::with_config(0, config.clone());
+        assert_eq!(bank.config(), config);
+        assert_eq!(bank.fee_collector(), Some(&2));
+        assert_eq!(bank.max_accounts(), Some(5));
+
+        let bank: Bank<Actor> = Bank::new(0);
+        assert_eq!(bank.config(), BankConfig::default());
+        assert_eq!(
+            bank.with_admin(3).with_max_deps(4).config(),
+            BankConfig {
+                admin: Some(3),
+                max_deps: 4,
+                ..BankConfig::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_adjust_balance() {
+        let mut ledger = Ledger::new(&[100, 50, 0]);
+        for bank in ledger.0.iter_mut() {
+            *bank = bank.clone().with_admin(2);
+        }
+
+        let op = ledger.bank(2).adjust_balance(1, 80).unwrap();
+        ledger.exec(2, op);
+        let op = ledger.bank(2).adjust_balance(0, 40).unwrap();
+        ledger.exec(2, op);
+        assert_eq!(ledger.bank(0).balance(&0), 40);
+        assert_eq!(ledger.bank(0).balance(&1), 80);
+        assert_eq!(ledger.bank(0).current_supply(), 120);
+        // Only the balances accounts were opened with count towards this
+        assert_eq!(ledger.bank(0).total_supply(), 150);
+        assert_eq!(
+            ledger
+                .bank(0)
+                .balance_adjustments(&1)
+                .map(|a| (a.previous_balance, a.new_balance))
+                .collect::<Vec<_>>(),
+            vec![(50, 80)]
+        );
+
+        // Adjusted balances can be spent like any other
+        ledger.transfer(1, 0, 80);
+        assert_eq!(ledger.bank(2).balance(&0), 120);
+        assert_eq!(ledger.bank(2).balance(&1), 0);
+        assert_eq!(ledger.bank(2).balances(), ledger.bank(1).balances());
+        assert!(ledger.bank(2).check_invariants().is_ok());
+
+        let op = Op::AdjustBalance {
+            owner: 1,
+            new_balance: 1000,
+        };
+        assert_eq!(
+            ledger.bank(0).validate(&1, &op),
+            Err(ValidationError::NotInitiatedByAdmin { initiator: 1 })
+        );
+        assert!(Ledger::new(&[100]).bank(0).adjust_balance(0, 0).is_err());
+    }
+
+    #[test]
+    fn test_next_transfer_size_estimate() {
+        let mut ledger = Ledger::new(&[100, 100, 100]);
+        let initial = ledger.bank(0).next_transfer_size_estimate(10);
+
+        ledger.transfer(1, 0, 10);
+        let one_dep = ledger.bank(0).next_transfer_size_estimate(10);
+        assert!(one_dep > initial);
+
+        ledger.transfer(2, 0, 10);
+        let two_deps = ledger.bank(0).next_transfer_size_estimate(10);
+        assert!(two_deps > one_dep);
+
+        let op = ledger.transfer(0, 1, 10);
+        match op {
+            Op::Transfer(transfer) => assert_eq!(transfer.serialized_size(), two_deps),
+            _ => panic!("Expected a transfer op"),
+        }
+    }
+
+    #[test]
+    fn test_reverse() {
+        let mut ledger = Ledger::new(&[100, 10]);
+        let transfer = match ledger.transfer(0, 1, 30) {
+            Op::Transfer(transfer) => transfer,
+            _ => panic!("Expected a transfer op"),
+        };
+
+        // Only the recipient can refund the transfer
+        assert_eq!(ledger.bank(0).reverse(&transfer), None);
+
+        let op = ledger.bank(1).reverse(&transfer).unwrap();
+        ledger.exec(1, op);
+        assert_eq!(ledger.bank(0).balance(&0), 100);
+        assert_eq!(ledger.bank(0).balance(&1), 10);
+
+        // Refunding takes funds the recipient no longer has
+        ledger.transfer(1, 0, 10);
+        assert_eq!(ledger.bank(1).reverse(&transfer), None);
+    }
+
+    #[test]
+    fn test_iterate_balances() {
+        let mut ledger = Ledger::new(&[100, 50, 0]);
+        ledger.transfer(0, 2, 30);
+
+        let mut balances = vec![];
+        for (actor, balance) in ledger.bank(1) {
+            balances.push((actor, balance));
+        }
+        assert_eq!(balances, vec![(0, 70), (1, 50), (2, 30)]);
+    }
+
+    #[test]
+    fn test_prune_to_retention() {
+        let mut ledger = Ledger::new(&[100, 0]);
+        for _ in 0..5 {
+            ledger.transfer(0, 1, 10);
+        }
+        ledger.transfer(1, 0, 50);
+        ledger.transfer(0, 1, 20);
+        let balances = ledger.bank(0).balances();
+
+        for bank in ledger.0.iter_mut() {
+            assert_eq!(bank.prune_to_retention(), 0);
+            bank.set_retention(2);
+            assert_eq!(bank.retention(), Some(2));
+            bank.prune_to_retention();
+            assert_eq!(bank.balances(), balances);
+            assert_eq!(bank.transfers_causal_order(&0).len(), 2);
+            assert_eq!(bank.check_invariants(), Ok(()));
+        }
+
+        // 1 has yet to spend the last transfer it received, so it is kept
+        // to prove 1's funds, whatever the retention
+        for bank in ledger.0.iter_mut() {
+            bank.set_retention(0);
+            bank.prune_to_retention();
+            assert_eq!(bank.transfers_causal_order(&1).len(), 1);
+            assert_eq!(bank.balances(), balances);
+        }
+
+        ledger.transfer(1, 0, 20);
+        for bank in ledger.0.iter() {
+            assert_eq!(bank.balance(&0), 100);
+            assert_eq!(bank.balance(&1), 0);
+            assert_eq!(bank.check_invariants(), Ok(()));
+        }
+    }
+
+    #[test]
+    fn test_validate_verbose() {
+        let ledger = Ledger::new(&[100, 0]);
+        let op = ledger.bank(0).transfer(0, 1, 40).unwrap();
+        assert_eq!(ledger.bank(0).validate_verbose(&0, &op), (Ok(()), vec![]));
+
+        let op = ledger.bank(0).transfer(0, 1, 100).unwrap();
+        assert_eq!(
+            ledger.bank(0).validate_verbose(&0, &op),
+            (
+                Ok(()),
+                vec![ValidationWarning::DrainsAccount { account: 0 }]
+            )
+        );
+
+        let op = ledger.bank(1).transfer(1, 1, 0).unwrap();
+        assert_eq!(
+            ledger.bank(1).validate_verbose(&1, &op),
+            (
+                Ok(()),
+                vec![
+                    ValidationWarning::ZeroAmount,
+                    ValidationWarning::SelfTransfer { account: 1 },
+                    ValidationWarning::DrainsAccount { account: 1 },
+                ]
+            )
+        );
+
+        // Warnings are reported along with validation errors
+        let (result, warnings) = ledger.bank(1).validate_verbose(&0, &op);
+        assert!(result.is_err());
+        assert_eq!(warnings.len(), 3);
+
+        let mut ledger = Ledger::new(&vec![1; MANY_DEPS_WARNING_THRESHOLD + 2]);
+        for actor in 1..=MANY_DEPS_WARNING_THRESHOLD + 1 {
+            ledger.transfer(actor as Actor, 0, 1);
+        }
+        let op = ledger.bank(0).transfer(0, 1, 1).unwrap();
+        assert_eq!(
+            ledger.bank(0).validate_verbose(&0, &op).1,
+            vec![ValidationWarning::ManyDeps {
+                deps: MANY_DEPS_WARNING_THRESHOLD + 1
+            }]
+        );
+    }
+
+    #[test]
+    fn test_decimal_strings() {
+        assert_eq!(1234u64.to_decimal_string(2), "12.34");
+        assert_eq!(1200u64.to_decimal_string(2), "12.00");
+        assert_eq!(5u64.to_decimal_string(3), "0.005");
+        assert_eq!(0u64.to_decimal_string(2), "0.00");
+        assert_eq!(12u64.to_decimal_string(0), "12");
+        assert_eq!(Money::MAX.to_decimal_string(19), "1.8446744073709551615");
+
+        assert_eq!(Money::from_decimal_string("12.34", 2), Ok(1234));
+        assert_eq!(Money::from_decimal_string("12.3", 2), Ok(1230));
+        assert_eq!(Money::from_decimal_string("12.340", 2), Ok(1234));
+        assert_eq!(Money::from_decimal_string("12", 2), Ok(1200));
+        assert_eq!(Money::from_decimal_string("007", 0), Ok(7));
+        assert_eq!(Money::from_decimal_string("12.0", 0), Ok(12));
+        assert_eq!(
+            Money::from_decimal_string("12.345", 2),
+            Err(ParseMoneyError::TooPrecise)
+        );
+        for invalid in [
+            "", ".", "12.", ".5", "-1", "+1", "1,000", " 1", "1e3", "1.2.3",
+        ]
+        .iter()
+        {
+            assert_eq!(
+                Money::from_decimal_string(invalid, 2),
+                Err(ParseMoneyError::Invalid)
+            );
+        }
+        assert_eq!(
+            Money::from_decimal_string("18446744073709551616", 0),
+            Err(ParseMoneyError::Overflow)
+        );
+        assert_eq!(
+            u8::from_decimal_string("2.56", 2),
+            Err(ParseMoneyError::Overflow)
+        );
+
+        let amount: Money = 98_765_432;
+        assert_eq!(
+            Money::from_decimal_string(&amount.to_decimal_string(4), 4),
+            Ok(amount)
+        );
+    }
+
+    #[test]
+    fn test_is_confirmed() {
+        let mut ledger = Ledger::new(&[100, 0]);
+        let transfer = match ledger.bank(0).transfer(0, 1, 30).unwrap() {
+            Op::Transfer(transfer) => transfer,
+            _ => panic!("Expected a transfer op"),
+        };
+        assert!(!ledger.bank(1).is_confirmed(&transfer));
+
+        ledger.exec(0, Op::Transfer(transfer.clone()));
+        assert!(ledger.bank(1).is_confirmed(&transfer));
+
+        // A pending transfer is only confirmed once finalized
+        let op = ledger.bank(0).initiate_transfer(0, 1, 20).unwrap();
+        let pending = match &op {
+            Op::InitiateTransfer(transfer) => transfer.clone(),
+            _ => panic!("Expected an InitiateTransfer op"),
+        };
+        ledger.exec(0, op);
+        assert!(!ledger.bank(1).is_confirmed(&pending));
+
+        let op = ledger.bank(0).finalize_transfer(pending.id()).unwrap();
+        ledger.exec(0, op);
+        assert!(ledger.bank(1).is_confirmed(&pending));
+    }
+
+    #[test]
+    fn test_apply_checked_rejects_overflows() {
+        let mut ledger = Ledger::new(&[100, Money::MAX - 95, Money::MAX - 2]);
+        ledger.configure(|bank| bank.with_fee_collector(2));
+
+        // The fee would overflow the fee collector
+        let op = ledger.bank(0).transfer_with_fee(0, 1, 0, 5).unwrap();
+        let mut bank = ledger.bank(0).clone();
+        assert_eq!(
+            bank.apply_checked(op),
+            Err(ApplyError::BalanceOverflow { account: 2 })
+        );
+        assert_eq!(&bank, ledger.bank(0));
+
+        // The refund of a cancelled transfer would overflow its sender
+        let op = ledger.bank(0).initiate_transfer(0, 1, 10).unwrap();
+        let id = match &op {
+            Op::InitiateTransfer(transfer) => transfer.id(),
+            _ => panic!("Expected an InitiateTransfer op"),
+        };
+        ledger.exec(0, op);
+        ledger.transfer(1, 0, Money::MAX - 95);
+        let mut bank = ledger.bank(0).clone();
+        assert_eq!(
+            bank.apply_checked(Op::CancelTransfer { id }),
+            Err(ApplyError::BalanceOverflow { account: 0 })
+        );
+        assert_eq!(&bank, ledger.bank(0));
+    }
+
+    #[test]
+    fn test_all_transfers_order_is_deterministic() {
+        let mut ledger = Ledger::new(&[100, 100, 100]);
+        let ops: Vec<(Actor, Op<Actor>)> = vec![
+            (0, ledger.bank(0).transfer(0, 1, 10).unwrap()),
+            (1, ledger.bank(1).transfer(1, 2, 20).unwrap()),
+            (2, ledger.bank(2).transfer(2, 0, 30).unwrap()),
+        ];
+
+        for (source, op) in ops.iter() {
+            ledger.0[0].validate(source, op).unwrap();
+            ledger.0[0].apply(op.clone());
+        }
+        for (source, op) in ops.iter().rev() {
+            ledger.0[1].validate(source, op).unwrap();
+            ledger.0[1].apply(op.clone());
+        }
+
+        let ours: Vec<_> = ledger.bank(0).all_transfers().into_iter().collect();
+        let theirs: Vec<_> = ledger.bank(1).all_transfers().into_iter().collect();
+        assert_eq!(ours.len(), 3);
+        assert_eq!(ours, theirs);
+        assert_eq!(
+            ledger.bank(0).export_ledger().transfers,
+            ledger.bank(1).export_ledger().transfers
+        );
+        assert_eq!(ledger.bank(0).state_hash(), ledger.bank(1).state_hash());
+    }
+
+    #[test]
+    fn test_close_and_sweep() {
+        let mut ledger = Ledger::new(&[100, 50, 0]);
+        ledger.configure(|bank| bank.with_admin(2));
+        ledger.transfer(0, 1, 30);
+
+        // Only the owner may close its account
+        let op = ledger.bank(0).close_and_sweep(1, 0);
+        assert_eq!(
+            ledger.bank(0).validate(&0, &op),
+            Err(ValidationError::NotInitiatedByAccountOwner {
+                initiator: 0,
+                owner: 1
+            })
+        );
+        assert_eq!(
+            ledger
+                .bank(1)
+                .validate(&1, &ledger.bank(1).close_and_sweep(1, 1)),
+            Err(ValidationError::SweepToClosingAccount { account: 1 })
+        );
+        assert_eq!(
+            ledger
+                .bank(1)
+                .validate(&1, &ledger.bank(1).close_and_sweep(1, 7)),
+            Err(ValidationError::ToAccountDoesNotExist { to: 7 })
+        );
+
+        // Pending transfers block closing
+        let mut bank = ledger.bank(1).clone();
+        let pending = bank.initiate_transfer(1, 0, 10).unwrap();
+        bank.apply(pending);
+        assert_eq!(
+            bank.validate(&1, &op),
+            Err(ValidationError::AccountHasPendingTransfers { account: 1 })
+        );
+
+        let op = ledger.bank(1).close_and_sweep(1, 0);
+        let mut bank = ledger.bank(1).clone();
+        assert_eq!(
+            bank.apply_with_effects(op.clone()),
+            Ok(AppliedEffect::Transfer {
+                from: 1,
+                to: 0,
+                amount: 80,
+                new_from_balance: 0,
+                new_to_balance: 150,
+            })
+        );
+        ledger.exec(1, op);
+        for bank in ledger.0.iter() {
+            assert_eq!(bank.balance(&0), 150);
+            assert_eq!(bank.balance(&1), 0);
+            assert!(bank.is_closed(&1));
+            assert!(bank.is_frozen(&1));
+            assert!(!bank.is_closed(&0));
+            assert_eq!(bank.current_supply(), 150);
+            assert_eq!(bank.check_invariants(), Ok(()));
+        }
+
+        // The closed account can't receive money, nor be unfrozen
+        assert_eq!(
+            ledger.bank(0).transfer(0, 1, 10),
+            Err(ValidationError::AccountFrozen { account: 1 })
+        );
+        let op = ledger.bank(2).unfreeze(1).unwrap();
+        assert_eq!(
+            ledger.bank(0).validate(&2, &op),
+            Err(ValidationError::AccountClosed { account: 1 })
+        );
+
+        // Closed state survives snapshot/restore
+        let restored = Bank::from_snapshot(0, ledger.bank(0).snapshot());
+        assert!(restored.is_closed(&1));
+    }
+
+    #[test]
+    fn test_transfers_between() {
+        let transfer = |op| match op {
+            Op::Transfer(transfer) => transfer,
+            op => panic!("Not a transfer: {}", op),
+        };
+        let mut ledger = Ledger::new(&[100, 100, 100]);
+        let t1 = transfer(ledger.transfer(0, 1, 10));
+        let t2 = transfer(ledger.transfer(1, 0, 3));
+        ledger.transfer(0, 2, 20);
+        let t3 = transfer(ledger.transfer(2, 1, 5));
+        let t4 = transfer(ledger.transfer(0, 1, 7));
+
+        let bank = ledger.bank(2);
+        let between = bank.transfers_between(&0, &1);
+        assert_eq!(between.len(), 3);
+        for t in [&t1, &t2, &t4].iter() {
+            assert!(between.contains(t));
+        }
+        assert_eq!(between, bank.transfers_between(&1, &0));
+        assert_eq!(bank.net_flow(&0, &1), 14);
+
+        assert_eq!(bank.transfers_between(&1, &2), vec![t3]);
+        assert!(bank.transfers_between(&0, &7).is_empty());
+    }
+
+    #[test]
+    fn test_snapshot_delta() {
+        let mut ledger = Ledger::new(&[100, 50, 0]);
+        ledger.configure(|bank| bank.with_admin(2));
+        ledger.transfer(0, 1, 10);
+        let op = ledger.bank(2).freeze(1).unwrap();
+        ledger.exec(2, op);
+        let base = ledger.bank(0).snapshot();
+
+        let op = ledger.bank(2).unfreeze(1).unwrap();
+        ledger.exec(2, op);
+        ledger.transfer(1, 0, 5);
+        ledger.transfer(0, 2, 20);
+        let op = ledger.bank(0).open_account(3, 30);
+        ledger.exec(3, op);
+        let op = ledger
+            .bank(0)
+            .set_account_metadata(0, "name".to_string(), "zero".to_string());
+        ledger.exec(0, op);
+        let op = ledger.bank(0).initiate_transfer(0, 3, 15).unwrap();
+        ledger.exec(0, op);
+        let op = ledger.bank(1).approve(1, 2, 7);
+        ledger.exec(1, op);
+        ledger.0[0].checkpoint_account(&2);
+        let current = ledger.bank(0).snapshot();
+
+        let delta = current.diff(&base);
+        assert_eq!(delta.added_accounts().collect::<Vec<_>>(), vec![&3]);
+        assert!(delta.added_transfers.values().flatten().count() >= 2);
+        let mut caught_up = base.clone();
+        caught_up.apply_delta(delta);
+        assert_eq!(caught_up, current);
+
+        // Nothing changed, nothing to apply
+        let delta = current.diff(&current);
+        assert!(delta.added_transfers.is_empty() && delta.frozen.is_empty());
+        let mut unchanged = current.clone();
+        unchanged.apply_delta(delta);
+        assert_eq!(unchanged, current);
+    }
+
+    #[test]
+    fn test_mint_balance_is_unissued_money() {
+        // Actor 0 is the mint, pre-funded with all the money it will issue
+        let mut ledger = Ledger::new(&[1000, 0, 0]);
+        ledger.transfer(0, 1, 300);
+        ledger.transfer(0, 2, 200);
+        ledger.transfer(1, 2, 50);
+
+        let bank = ledger.bank(0);
+        let issued = bank.initial_balance(&0) - bank.balance(&0);
+        assert_eq!(issued, 500);
+        assert_eq!(bank.balance(&1) + bank.balance(&2), issued);
+        assert_eq!(bank.current_supply(), 1000);
+
+        // Money flowing back to the mint is unissued again
+        ledger.transfer(2, 0, 100);
+        let bank = ledger.bank(0);
+        assert_eq!(bank.initial_balance(&0) - bank.balance(&0), 400);
+
+        // The mint can't issue more than it has left
+        assert_eq!(
+            bank.transfer(0, 1, 601),
+            Err(ValidationError::InsufficientFunds {
+                balance: 600,
+                transfer_amount: 601
+            })
+        );
+    }
+
+    #[test]
+    fn test_classify_ops() {
+        let mut ledger = Ledger::new(&[100, 0, 0]);
+        let applied = ledger.transfer(0, 1, 10);
+
+        // `dependent` follows `next`, which isn't applied yet
+        let mut bank = ledger.bank(0).clone();
+        let next = bank.transfer(0, 2, 20).unwrap();
+        bank.apply(next.clone());
+        let dependent = bank.transfer(0, 1, 5).unwrap();
+
+        let before = ledger.bank(0).clone();
+        let statuses = ledger.bank(0).classify_ops(&[
+            (0, applied),
+            (0, next.clone()),
+            (0, dependent),
+            (2, next),
+        ]);
+        assert_eq!(statuses[0], OpStatus::AlreadyApplied);
+        assert_eq!(statuses[1], OpStatus::Applicable);
+        assert!(matches!(statuses[2], OpStatus::Blocked(_)));
+        assert!(matches!(
+            statuses[3],
+            OpStatus::Blocked(ValidationError::NotInitiatedByAccountOwner { .. })
+        ));
+        assert!(ledger.bank(0).same_ledger(&before));
+    }
+
+    #[test]
+    fn test_liability_accounts() {
+        let mut ledger = Ledger::new(&[100, 0]);
+        let op = ledger.bank(0).open_liability_account(2);
+        ledger.exec(2, op);
+        assert_eq!(ledger.bank(0).account_kind(&0), Some(AccountKind::Asset));
+        assert_eq!(
+            ledger.bank(0).account_kind(&2),
+            Some(AccountKind::Liability)
+        );
+        assert_eq!(ledger.bank(0).account_kind(&7), None);
+
+        let liability_transfer = |bank: &Bank<Actor>, to, amount| {
+            let transfer = TransferBuilder::new(2, to, amount)
+                .seq(bank.next_seq(&2))
+                .deps(bank.incoming_transfers(&2))
+                .build();
+            Op::Transfer(transfer)
+        };
+
+        // The liability may send money it doesn't hold
+        let op = liability_transfer(ledger.bank(0), 1, 40);
+        ledger.exec(2, op);
+        for bank in ledger.0.iter() {
+            assert_eq!(bank.signed_balance(&2), Some(-40));
+            assert_eq!(bank.balance(&2), 0);
+            assert_eq!(bank.balances()[&2], 0);
+            assert_eq!(bank.balance(&1), 40);
+            assert_eq!(bank.current_supply(), 100);
+            assert_eq!(bank.check_invariants(), Ok(()));
+        }
+
+        // Asset accounts still can't overdraw
+        assert_eq!(
+            ledger.bank(1).transfer(1, 0, 41),
+            Err(ValidationError::InsufficientFunds {
+                balance: 40,
+                transfer_amount: 41
+            })
+        );
+        assert_eq!(ledger.bank(1).signed_balance(&1), Some(40));
+
+        // Paying the liability back raises its balance towards zero
+        ledger.transfer(1, 2, 15);
+        assert_eq!(ledger.bank(0).signed_balance(&2), Some(-25));
+
+        // The debt must be paid back before the account can be closed
+        let bank = ledger.bank(0);
+        assert_eq!(
+            bank.validate(&2, &bank.close_and_sweep(2, 1)),
+            Err(ValidationError::AccountInDebt {
+                account: 2,
+                balance: -25
+            })
+        );
+
+        // Down to minus the maximum amount
+        let op = liability_transfer(ledger.bank(0), 1, Money::MAX - 25);
+        assert_eq!(ledger.bank(0).validate(&2, &op), Ok(()));
+        let op = liability_transfer(ledger.bank(0), 1, Money::MAX - 24);
+        assert_eq!(
+            ledger.bank(0).validate(&2, &op),
+            Err(ValidationError::InsufficientFunds {
+                balance: 0,
+                transfer_amount: Money::MAX - 24
+            })
+        );
+
+        // Liabilities aren't checkpointed, and keep their kind across snapshots
+        let mut bank = ledger.bank(0).clone();
+        assert_eq!(bank.checkpoint_account(&2), 0);
+        let restored = Bank::from_snapshot(0, bank.snapshot());
+        assert_eq!(restored.account_kind(&2), Some(AccountKind::Liability));
+        assert_eq!(restored.signed_balance(&2), Some(-25));
+        let imported = Bank::import_ledger(0, bank.export_ledger()).unwrap();
+        assert_eq!(imported.signed_balance(&2), Some(-25));
+    }
+
+    #[test]
+    fn test_history_depth() {
+        let mut ledger = Ledger::new(&[100, 0, 0]);
+        assert_eq!(ledger.bank(0).history_depth(&0), 0);
+
+        // Each transfer back and forth depends on the previous one
+        for i in 0..4 {
+            let (from, to) = if i % 2 == 0 { (0, 1) } else { (1, 0) };
+            ledger.transfer(from, to, 10);
+        }
+        assert_eq!(ledger.bank(0).history_depth(&0), 3);
+        assert_eq!(ledger.bank(0).history_depth(&1), 3);
+
+        // The chain carries on into the history of the next recipient
+        ledger.transfer(0, 2, 5);
+        assert_eq!(ledger.bank(0).history_depth(&2), 4);
+        assert_eq!(ledger.bank(0).history_depth(&7), 0);
+    }
+
+    #[test]
+    fn test_batch_open() {
+        let mut ledger = Ledger::new(&[100, 0]);
+        assert_eq!(
+            ledger.bank(1).batch_open(vec![(2, 10)]),
+            Err(ValidationError::NotInitiatedByAdmin { initiator: 1 })
+        );
+        ledger.configure(|bank| bank.with_admin(1));
+
+        // A single account already open rejects the whole batch
+        let op = ledger
+            .bank(1)
+            .batch_open(vec![(2, 10), (0, 5), (3, 0)])
+            .unwrap();
+        assert_eq!(
+            ledger.bank(0).validate(&1, &op),
+            Err(ValidationError::OwnerAlreadyHasAnAccount)
+        );
+        let op = ledger
+            .bank(1)
+            .batch_open(vec![(2, 10), (3, 0), (2, 5)])
+            .unwrap();
+        assert_eq!(
+            ledger.bank(0).validate(&1, &op),
+            Err(ValidationError::AccountListedTwice { account: 2 })
+        );
+        let mut bank = ledger.bank(0).clone();
+        assert_eq!(
+            bank.apply_checked(op),
+            Err(ApplyError::AccountAlreadyExists { account: 2 })
+        );
+        assert!(!bank.account_exists(&3));
+
+        let op = ledger.bank(1).batch_open(vec![(2, 10), (3, 0)]).unwrap();
+        assert_eq!(
+            ledger.bank(0).validate(&0, &op),
+            Err(ValidationError::NotInitiatedByAdmin { initiator: 0 })
+        );
+        let mut bank = ledger.bank(0).clone();
+        assert_eq!(
+            bank.apply_with_effects(op.clone()),
+            Ok(AppliedEffect::OpenAccounts {
+                accounts: vec![(2, 10), (3, 0)]
+            })
+        );
+        ledger.exec(1, op);
+        for bank in ledger.0.iter() {
+            assert_eq!(bank.balance(&2), 10);
+            assert_eq!(bank.balance(&3), 0);
+            assert_eq!(bank.accounts_by_open_order(), vec![0, 1, 2, 3]);
+            assert_eq!(bank.current_supply(), 110);
+        }
+
+        // The account limit covers the whole batch
+        ledger.configure(|bank| bank.with_max_accounts(5));
+        let op = ledger.bank(1).batch_open(vec![(4, 0), (5, 0)]).unwrap();
+        assert_eq!(
+            ledger.bank(0).validate(&1, &op),
+            Err(ValidationError::AccountLimitReached { max_accounts: 5 })
+        );
+    }
+
+    #[test]
+    fn test_balance_sums_past_max_amount() {
+        let mut ledger = Ledger::new(&[Money::MAX - 1, 0]);
+        // Account 1 receives more than the maximum amount in total, though
+        // it never holds more than it
+        for _ in 0..3 {
+            ledger.transfer(0, 1, Money::MAX - 1);
+            ledger.transfer(1, 0, Money::MAX - 1);
+        }
+        ledger.transfer(0, 1, Money::MAX - 1);
+
+        let bank = ledger.bank(0);
+        assert_eq!(bank.balance(&1), Money::MAX - 1);
+        assert_eq!(bank.balance(&0), 0);
+        assert_eq!(bank.try_balance(&1), Ok(Money::MAX - 1));
+        assert_eq!(bank.balances()[&1], Money::MAX - 1);
+        assert_eq!(bank.check_invariants(), Ok(()));
+
+        let mut bank = bank.clone();
+        bank.checkpoint_account(&1);
+        assert_eq!(bank.balance(&1), Money::MAX - 1);
+    }
+
+    #[test]
+    fn test_subscribe() {
+        use std::sync::{Arc, Mutex};
+
+        let mut ledger = Ledger::new(&[100, 0]);
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let recorder = seen.clone();
+        ledger.0[1].subscribe(Box::new(move |_op, effect| {
+            recorder.lock().unwrap().push(effect.clone());
+        }));
+
+        let op = ledger.transfer(0, 1, 30);
+        assert_eq!(
+            *seen.lock().unwrap(),
+            vec![AppliedEffect::Transfer {
+                from: 0,
+                to: 1,
+                amount: 30,
+                new_from_balance: 70,
+                new_to_balance: 30,
+            }]
+        );
+
+        // Duplicates, trial runs and clones don't notify observers
+        ledger.0[1].apply(op);
+        let next = ledger.bank(0).transfer(0, 1, 10).unwrap();
+        assert_eq!(
+            ledger
+                .bank(1)
+                .validate_batch(&0, std::slice::from_ref(&next)),
+            Ok(())
+        );
+        ledger.bank(1).clone().apply(next.clone());
+        assert_eq!(seen.lock().unwrap().len(), 1);
+
+        ledger.exec(0, next);
+        assert_eq!(seen.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_transfer_split() {
+        let mut ledger = Ledger::new(&[100, 0, 0, 0]);
+
+        // 50%, 30% and 20% of 7 are rounded down to 3, 2 and 1, and the
+        // leftover goes to the last recipient
+        let op = ledger
+            .bank(0)
+            .transfer_split(0, 7, vec![(1, 50), (2, 30), (3, 20)])
+            .unwrap();
+        assert_eq!(format!("{}", op), "MultiTransfer transfers=3");
+        ledger.exec(0, op);
+        let received: Vec<Money> = (1..4).map(|a| ledger.bank(0).balance(&a)).collect();
+        assert_eq!(received, vec![3, 2, 2]);
+        assert_eq!(received.iter().sum::<Money>(), 7);
+        assert_eq!(ledger.bank(0).balance(&0), 93);
+
+        // The next split carries on from the sender's sequence numbers
+        let op = ledger
+            .bank(0)
+            .transfer_split(0, 93, vec![(1, 1), (2, 1), (3, 1)])
+            .unwrap();
+        ledger.exec(0, op);
+        let received: Vec<Money> = (1..4).map(|a| ledger.bank(0).balance(&a)).collect();
+        assert_eq!(received, vec![34, 33, 33]);
+        assert_eq!(ledger.bank(0).balance(&0), 0);
+        assert_eq!(ledger.bank(0).check_invariants(), Ok(()));
+
+        assert_eq!(ledger.bank(1).transfer_split(1, 35, vec![(0, 1)]), None);
+        assert_eq!(
+            ledger.bank(1).transfer_split(1, 10, vec![(0, 0), (2, 0)]),
+            None
+        );
+    }
+
+    #[test]
+    fn test_multi_transfer_is_atomic() {
+        let ledger = Ledger::new(&[100, 0, 0]);
+        let op = Op::MultiTransfer {
+            transfers: vec![
+                TransferBuilder::new(0, 1, 60).build(),
+                TransferBuilder::new(0, 2, 60).seq(1).build(),
+            ],
+        };
+
+        // The second transfer overdraws the sender once the first is applied
+        let mut bank = ledger.bank(1).clone();
+        assert_eq!(
+            bank.validate(&0, &op),
+            Err(ValidationError::InsufficientFunds {
+                balance: 40,
+                transfer_amount: 60
+            })
+        );
+        assert_eq!(
+            bank.apply_checked(op),
+            Err(ApplyError::BalanceUnderflow { account: 0 })
+        );
+        assert_eq!(&bank, ledger.bank(1));
+
+        let op = Op::MultiTransfer {
+            transfers: vec![
+                TransferBuilder::new(0, 1, 60).build(),
+                TransferBuilder::new(0, 2, 40).seq(1).build(),
+            ],
+        };
+        assert_eq!(bank.validate(&0, &op), Ok(()));
+
+        // Every transfer counts towards the sender's rate limit
+        let limited = bank.clone().with_rate_limit(1, 10);
+        assert_eq!(
+            limited.validate(&0, &op),
+            Err(ValidationError::RateLimited {
+                max_transfers: 1,
+                window: 10
+            })
+        );
+
+        assert_eq!(
+            bank.apply_with_effects(op.clone()),
+            Ok(AppliedEffect::MultiTransfer {
+                from: 0,
+                new_from_balance: 0,
+                credits: vec![(1, 60, 60), (2, 40, 40)],
+            })
+        );
+        // Re-delivering the op is a no-op
+        assert_eq!(
+            bank.apply_with_effects(op),
+            Ok(AppliedEffect::NoBalanceChange)
+        );
+        assert_eq!(bank.transfer_count(), 2);
+    }
+
+    #[test]
+    fn test_transfer_with_deps() {
+        use std::collections::BTreeSet;
+
+        let mut ledger = Ledger::new(&[100, 100, 10]);
+        let incoming: Vec<_> = [(0, 20), (1, 50), (0, 30)]
+            .iter()
+            .map(|(from, amount)| match ledger.transfer(*from, 2, *amount) {
+                Op::Transfer(transfer) => transfer,
+                _ => panic!("expected a Transfer op"),
+            })
+            .collect();
+
+        // The initial balance needs no proof, unspent incoming transfers do
+        let bank = ledger.bank(2);
+        let deps: BTreeSet<_> = vec![incoming[1].clone()].into_iter().collect();
+        assert_eq!(
+            bank.transfer_with_deps(2, 0, 70, deps.clone()),
+            Err(ValidationError::DepsDoNotCoverAmount {
+                covered: 60,
+                transfer_amount: 70,
+            })
+        );
+        assert!(bank.transfer_with_deps(2, 0, 10, BTreeSet::new()).is_ok());
+        let stray = match ledger.bank(0).transfer(0, 1, 5).unwrap() {
+            Op::Transfer(transfer) => transfer,
+            _ => panic!("expected a Transfer op"),
+        };
+        assert_eq!(
+            bank.transfer_with_deps(2, 0, 10, vec![stray].into_iter().collect()),
+            Err(ValidationError::InvalidDependency)
+        );
+
+        let op = bank.transfer_with_deps(2, 0, 60, deps).unwrap();
+        ledger.exec(2, op);
+        assert_eq!(ledger.bank(2).balance(&2), 50);
+
+        // The transfers left out are still needed to prove the rest
+        let bank = ledger.bank(2);
+        assert!(bank.transfer_with_deps(2, 0, 50, BTreeSet::new()).is_err());
+        let deps = vec![incoming[0].clone(), incoming[2].clone()]
+            .into_iter()
+            .collect();
+        assert!(bank.transfer_with_deps(2, 0, 50, deps).is_ok());
+    }
+
+    #[test]
+    fn test_dependency_cycle() {
+        let mut ledger = Ledger::new(&[100, 100]);
+        ledger.transfer(1, 0, 20);
+        let seq = ledger.bank(0).next_seq(&0);
+
+        // A transfer listing a copy of itself among its deps
+        let copy = TransferBuilder::new(0, 1, 10).seq(seq).build();
+        let cyclic = TransferBuilder::new(0, 1, 10)
+            .seq(seq)
+            .deps(vec![copy.clone()])
+            .build();
+        assert_eq!(
+            ledger.bank(0).validate(&0, &Op::Transfer(cyclic)),
+            Err(ValidationError::DependencyCycle)
+        );
+
+        // ... or further down its nested deps
+        let incoming = TransferBuilder::new(1, 0, 5).deps(vec![copy]).build();
+        let cyclic = TransferBuilder::new(0, 1, 10)
+            .seq(seq)
+            .deps(vec![incoming])
+            .build();
+        assert_eq!(
+            ledger.bank(0).validate(&0, &Op::Transfer(cyclic)),
+            Err(ValidationError::DependencyCycle)
+        );
+
+        let op = ledger.bank(0).transfer(0, 1, 10).unwrap();
+        assert_eq!(ledger.bank(0).validate(&0, &op), Ok(()));
+    }
+
+    #[test]
+    fn test_clone_for() {
+        let mut ledger = Ledger::new(&[100, 0, 0]);
+        ledger.transfer(0, 1, 30);
+        ledger.transfer(0, 1, 20);
+        ledger.transfer(1, 2, 10);
+        ledger.transfer(0, 1, 5);
+
+        let replica = ledger.bank(0).clone_for(1);
+        assert_eq!(replica.id(), &1);
+        for actor in 0..3 {
+            assert_eq!(replica.balance(&actor), ledger.bank(1).balance(&actor));
+        }
+
+        // Carries the incoming transfers not yet spent, as the owner's own bank would
+        let op = replica.transfer(1, 2, 45).unwrap();
+        assert_eq!(op, ledger.bank(1).transfer(1, 2, 45).unwrap());
+        ledger.exec(1, op);
+        assert_eq!(ledger.bank(2).balance(&2), 55);
+        assert!(ledger.bank(0).clone_for(0).transfer(0, 2, 45).is_ok());
+    }
+
+    #[test]
+    fn test_check_invariants_resolves_nested_deps() {
+        let mut ledger = Ledger::new(&[100, 0, 0, 0]);
+        ledger.configure(|bank| bank.with_fee_collector(3));
+        ledger.transfer(0, 1, 60);
+        ledger.transfer(1, 2, 50);
+        let op = ledger.bank(2).transfer_with_fee(2, 0, 10, 1).unwrap();
+        ledger.exec(2, op);
+
+        for bank in ledger.0.iter() {
+            assert_eq!(bank.check_invariants(), Ok(()));
+
+            // Compacted deps only keep one level of nesting
+            let mut compacted = bank.clone();
+            compacted.compact();
+            assert_eq!(compacted.check_invariants(), Ok(()));
+        }
+    }
+
+    #[test]
+    fn test_merge_compacted_replica() {
+        let mut ledger = Ledger::new(&[100, 0, 0]);
+        ledger.transfer(0, 1, 60);
+        ledger.transfer(1, 2, 50);
+        ledger.transfer(2, 0, 40);
+
+        let mut compacted = ledger.bank(0).clone();
+        compacted.compact();
+        let uncompacted = ledger.bank(1).clone_for(0);
+
+        let mut merged = compacted.clone();
+        merged.merge(&uncompacted).unwrap();
+        let mut merged_other_way = uncompacted.clone();
+        merged_other_way.merge(&compacted).unwrap();
+
+        // Each transfer is kept once, in its compacted form
+        assert_eq!(merged.snapshot(), compacted.snapshot());
+        assert_eq!(merged_other_way.snapshot(), compacted.snapshot());
+        assert_eq!(merged.transfer_count(), 3);
+        for actor in 0..3 {
+            assert_eq!(merged.balance(&actor), ledger.bank(0).balance(&actor));
+        }
+        assert_eq!(merged.check_invariants(), Ok(()));
+    }
+
+    #[test]
+    fn test_merge_keeps_cancellation() {
+        let mut ledger = Ledger::new(&[100, 0]);
+        let op = ledger.bank(0).initiate_transfer(0, 1, 30).unwrap();
+        let id = match &op {
+            Op::InitiateTransfer(transfer) => transfer.id(),
+            _ => panic!("expected an InitiateTransfer op"),
+        };
+        ledger.exec(0, op);
+        let stale = ledger.bank(1).clone();
+        let op = ledger.bank(0).cancel_transfer(id).unwrap();
+        ledger.exec(0, op);
+
+        // The cancelled transfer stays cancelled, its funds released
+        let mut merged = ledger.bank(1).clone();
+        merged.merge(&stale).unwrap();
+        let mut merged_other_way = stale;
+        merged_other_way.merge(ledger.bank(1)).unwrap();
+        for bank in &[merged, merged_other_way] {
+            assert_eq!(bank.pending_transfer(&id), None);
+            assert_eq!(bank.balance(&0), 100);
+            assert_eq!(bank.snapshot(), ledger.bank(1).snapshot());
+        }
+    }
+
+    #[test]
+    fn test_merge_keeps_unfreeze() {
+        let mut ledger = Ledger::new(&[100, 0, 0]);
+        ledger.configure(|bank| bank.with_admin(2));
+        let op = ledger.bank(2).freeze(1).unwrap();
+        ledger.exec(2, op);
+        let stale = ledger.bank(0).clone();
+        let op = ledger.bank(2).unfreeze(1).unwrap();
+        ledger.exec(2, op);
+
+        // The latest change wins, whichever replica saw it
+        let mut merged = ledger.bank(0).clone();
+        merged.merge(&stale).unwrap();
+        let mut merged_other_way = stale;
+        merged_other_way.merge(ledger.bank(0)).unwrap();
+        for bank in &[merged, merged_other_way] {
+            assert!(!bank.is_frozen(&1));
+            assert_eq!(bank.snapshot(), ledger.bank(0).snapshot());
+        }
+    }
+
+    #[test]
+    fn test_merge_keeps_latest_approval() {
+        let mut ledger = Ledger::new(&[100, 0, 0]);
+        let op = ledger.bank(0).approve(0, 2, 50);
+        ledger.exec(0, op);
+        let unspent = ledger.bank(1).clone();
+        let op = ledger.bank(2).transfer_from(0, 1, 20).unwrap();
+        ledger.exec(2, op);
+        let spent = ledger.bank(1).clone();
+        let op = ledger.bank(0).approve(0, 2, 40);
+        ledger.exec(0, op);
+        let approved = ledger.bank(1);
+
+        // Replicas that saw the same approval keep the smaller allowance,
+        // and a newer approval replaces it
+        for (ours, theirs, allowance) in [
+            (&unspent, &spent, 30),
+            (&spent, &unspent, 30),
+            (&unspent, approved, 40),
+            (approved, &unspent, 40),
+        ]
+        .iter()
+        {
+            let mut merged = (*ours).clone();
+            merged.merge(theirs).unwrap();
+            assert_eq!(merged.allowance(&0, &2), *allowance);
+        }
+    }
+
+    #[test]
+    fn test_merge_conflicting_replicas() {
+        let ledger = Ledger::new(&[100, 0]);
+        let mut other = Bank::new(0);
+        let op = other.open_account(1, 10);
+        other.apply(op);
+
+        let mut merged = ledger.bank(0).clone();
+        assert_eq!(
+            merged.merge(&other),
+            Err(MergeError::InitialBalanceMismatch { account: 1 })
+        );
+        assert_eq!(&merged, ledger.bank(0));
+    }
+
+    quickcheck! {
+        fn prop_merge_is_order_independent(transfers: Vec<(Actor, Actor, Money)>, cuts: (usize, usize, usize)) -> TestResult {
+            if transfers.len() > 20 {
+                return TestResult::discard();
+            }
+
+            // Replicas that have seen different prefixes of the same op log
+            let mut ledger = Ledger::new(&[100, 100, 100]);
+            let mut states = vec![ledger.bank(0).clone()];
+            for (from, to, amount) in transfers {
+                let (from, to) = (from % 3, to % 3);
+                if let Ok(op) = ledger.bank(from).transfer(from, to, amount % 150) {
+                    ledger.exec(from, op);
+                    states.push(ledger.bank(0).clone());
+                }
+            }
+            let replica = |cut: usize| states[cut % states.len()].clone();
+            let (a, b, c) = (replica(cuts.0), replica(cuts.1), replica(cuts.2));
+
+            let merged = |replicas: [&Bank<Actor>; 3]| {
+                let mut bank = replicas[0].clone();
+                bank.merge(replicas[1]).unwrap();
+                bank.merge(replicas[2]).unwrap();
+                bank
+            };
+            let abc = merged([&a, &b, &c]);
+            for order in [[&b, &a, &c], [&c, &b, &a], [&a, &c, &b]].iter() {
+                assert_eq!(merged(*order).snapshot(), abc.snapshot());
+            }
+
+            assert_eq!(abc.check_invariants(), Ok(()));
+
+            // Merging is idempotent, and converges on the longest prefix
+            let mut again = abc.clone();
+            again.merge(&abc).unwrap();
+            assert_eq!(again, abc);
+            let longest = [&a, &b, &c].iter().map(|r| r.transfer_count()).max().unwrap();
+            assert_eq!(abc.transfer_count(), longest);
+
+            TestResult::passed()
+        }
+    }
+
+    #[cfg(feature = "testing")]
+    quickcheck! {
+        fn prop_random_ops_keep_invariants(ops: Vec<(Actor, Op<Actor>)>) -> TestResult {
+            let mut bank = Bank::new(0).with_max_initial_balance(Money::MAX);
+            for (source, op) in ops {
+                if bank.validate(&source, &op).is_ok() {
+                    bank.apply(op);
+                }
+            }
+            assert_eq!(bank.check_invariants(), Ok(()));
+
+            TestResult::passed()
+        }
+
+        fn prop_ordering_seed_leaves_results_unchanged(transfers: Vec<(Actor, Actor, Money)>, seed: u64) -> TestResult {
+            if transfers.len() > 20 {
+                return TestResult::discard();
+            }
+
+            let mut ledger = Ledger::new(&[100, 100, 100]);
+            let mut seeded = ledger.bank(0).clone().with_ordering_seed(seed);
+            for (from, to, amount) in transfers {
+                let (from, to) = (from % 3, to % 3);
+                if let Ok(op) = ledger.bank(from).transfer(from, to, amount % 150) {
+                    assert_eq!(seeded.validate(&from, &op), Ok(()));
+                    seeded.apply(op.clone());
+                    ledger.exec(from, op);
+                }
+            }
+
+            for actor in 0..3 {
+                assert_eq!(seeded.balance(&actor), ledger.bank(0).balance(&actor));
+            }
+            assert_eq!(seeded.snapshot(), ledger.bank(0).snapshot());
+
+            TestResult::passed()
+        }
+    }
+}
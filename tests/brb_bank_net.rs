@@ -9,6 +9,15 @@ impl BankNet {
         Self(Net::new())
     }
 
+    /// Initializes a proc whose bank allows accounts to open with any balance
+    pub fn initialize_proc(&mut self) -> Actor {
+        let actor = self.0.initialize_proc();
+        self.0.on_proc_mut(&actor, |p| {
+            p.dt = p.dt.clone().with_max_initial_balance(Money::MAX);
+        });
+        actor
+    }
+
     pub fn find_actor_with_balance(&self, balance: Money) -> Option<Actor> {
         self.0
             .actors()
@@ -41,9 +50,10 @@ impl BankNet {
         amount: Money,
     ) -> Option<Vec<Packet>> {
         self.0.on_proc(&initiating_proc, |p| {
-            p.dt.transfer(from, to, amount)
-                .map(|op| p.exec_op(op).unwrap())
-                .unwrap_or_default()
+            match p.dt.transfer(from, to, amount) {
+                Ok(op) => p.exec_op(op).unwrap(),
+                Err(_) => Default::default(),
+            }
         })
     }
 }
@@ -56,7 +66,7 @@ mod tests {
     fn bootstrap_network(net: &mut BankNet, balances: Vec<Money>) {
         let mut balance_iter = balances.into_iter();
         let genesis_balance = balance_iter.next().unwrap();
-        let genesis_actor = net.0.initialize_proc();
+        let genesis_actor = net.initialize_proc();
         net.0
             .on_proc_mut(&genesis_actor, |p| p.force_join(genesis_actor))
             .unwrap();
@@ -68,7 +78,7 @@ mod tests {
         assert!(net.0.members_are_in_agreement());
 
         for balance in balance_iter {
-            let actor = net.0.initialize_proc();
+            let actor = net.initialize_proc();
             net.0.on_proc_mut(&actor, |p| p.force_join(genesis_actor));
             let packets = net
                 .0
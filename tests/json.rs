@@ -0,0 +1,103 @@
+#![cfg(feature = "json")]
+
+use brb_dt_at2::{json::OpParseError, Bank, Op, TransferBuilder};
+
+type Actor = u8;
+
+#[test]
+fn test_json_round_trip() {
+    let bank: Bank<Actor> = Bank::new(0);
+    let ops = vec![
+        bank.open_account(1, 0),
+        bank.set_account_metadata(1, "name".to_string(), "Alice".to_string()),
+        bank.approve(0, 1, 20),
+        bank.register_alias(1, "alice".to_string()),
+    ];
+
+    for op in ops {
+        assert_eq!(Op::from_json(&op.to_json()), Ok(op));
+    }
+}
+
+/// The JSON schema of ops is relied upon by external consumers, so any
+/// change to the encoding of these ops must be deliberate.
+#[test]
+fn test_json_golden_fixtures() {
+    let mut bank: Bank<Actor> = Bank::new(0);
+    let open = bank.open_account(0, 100);
+    bank.apply(open.clone());
+    bank.apply(bank.open_account(1, 0));
+    let ops = vec![
+        open,
+        bank.transfer(0, 1, 10).unwrap(),
+        Op::TransferFrom {
+            spender: 2,
+            transfer: TransferBuilder::new(0, 1, 5).fee(1).not_before(3).build(),
+        },
+        Op::BatchOpen {
+            accounts: vec![(2, 10), (3, 0)],
+        },
+        Op::SetFrozen {
+            owner: 1,
+            frozen: true,
+        },
+        Op::AdjustBalance {
+            owner: 1,
+            new_balance: 7,
+        },
+        Op::RecoverAccount {
+            owner: 1,
+            new_owner: 4,
+        },
+        Op::MultiTransfer {
+            transfers: vec![
+                TransferBuilder::new(0, 1, 3).build(),
+                TransferBuilder::new(0, 2, 2).seq(1).build(),
+            ],
+        },
+    ];
+
+    let fixtures: Vec<_> = include_str!("fixtures/ops.jsonl").lines().collect();
+    assert_eq!(fixtures.len(), ops.len());
+    for (op, json) in ops.into_iter().zip(fixtures) {
+        assert_eq!(op.to_json(), json);
+        assert_eq!(Op::from_json(json), Ok(op));
+    }
+}
+
+#[test]
+fn test_bincode_keeps_default_representation() {
+    let bank: Bank<Actor> = Bank::new(0);
+    let op = bank.open_account(1, 5);
+
+    // Externally tagged: the variant index, then the fields in order
+    let config = bincode::config::legacy();
+    let bytes = bincode::serde::encode_to_vec(&op, config).unwrap();
+    assert_eq!(&bytes[..5], &[1, 0, 0, 0, 1]);
+    let (decoded, _) = bincode::serde::decode_from_slice::<Op<Actor>, _>(&bytes, config).unwrap();
+    assert_eq!(decoded, op);
+}
+
+#[test]
+fn test_json_rejects_malformed_ops() {
+    assert!(matches!(
+        Op::<Actor>::from_json("{\"type\":\"openAccount\","),
+        Err(OpParseError::Syntax(_))
+    ));
+    assert!(matches!(
+        Op::<Actor>::from_json("{\"type\":\"mint\",\"owner\":1}"),
+        Err(OpParseError::UnknownVariant(_))
+    ));
+    assert!(matches!(
+        Op::<Actor>::from_json("{\"type\":\"setFrozen\",\"owner\":1,\"frozen\":\"yes\"}"),
+        Err(OpParseError::InvalidField(_))
+    ));
+    assert!(matches!(
+        Op::<Actor>::from_json("{\"type\":\"setFrozen\",\"owner\":1}"),
+        Err(OpParseError::InvalidField(_))
+    ));
+    assert!(matches!(
+        Op::<Actor>::from_json("{\"owner\":1,\"frozen\":true}"),
+        Err(OpParseError::InvalidField(_))
+    ));
+}
@@ -0,0 +1,93 @@
+#![cfg(feature = "proto")]
+
+use brb_dt_at2::{proto::ProtoError, Bank, Money, Op, Transfer};
+
+type Actor = Vec<u8>;
+
+#[test]
+fn test_proto_round_trip() {
+    let (alice, bob) = (b"alice".to_vec(), b"bob".to_vec());
+    let mut bank: Bank<Actor> = Bank::new(alice.clone()).with_max_initial_balance(Money::MAX);
+
+    let mut ops = vec![
+        bank.open_account(alice.clone(), 100),
+        bank.open_account(bob.clone(), 0),
+    ];
+    for op in ops.clone() {
+        bank.apply(op);
+    }
+
+    let op = bank.transfer(alice.clone(), bob.clone(), 60).unwrap();
+    bank.apply(op.clone());
+    ops.push(op);
+    let split = vec![(bob.clone(), 3), (bob.clone(), 1)];
+    ops.push(bank.transfer_split(alice.clone(), 20, split).unwrap());
+    ops.push(bank.approve(alice.clone(), bob.clone(), 20));
+    ops.push(bank.set_account_metadata(alice.clone(), "name".to_string(), "Alice".to_string()));
+    ops.push(bank.register_alias(alice.clone(), "alice".to_string()));
+    ops.push(bank.open_account_with_recovery(b"carol".to_vec(), 0, alice.clone()));
+    ops.push(bank.recover_account(alice.clone(), b"alice2".to_vec()));
+    ops.push(Op::SetFrozen {
+        owner: bob.clone(),
+        frozen: true,
+    });
+    ops.push(Op::AdjustBalance {
+        owner: bob.clone(),
+        new_balance: 5,
+    });
+    ops.push(bank.open_liability_account(b"dave".to_vec()));
+    ops.push(Op::BatchOpen {
+        accounts: vec![(b"erin".to_vec(), 10), (b"frank".to_vec(), 0)],
+    });
+    ops.push(Op::CloseAndSweep {
+        owner: alice.clone(),
+        destination: bob.clone(),
+    });
+
+    let op = bank.initiate_transfer(alice, bob, 10).unwrap();
+    let id = match &op {
+        Op::InitiateTransfer(transfer) => transfer.id(),
+        _ => panic!("Expected an InitiateTransfer op"),
+    };
+    bank.apply(op.clone());
+    ops.push(op);
+    ops.push(bank.finalize_transfer(id).unwrap());
+    ops.push(Op::CancelTransfer { id });
+
+    for op in ops {
+        let bytes = op.to_proto_bytes();
+        assert_eq!(Op::from_proto_bytes(&bytes), Ok(op.clone()));
+
+        if let Op::Transfer(transfer) = op {
+            let bytes = transfer.to_proto_bytes();
+            assert_eq!(Transfer::from_proto_bytes(&bytes), Ok(transfer));
+        }
+    }
+}
+
+#[test]
+fn test_proto_rejects_malformed_ops() {
+    assert_eq!(
+        Op::<Actor>::from_proto_bytes(&[]),
+        Err(ProtoError::MissingField("Op.kind"))
+    );
+    assert!(matches!(
+        Op::<Actor>::from_proto_bytes(&[0xff]),
+        Err(ProtoError::Decode(_))
+    ));
+
+    // A finalize op with a truncated transfer id
+    let op: Op<Actor> = Op::FinalizeTransfer {
+        id: brb_dt_at2::TransferId([7; 32]),
+    };
+    let mut msg = op.to_proto();
+    if let Some(brb_dt_at2::proto::messages::op::Kind::FinalizeTransfer(pending)) =
+        msg.kind.as_mut()
+    {
+        pending.id.truncate(16);
+    }
+    assert_eq!(
+        Op::<Actor>::from_proto(msg),
+        Err(ProtoError::InvalidTransferId { len: 16 })
+    );
+}